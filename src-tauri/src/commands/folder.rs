@@ -1,22 +1,73 @@
 //! Kommandoer for mappehåndtering og duplikatdeteksjon
 
-use crate::services::{hashing, scanner, thumbnail, sorter};
+use crate::services::{clustering, hashing, keeper, media, metadata, scanner, thumbnail, sorter, video};
+use crate::services::media::MediaKind;
+use crate::services::metadata::MediaMetadata;
+use crate::services::clustering::ClusterMode;
+use crate::services::keeper::{KeepDecision, KeepRule};
 use crate::services::sorter::{OperationResult, SortConfig};
-use crate::services::hashing::ComparableHash;
+use crate::services::hashing::{DihedralHash, HashType, ImageHash, Transform, VerificationLevel};
+use bk_tree::Metric;
 use rayon::prelude::*;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex, RwLock};
 use crate::services::cache::HashCache;
+use crate::services::jobs::{CancelToken, JobRegistry, CANCELLED_MESSAGE};
+use crate::services::progress::{ProgressEvent, ProgressPhase, ProgressReporter};
+use crate::services::scanner::ScanOptions;
+use crate::services::video::VideoFingerprint;
 
-#[derive(Serialize, Clone)]
+#[derive(Serialize, Clone, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct ImageInfo {
     pub path: String,
     pub filename: String,
     pub extension: String,
     pub size_bytes: u64,
+    /// Andre stier til samme fil (hardlenker) - sletting av én sti frigjør ikke plass
+    pub aliases: Vec<String>,
+    /// Formatet funnet i filinnholdet, hvis skanningen leste filsignaturen
+    pub detected_format: Option<String>,
+    /// Filendelsen stemmer ikke med innholdet - kan rettes med `fix_extensions`
+    pub extension_mismatch: bool,
+    pub kind: MediaKind,
+    /// Sist endret / opprettet, sekunder siden epoch
+    pub modified: Option<i64>,
+    pub created: Option<i64>,
+    /// Dimensjoner, hvis skanningen leste bildeheaderen
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub has_sidecar: bool,
+}
+
+impl From<scanner::ImageInfo> for ImageInfo {
+    fn from(img: scanner::ImageInfo) -> Self {
+        Self {
+            path: img.path,
+            filename: img.filename,
+            extension: img.extension,
+            size_bytes: img.size_bytes,
+            aliases: img.aliases,
+            detected_format: img.detected_format,
+            extension_mismatch: img.extension_mismatch,
+            kind: img.kind,
+            modified: img.modified,
+            created: img.created,
+            width: img.dimensions.map(|(w, _)| w),
+            height: img.dimensions.map(|(_, h)| h),
+            has_sidecar: img.has_sidecar,
+        }
+    }
+}
+
+/// Payload for "scan-batch"-eventet ved strømmende skanning
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ScanBatch {
+    pub job_id: String,
+    pub images: Vec<ImageInfo>,
 }
 
 #[derive(Serialize)]
@@ -25,6 +76,10 @@ pub struct ScanResult {
     pub image_count: usize,
     pub total_size_bytes: u64,
     pub images: Vec<ImageInfo>,
+    /// Stier som peker til samme fil, første sti er den som er med i `images`
+    pub hardlink_groups: Vec<Vec<String>>,
+    /// Symlenker som pekte tilbake til en overordnet mappe og ble hoppet over
+    pub symlink_loops: Vec<String>,
 }
 
 #[derive(Serialize, Clone)]
@@ -34,48 +89,232 @@ pub struct ImageWithHash {
     pub hash: String,
 }
 
+/// Hvordan medlemmene i en duplikatgruppe henger sammen
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum MatchKind {
+    /// Alle medlemmer er byte-identiske kopier - trygt å slette automatisk
+    Exact,
+    /// Kun visuelle treff (perceptuell hash innenfor terskel) - krever manuell gjennomgang
+    Visual,
+    /// Visuelle treff der noen av bildene også har eksakte kopier
+    Mixed,
+    /// Samme fil nådd via flere stier (hardlenker) - ikke duplikater, sletting frigjør ikke plass
+    Hardlink,
+}
+
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct DuplicateGroup {
+    /// Alle medlemmer, med referansebildet først
     pub images: Vec<ImageInfo>,
+    /// Hamming-distanse fra hvert medlem til referansebildet (samme rekkefølge som `images`).
+    /// For videoer er det snittet over bildene i fingeravtrykket.
+    pub distances: Vec<u32>,
+    /// Rotasjon/speiling som må brukes på hvert medlem for å matche referansebildet.
+    /// Alltid `identity` uten `transform_invariant`.
+    pub transforms: Vec<Transform>,
+    pub kind: MatchKind,
+    /// Stier til byte-identiske kopier innad i gruppen, én liste per sett med kopier
+    pub exact_subgroups: Vec<Vec<String>>,
+    /// Verifiseringsnivå for eksakte kopier i gruppen (None hvis gruppen kun er visuelle treff)
+    pub verification: Option<VerificationLevel>,
+}
+
+impl DuplicateGroup {
+    fn new(
+        images: Vec<ImageInfo>,
+        distances: Vec<u32>,
+        transforms: Vec<Transform>,
+        exact_subgroups: Vec<Vec<String>>,
+        verification: VerificationLevel,
+    ) -> Self {
+        let exact_members: usize = exact_subgroups.iter().map(|g| g.len()).sum();
+        let kind = if exact_subgroups.is_empty() {
+            MatchKind::Visual
+        } else if exact_subgroups.len() == 1 && exact_members == images.len() {
+            MatchKind::Exact
+        } else {
+            MatchKind::Mixed
+        };
+
+        Self {
+            images,
+            distances,
+            transforms,
+            kind,
+            verification: (!exact_subgroups.is_empty()).then_some(verification),
+            exact_subgroups,
+        }
+    }
+
+    /// Gruppe med stier som deler samme inode
+    fn hardlinks(paths: &[String]) -> Self {
+        let images: Vec<ImageInfo> = paths.iter().map(|p| ImageInfo::from_path(p)).collect();
+        Self {
+            distances: vec![0; images.len()],
+            transforms: vec![Transform::Identity; images.len()],
+            images,
+            kind: MatchKind::Hardlink,
+            exact_subgroups: Vec::new(),
+            verification: None,
+        }
+    }
+}
+
+impl ImageInfo {
+    fn from_path(path_str: &str) -> Self {
+        let size_bytes = std::fs::metadata(path_str).map(|m| m.len()).unwrap_or(0);
+        Self::describe(path_str, size_bytes)
+    }
+
+    /// Navn, endelse og medietype ut fra stien, for filer som ikke kommer fra skanneren
+    fn describe(path_str: &str, size_bytes: u64) -> Self {
+        let path = Path::new(path_str);
+        let extension = path.extension().unwrap_or_default().to_string_lossy().to_string().to_lowercase();
+        Self {
+            path: path_str.to_string(),
+            filename: path.file_name().unwrap_or_default().to_string_lossy().to_string(),
+            kind: media::classify(path, &extension, false).unwrap_or_default(),
+            extension,
+            size_bytes,
+            ..Default::default()
+        }
+    }
 }
 
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct DuplicateResult {
     pub groups: Vec<DuplicateGroup>,
+    /// Hardlenkede filer blant inndataene, rapportert separat fra duplikatene
+    pub hardlink_groups: Vec<DuplicateGroup>,
     pub total_duplicates: usize,
     pub processed: usize,
     pub errors: usize,
 }
 
+/// Innstillinger for duplikatsøk
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DuplicateConfig {
+    /// Algoritme for visuell sammenligning (dHash, pHash eller aHash)
+    pub hash_type: HashType,
+    /// Hash-størrelse: 8 (8x8), 16 (16x16) eller 32 (32x32)
+    pub hash_size: u32,
+    /// Hvor grundig eksakte kandidater bekreftes før de merkes som eksakte
+    pub verification: VerificationLevel,
+    /// Hvordan visuelle treff slås sammen til grupper
+    pub cluster_mode: ClusterMode,
+    /// Finn også kopier som er rotert eller speilet. Hasher bildet i alle 8 orienteringer,
+    /// så hashingen tar lengre tid.
+    pub transform_invariant: bool,
+}
+
+impl Default for DuplicateConfig {
+    fn default() -> Self {
+        Self {
+            hash_type: HashType::Difference,
+            hash_size: hashing::DEFAULT_HASH_SIZE,
+            verification: VerificationLevel::Partial,
+            cluster_mode: ClusterMode::Leader,
+            transform_invariant: false,
+        }
+    }
+}
+
 /// Henter cache-mappe for thumbnails
 /// Bruker systemets midlertidige mappe for OS-agnostisk støtte (Windows/Linux/macOS)
 fn get_thumbnail_cache_dir() -> PathBuf {
     std::env::temp_dir().join("imagesorter-thumbnails")
 }
 
+/// Lager en reporter som sender fremdrift som "progress"-event til frontend
+fn progress_reporter(app: &tauri::AppHandle, job_id: &str) -> ProgressReporter {
+    use tauri::Emitter;
+    let app = app.clone();
+    ProgressReporter::new(job_id, move |event: &ProgressEvent| {
+        let _ = app.emit("progress", event);
+    })
+}
+
+/// Setter `aliases` på bildene som står først i en hardlenkegruppe (den stien som ble beholdt)
+fn apply_hardlink_aliases<'a>(images: impl IntoIterator<Item = &'a mut ImageInfo>, hardlink_groups: &[Vec<String>]) {
+    let aliases: HashMap<&str, &[String]> = hardlink_groups
+        .iter()
+        .map(|group| (group[0].as_str(), &group[1..]))
+        .collect();
+    for image in images {
+        if let Some(paths) = aliases.get(image.path.as_str()) {
+            image.aliases = paths.to_vec();
+        }
+    }
+}
+
 /// Skanner en mappe og returnerer informasjon om bildene som ble funnet
+/// Kan avbrytes med `cancel_job(job_id)`.
+///
+/// Med `stream = true` sendes funne filer fortløpende som "scan-batch"-events, og
+/// returverdien er bare en oppsummering (tom `images`), så galleriet kan fylles
+/// mens skanningen pågår. Hardlenker er først kjent til slutt, så de sendte bildene
+/// har tomme `aliases` - frontend setter dem fra `hardlink_groups` i oppsummeringen.
 #[tauri::command]
-pub async fn scan_folder(path: String) -> Result<ScanResult, String> {
-    let images = scanner::scan_directory(&path).map_err(|e| e.to_string())?;
+pub async fn scan_folder(
+    app: tauri::AppHandle,
+    jobs: tauri::State<'_, JobRegistry>,
+    path: String,
+    job_id: Option<String>,
+    stream: Option<bool>,
+    options: Option<ScanOptions>,
+) -> Result<ScanResult, String> {
+    use tauri::Emitter;
+    let options = options.unwrap_or_default();
+    let job = jobs.start(job_id)?;
+    let progress = progress_reporter(&app, &job.id);
+
+    if stream.unwrap_or(false) {
+        let totals = scanner::scan_directory_streaming(
+            &path,
+            &options,
+            &job.token,
+            &progress,
+            scanner::DEFAULT_BATCH_SIZE,
+            |batch| {
+                let images = batch.into_iter().map(ImageInfo::from).collect();
+                let _ = app.emit("scan-batch", ScanBatch { job_id: job.id.clone(), images });
+            },
+        )
+        .map_err(|e| e.to_string())?;
 
-    let total_size: u64 = images.iter().map(|img| img.size_bytes).sum();
-    
-    let image_infos: Vec<ImageInfo> = images
-        .into_iter()
-        .map(|img| ImageInfo {
-            path: img.path,
-            filename: img.filename,
-            extension: img.extension,
-            size_bytes: img.size_bytes,
-        })
-        .collect();
+        return Ok(ScanResult {
+            image_count: totals.image_count,
+            total_size_bytes: totals.total_size_bytes,
+            images: Vec::new(),
+            hardlink_groups: totals.hardlink_groups,
+            symlink_loops: totals.symlink_loops,
+        });
+    }
+
+    let mut images = Vec::new();
+    let totals = scanner::scan_directory_streaming(
+        &path,
+        &options,
+        &job.token,
+        &progress,
+        scanner::DEFAULT_BATCH_SIZE,
+        |batch| images.extend(batch.into_iter().map(ImageInfo::from)),
+    )
+    .map_err(|e| e.to_string())?;
+
+    // Aliaser er først kjent etter hele skanningen
+    apply_hardlink_aliases(&mut images, &totals.hardlink_groups);
 
     Ok(ScanResult {
-        image_count: image_infos.len(),
-        total_size_bytes: total_size,
-        images: image_infos,
+        image_count: totals.image_count,
+        total_size_bytes: totals.total_size_bytes,
+        images,
+        hardlink_groups: totals.hardlink_groups,
+        symlink_loops: totals.symlink_loops,
     })
 }
 
@@ -98,19 +337,56 @@ pub async fn open_image(path: String) -> Result<(), String> {
     open::that(&path).map_err(|e| e.to_string())
 }
 
+/// Henter opptaksdato og, for videoer, varighet, oppløsning, kodek, posisjon og kamera
+#[tauri::command]
+pub async fn get_media_metadata(path: String) -> Result<MediaMetadata, String> {
+    let media_path = Path::new(&path);
+    if !media_path.exists() {
+        return Err(format!("Fil finnes ikke: {}", path));
+    }
+    Ok(metadata::read_media_metadata(media_path))
+}
+
 /// Finner duplikater blant gitte bildestier ved hjelp av perceptuell hashing
 /// Optimalisert for store bildesamlinger med parallell prosessering
+///
+/// `threshold` er maks Hamming-distanse og må skaleres med hash-størrelsen
+/// (16x16 gir 4 ganger så mange bit som 8x8).
 #[tauri::command]
-pub async fn find_duplicates(app: tauri::AppHandle, paths: Vec<String>, threshold: u32) -> Result<DuplicateResult, String> {
-    use tauri::Emitter;
+pub async fn find_duplicates(
+    app: tauri::AppHandle,
+    jobs: tauri::State<'_, JobRegistry>,
+    paths: Vec<String>,
+    threshold: u32,
+    options: Option<DuplicateConfig>,
+    job_id: Option<String>,
+) -> Result<DuplicateResult, String> {
+    let config = options.unwrap_or_default();
+    let job = jobs.start(job_id)?;
+    let cancel = &job.token;
+    let progress = progress_reporter(&app, &job.id);
+
+    if config.hash_type == HashType::Exact {
+        return Err("Eksakt hash kan ikke brukes for visuell sammenligning".to_string());
+    }
+    if !hashing::SUPPORTED_HASH_SIZES.contains(&config.hash_size) {
+        return Err(format!("Ugyldig hash-størrelse: {} (støttet: 8, 16, 32)", config.hash_size));
+    }
+    let mut algorithm = hashing::algorithm_id(config.hash_type, config.hash_size);
+    let video_algorithm = format!("video{}-{}", video::FINGERPRINT_FRAMES, algorithm);
+    if config.transform_invariant {
+        algorithm.push_str("-dihedral");
+    }
+
     let error_count = Arc::new(Mutex::new(0usize));
+
+    // Hardlenker er samme fil, ikke kopier. Kun første sti går videre til duplikatsøket.
+    let paths_len = paths.len();
+    let (paths, hardlink_paths) = scanner::collapse_hardlinks(paths);
     
     // --------------- STAGE 1: EXACT DUPLICATES (Rask filtrering) ---------------
     // Grupperer filer basert på størrelse først, så partial hash for kandidater.
     
-    let app_handle = app.clone();
-    let paths_len = paths.len();
-    
     // 1.1 Samle filinfo (størrelse) raskt
     let mut file_sizes: HashMap<u64, Vec<String>> = HashMap::new();
     for path in &paths {
@@ -120,49 +396,61 @@ pub async fn find_duplicates(app: tauri::AppHandle, paths: Vec<String>, threshol
     }
     
     // 1.2 Identifiser kandidater for eksakt match (samme størrelse)
-    let potential_exact_dupes: Vec<String> = file_sizes
+    let potential_exact_dupes: Vec<(String, u64)> = file_sizes
         .into_iter()
         .filter(|(_, files)| files.len() > 1)
-        .flat_map(|(_, files)| files)
+        .flat_map(|(size, files)| files.into_iter().map(move |path| (path, size)))
         .collect();
 
     // 1.3 Beregn partial hash for kandidater parallelt
     let exact_dupe_cache = Arc::new(Mutex::new(HashMap::new()));
     let exact_pool = rayon::ThreadPoolBuilder::new().num_threads(16).build().unwrap();
     
-    let potential_ids: Vec<String> = potential_exact_dupes.clone();
+    progress.start_phase(ProgressPhase::ExactStage, Some(potential_exact_dupes.len() as u64));
     
     exact_pool.install(|| {
-        potential_ids.par_iter().for_each(|path_str| {
+        potential_exact_dupes.par_iter().for_each(|(path_str, size)| {
+            if cancel.is_cancelled() {
+                return;
+            }
             let path = Path::new(path_str);
-            if let Ok(p_hash) = hashing::compute_partial_hash(path) {
-                 exact_dupe_cache.lock().unwrap().insert(path_str.clone(), p_hash);
+            match hashing::compute_partial_hash(path) {
+                Ok(p_hash) => {
+                    exact_dupe_cache.lock().unwrap().insert(path_str.clone(), p_hash);
+                    progress.advance(hashing::partial_hash_bytes(*size), Some(path_str));
+                }
+                Err(_) => progress.error(Some(path_str)),
             }
         });
     });
+    progress.finish_phase();
     
     // 1.4 Grupper eksakte duplikater
     let mut exact_groups: HashMap<String, Vec<ImageInfo>> = HashMap::new();
     let exact_cache_lock = exact_dupe_cache.lock().unwrap();
     
-    for path_str in &potential_exact_dupes {
+    for (path_str, _) in &potential_exact_dupes {
         if let Some(hash) = exact_cache_lock.get(path_str) {
-             let path = Path::new(path_str);
-             let size = std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
-             let filename = path.file_name().unwrap_or_default().to_string_lossy().to_string();
-             
-             let extension = path.extension().unwrap_or_default().to_string_lossy().to_string().to_lowercase();
+             let info = ImageInfo::from_path(path_str);
+             let output_key = format!("{}_{}", info.size_bytes, hash); // Unik nøkkel for eksakt gruppe
              
-             let output_key = format!("{}_{}", size, hash); // Unik nøkkel for eksakt gruppe
-             
-             exact_groups.entry(output_key).or_default().push(ImageInfo {
-                 path: path_str.clone(),
-                 filename,
-                 extension,
-                 size_bytes: size
-             });
+             exact_groups.entry(output_key).or_default().push(info);
         }
     }
+
+    // 1.5 Bekreft kandidatgruppene med full hash eller byte-sammenligning (valgfritt)
+    // Partial hash dekker bare start og slutt av filen, så RAW-filer/videoer som kun
+    // er forskjellige i midten ville ellers blitt merket som eksakte kopier.
+    if config.verification != VerificationLevel::Partial {
+        let candidates = exact_groups.values().filter(|g| g.len() > 1).map(|g| g.len() as u64).sum();
+        progress.start_phase(ProgressPhase::Verify, Some(candidates));
+        exact_groups = exact_pool.install(|| verify_exact_groups(exact_groups, config.verification, cancel, &progress));
+        progress.finish_phase();
+    }
+
+    if cancel.is_cancelled() {
+        return Err(CANCELLED_MESSAGE.to_string());
+    }
     
     // --------------- STAGE 2: VISUAL DUPLICATES (Perceptuell Hash) ---------------
     // For alle bilder som IKKE er en del av en eksakt gruppe (eller vi velger 1 representant fra hver eksakt gruppe)
@@ -175,14 +463,17 @@ pub async fn find_duplicates(app: tauri::AppHandle, paths: Vec<String>, threshol
     let mut _handled_paths: std::collections::HashSet<String> = std::collections::HashSet::new();
 
     // Legg til unike filer (de som ikke var i potential_exact_dupes)
-    let potential_set: std::collections::HashSet<_> = potential_exact_dupes.iter().collect();
+    let potential_set: std::collections::HashSet<_> = potential_exact_dupes.iter().map(|(path, _)| path).collect();
     for path in &paths {
         if !potential_set.contains(path) {
             files_to_visual_scan.push(path.clone());
         }
     }
     
-    // For eksakte grupper, legg til den første som representant
+    // For eksakte grupper, legg til den første (sortert på sti) som representant
+    for group in exact_groups.values_mut() {
+        group.sort_by(|a, b| a.path.cmp(&b.path));
+    }
     for (_, group) in &exact_groups {
         if let Some(first) = group.first() {
             files_to_visual_scan.push(first.path.clone());
@@ -200,180 +491,371 @@ pub async fn find_duplicates(app: tauri::AppHandle, paths: Vec<String>, threshol
         .build()
         .map_err(|e| format!("Kunne ikke starte trådpool: {}", e))?;
 
-    let hashed_images: Vec<ImageWithHash> = visual_pool.install(|| {
+    progress.start_phase(ProgressPhase::VisualStage, Some(files_to_visual_scan.len() as u64));
+    let mut hashed_images: Vec<ImageWithHash> = visual_pool.install(|| {
         files_to_visual_scan
         .par_iter()
         .filter_map(|path_str| {
+            if cancel.is_cancelled() {
+                return None;
+            }
             let path = Path::new(path_str);
             let metadata = match std::fs::metadata(path) {
                 Ok(m) => m,
                 Err(_) => {
                     *error_count.lock().unwrap() += 1;
+                    progress.error(Some(path_str));
                     return None;
                 }
             };
             
             let mtime = metadata.modified().unwrap_or(std::time::UNIX_EPOCH);
             let size_bytes = metadata.len();
-            let filename = path.file_name().unwrap_or_default().to_string_lossy().to_string();
 
-            let extension = path.extension().unwrap_or_default().to_string_lossy().to_string().to_lowercase();
+            // Videoer får fingeravtrykk av flere bilder i stedet for én hash
+            let is_video = media::is_video(path);
+            let key_algorithm = if is_video { &video_algorithm } else { &algorithm };
 
             // Sjekk cache
             {
                 let read_guard = cache.read().unwrap();
-                if let Some(cached_hash_str) = read_guard.get(path_str, mtime) {
-                    let _ = app_handle.emit("progress", serde_json::json!({ "tick": true }));
+                if let Some(cached_hash_str) = read_guard.get(path_str, mtime, key_algorithm) {
+                    progress.advance(size_bytes, Some(path_str));
                     return Some(ImageWithHash {
-                        info: ImageInfo { path: path_str.clone(), filename, extension, size_bytes },
+                        info: ImageInfo::describe(path_str, size_bytes),
                         hash: cached_hash_str,
                     });
                 }
             }
 
             // Beregn hash
-            match hashing::load_image(path) {
-                Ok(img) => {
-                    match hashing::compute_perceptual_hash(&img, hashing::HashType::Difference) {
-                        Ok(hash) => {
-                            let hash_str = hash.to_base64();
-                            {
-                                let mut write_guard = cache.write().unwrap();
-                                write_guard.insert(path_str.clone(), mtime, hash_str.clone());
-                            }
-                            let _ = app_handle.emit("progress", serde_json::json!({ "tick": true }));
-                            Some(ImageWithHash {
-                                info: ImageInfo { path: path_str.clone(), filename, extension, size_bytes },
-                                hash: hash_str,
-                            })
-                        }
-                        Err(_) => {
-                            *error_count.lock().unwrap() += 1;
-                            None
-                        }
+            let hash = if is_video {
+                video::compute_fingerprint(path, config.hash_type, config.hash_size).map(|f| f.to_cache_string())
+            } else {
+                hashing::load_image(path).and_then(|img| {
+                    if config.transform_invariant {
+                        hashing::compute_dihedral_hash(&img, config.hash_type, config.hash_size).map(|h| h.to_hex())
+                    } else {
+                        hashing::compute_perceptual_hash_with_size(&img, config.hash_type, config.hash_size).map(|h| h.to_hex())
                     }
+                })
+            };
+            match hash {
+                Ok(hash_str) => {
+                    {
+                        let mut write_guard = cache.write().unwrap();
+                        write_guard.insert(path_str.clone(), mtime, key_algorithm, hash_str.clone());
+                    }
+                    progress.advance(size_bytes, Some(path_str));
+                    Some(ImageWithHash {
+                        info: ImageInfo::describe(path_str, size_bytes),
+                        hash: hash_str,
+                    })
                 }
                 Err(_) => {
                     *error_count.lock().unwrap() += 1;
+                    progress.error(Some(path_str));
                     None
                 }
             }
         })
         .collect()
     });
+    progress.finish_phase();
 
-    // Lagre cache
+    // Lagre cache (også ved avbrytelse, så ferdig hashede bilder slipper å hashes på nytt)
     if let Ok(read_guard) = cache.read() {
         let _ = read_guard.save();
     }
+
+    if cancel.is_cancelled() {
+        return Err(CANCELLED_MESSAGE.to_string());
+    }
     
-    // Bygg BK-Tree for visuelt søk
-    let mut tree = bk_tree::BKTree::new(hashing::PerceptualMetric);
-    let mut hash_to_indices: HashMap<ComparableHash, Vec<usize>> = HashMap::new();
+    // Sorter på sti så gruppene blir like uavhengig av trådrekkefølgen
+    hashed_images.sort_by(|a, b| a.info.path.cmp(&b.info.path));
+
+    // Finn visuelle grupper. Klyngingen teller ikke per fil, så fasen har ingen total.
+    progress.start_phase(ProgressPhase::Clustering, None);
+    let mut clusters = if config.transform_invariant {
+        visual_clusters(
+            &hashed_images,
+            |img| (!img.info.kind.is_video()).then(|| DihedralHash::from_hex(&img.hash)).flatten(),
+            |keys| clustering::cluster_dihedral(keys, threshold, config.cluster_mode),
+            |reference, member| member.best_match(reference),
+        )
+    } else {
+        visual_clusters(
+            &hashed_images,
+            |img| (!img.info.kind.is_video()).then(|| ImageHash::from_hex(&img.hash)).flatten(),
+            |keys| clustering::cluster(keys, hashing::PerceptualMetric, threshold, config.cluster_mode),
+            |reference, member| (hashing::PerceptualMetric.distance(reference, member), Transform::Identity),
+        )
+    };
+    // Videoer sammenlignes bare med videoer. Distansen er snittet per bilde, så samme terskel gjelder.
+    clusters.extend(visual_clusters(
+        &hashed_images,
+        |img| img.info.kind.is_video().then(|| VideoFingerprint::from_cache_string(&img.hash)).flatten(),
+        |keys| video::cluster_videos(keys, threshold, config.cluster_mode),
+        |reference, member| (reference.distance(member), Transform::Identity),
+    ));
+    let mut duplicate_groups = build_duplicate_groups(clusters, &hashed_images, &exact_groups, config.verification);
+    apply_hardlink_aliases(duplicate_groups.iter_mut().flat_map(|g| g.images.iter_mut()), &hardlink_paths);
+    progress.finish_phase();
+
+    let total_duplicates: usize = duplicate_groups.iter().map(|g| g.images.len() - 1).sum();
+    let errors = *error_count.lock().unwrap();
+
+    Ok(DuplicateResult {
+        groups: duplicate_groups,
+        hardlink_groups: hardlink_paths.iter().map(|group| DuplicateGroup::hardlinks(group)).collect(),
+        total_duplicates,
+        processed: paths_len,
+        errors,
+    })
+}
 
+/// Parser hashene og deler bildene i klynger. Returnerer indekser inn i `hashed_images`
+/// med distanse og transformasjon relativt til første medlem (lavest sti), som er
+/// referansebildet. Bilder som `parse` ikke gir nøkkel for hoppes over, og `cluster`
+/// får nøklene og returnerer klyngene som indekser inn i dem.
+fn visual_clusters<K>(
+    hashed_images: &[ImageWithHash],
+    parse: impl Fn(&ImageWithHash) -> Option<K>,
+    cluster: impl FnOnce(&[K]) -> Vec<Vec<usize>>,
+    compare: impl Fn(&K, &K) -> (u32, Transform),
+) -> Vec<Vec<(usize, u32, Transform)>> {
+    // `owners` peker fra nøkkel tilbake til hashed_images
+    let mut keys: Vec<K> = Vec::new();
+    let mut owners: Vec<usize> = Vec::new();
     for (idx, img) in hashed_images.iter().enumerate() {
-        if let Ok(hash) = img_hash::ImageHash::<Box<[u8]>>::from_base64(&img.hash) {
-             let comp_hash = ComparableHash(hash);
-             tree.add(comp_hash.clone());
-             hash_to_indices.entry(comp_hash).or_default().push(idx);
+        if let Some(key) = parse(img) {
+            keys.push(key);
+            owners.push(idx);
         }
     }
 
-    // Finn visuelle grupper
-    let mut final_groups: Vec<Vec<ImageInfo>> = Vec::new();
-    let mut visited: std::collections::HashSet<usize> = std::collections::HashSet::new();
+    cluster(&keys)
+        .into_iter()
+        .map(|cluster| {
+            let reference = &keys[cluster[0]];
+            cluster
+                .into_iter()
+                .map(|key_idx| {
+                    let (dist, transform) = compare(reference, &keys[key_idx]);
+                    (owners[key_idx], dist, transform)
+                })
+                .collect()
+        })
+        .collect()
+}
 
-    for (i, img) in hashed_images.iter().enumerate() {
-        if visited.contains(&i) { continue; }
+/// Bygger duplikatgrupper fra de visuelle klyngene. Hver representant utvides med de
+/// eksakte kopiene som ble filtrert ut i steg 1, og eksakte grupper der representanten
+/// ikke havnet i noen klynge rapporteres som rene eksakte kopier. Sortert på første sti.
+fn build_duplicate_groups(
+    clusters: Vec<Vec<(usize, u32, Transform)>>,
+    hashed_images: &[ImageWithHash],
+    exact_groups: &HashMap<String, Vec<ImageInfo>>,
+    verification: VerificationLevel,
+) -> Vec<DuplicateGroup> {
+    // Oppslag fra representant til eksakte kopier (inkludert representanten selv)
+    let exact_by_rep: HashMap<&str, &Vec<ImageInfo>> = exact_groups
+        .values()
+        .filter_map(|group| group.first().map(|first| (first.path.as_str(), group)))
+        .collect();
 
-        if let Ok(hash) = img_hash::ImageHash::<Box<[u8]>>::from_base64(&img.hash) {
-            let comp_hash = ComparableHash(hash);
-            let matches = tree.find(&comp_hash, threshold);
-            
-            let mut group_members: Vec<ImageInfo> = Vec::new();
-            
-            // Hvis vi finner matcher, må vi utvide resultatet med evt eksakte kopier
-            // som vi filtrerte ut tidligere.
-            for (_dist, found_hash) in matches {
-                if let Some(indices) = hash_to_indices.get(found_hash) {
-                    for &idx in indices {
-                        if !visited.contains(&idx) {
-                            visited.insert(idx);
-                            
-                            // 1. Legg til den visuelle matchen (representanten)
-                            let rep = &hashed_images[idx];
-                            group_members.push(rep.info.clone());
-                            
-                            // 2. Sjekk om denne representanten har eksakte kopier
-                            // Vi må finne dem ved å søke gjennom exact_groups
-                            // Dette er litt tregt (lineært søk), men antall grupper er forhåpentligvis håndterbart.
-                            // Optimalisering: Kunne lagd en map: path -> group_id
-                            
-                            for group in exact_groups.values() {
-                                // Hvis representanten finnes i en eksakt gruppe...
-                                if group.iter().any(|g| g.path == rep.info.path) {
-                                    // ...legg til resten av gruppen også
-                                    for member in group {
-                                        if member.path != rep.info.path {
-                                            group_members.push(member.clone());
-                                        }
-                                    }
-                                }
-                            }
-                        }
+    let mut duplicate_groups: Vec<DuplicateGroup> = Vec::new();
+    let mut grouped_reps: std::collections::HashSet<&str> = std::collections::HashSet::new();
+
+    for cluster in clusters {
+        let mut images: Vec<ImageInfo> = Vec::new();
+        let mut distances: Vec<u32> = Vec::new();
+        let mut transforms: Vec<Transform> = Vec::new();
+        let mut exact_subgroups: Vec<Vec<String>> = Vec::new();
+
+        // Utvid hver visuell match med eksakte kopier som ble filtrert ut i steg 1
+        for (image_idx, dist, transform) in cluster {
+            let rep = &hashed_images[image_idx];
+            images.push(rep.info.clone());
+            distances.push(dist);
+            transforms.push(transform);
+
+            if let Some(group) = exact_by_rep.get(rep.info.path.as_str()) {
+                if group.len() > 1 {
+                    for member in group.iter().filter(|m| m.path != rep.info.path) {
+                        images.push(member.clone());
+                        distances.push(dist);
+                        transforms.push(transform);
                     }
+                    exact_subgroups.push(group.iter().map(|m| m.path.clone()).collect());
+                    // En representant med eksakte kopier gir alltid en gruppe (> 1 medlem)
+                    grouped_reps.insert(rep.info.path.as_str());
                 }
             }
+        }
 
-            if group_members.len() > 1 {
-                final_groups.push(group_members);
+        if images.len() > 1 {
+            duplicate_groups.push(DuplicateGroup::new(images, distances, transforms, exact_subgroups, verification));
+        }
+    }
+
+    // Eksakte grupper der representanten ikke kunne hashes visuelt (f.eks. videoer når ffmpeg mangler)
+    // rapporteres likevel som rene eksakte kopier.
+    for (rep_path, group) in &exact_by_rep {
+        if group.len() > 1 && !grouped_reps.contains(rep_path) {
+            let paths = group.iter().map(|m| m.path.clone()).collect();
+            duplicate_groups.push(DuplicateGroup::new(
+                group.to_vec(),
+                vec![0; group.len()],
+                vec![Transform::Identity; group.len()],
+                vec![paths],
+                verification,
+            ));
+        }
+    }
+    duplicate_groups.sort_by(|a, b| a.images[0].path.cmp(&b.images[0].path));
+    duplicate_groups
+}
+
+/// Deler kandidatgrupper (lik størrelse + partial hash) i undergrupper som er bekreftet like
+/// Filer som ikke kan leses havner i egne grupper, slik at de aldri merkes som eksakte.
+fn verify_exact_groups(
+    groups: HashMap<String, Vec<ImageInfo>>,
+    level: VerificationLevel,
+    cancel: &CancelToken,
+    progress: &ProgressReporter,
+) -> HashMap<String, Vec<ImageInfo>> {
+    groups
+        .into_par_iter()
+        .flat_map_iter(|(key, members)| {
+            let subgroups = if members.len() < 2 || cancel.is_cancelled() {
+                vec![members]
+            } else {
+                match level {
+                    VerificationLevel::Partial => vec![members],
+                    VerificationLevel::FullHash => split_by_full_hash(members, progress),
+                    VerificationLevel::ByteCompare => split_by_byte_compare(members, progress),
+                }
+            };
+
+            subgroups
+                .into_iter()
+                .enumerate()
+                .map(move |(i, group)| (format!("{}_{}", key, i), group))
+        })
+        .collect()
+}
+
+/// Grupperer på full SHA-256 av filinnholdet
+fn split_by_full_hash(members: Vec<ImageInfo>, progress: &ProgressReporter) -> Vec<Vec<ImageInfo>> {
+    let mut by_hash: HashMap<String, Vec<ImageInfo>> = HashMap::new();
+    let mut unreadable: Vec<Vec<ImageInfo>> = Vec::new();
+
+    for member in members {
+        match hashing::compute_exact_hash(Path::new(&member.path)) {
+            Ok(hash) => {
+                progress.advance(member.size_bytes, Some(&member.path));
+                by_hash.entry(hash).or_default().push(member);
+            }
+            Err(_) => {
+                progress.error(Some(&member.path));
+                unreadable.push(vec![member]);
             }
         }
     }
-    
-    // Legg til eventuelle "rene" eksakte grupper som ikke ble fanget opp av visuelt søk? 
-    // (Det burde ikke skje, siden representanten er med i visuelt søk, og vil matche seg selv med distanse 0).
-    
-    let duplicate_groups: Vec<DuplicateGroup> = final_groups
-        .into_iter()
-        .map(|images| DuplicateGroup { images })
-        .collect();
 
-    let total_duplicates: usize = duplicate_groups.iter().map(|g| g.images.len() - 1).sum();
-    let errors = *error_count.lock().unwrap();
+    by_hash.into_values().chain(unreadable).collect()
+}
 
-    Ok(DuplicateResult {
-        groups: duplicate_groups,
-        total_duplicates,
-        processed: paths_len,
-        errors,
-    })
+/// Sammenligner hver fil byte for byte mot representanten for hver undergruppe
+fn split_by_byte_compare(members: Vec<ImageInfo>, progress: &ProgressReporter) -> Vec<Vec<ImageInfo>> {
+    let mut subgroups: Vec<Vec<ImageInfo>> = Vec::new();
+
+    for member in members {
+        progress.advance(member.size_bytes, Some(&member.path));
+        let path = Path::new(&member.path);
+        let existing = subgroups.iter().position(|group| {
+            hashing::files_identical(Path::new(&group[0].path), path).unwrap_or(false)
+        });
+
+        match existing {
+            Some(idx) => subgroups[idx].push(member),
+            None => subgroups.push(vec![member]),
+        }
+    }
+
+    subgroups
 }
 
+/// Velger hvilket bilde som skal beholdes i hver duplikatgruppe
+/// `groups` er stiene fra hver `DuplicateGroup`; uten egne regler brukes `keeper::default_rules`.
+/// Svaret har ett element per gruppe i samme rekkefølge - None for tomme grupper.
+#[tauri::command]
+pub async fn select_keepers(
+    groups: Vec<Vec<String>>,
+    rules: Option<Vec<KeepRule>>,
+) -> Result<Vec<Option<KeepDecision>>, String> {
+    let rules = rules.unwrap_or_else(keeper::default_rules);
+
+    let decisions = groups
+        .par_iter()
+        .map(|group| keeper::select_keeper(group, &rules))
+        .collect();
 
+    Ok(decisions)
+}
 
 /// Sorterer bilder basert på dato til en målsti (År/Måned)
+/// Kan avbrytes med `cancel_job(job_id)`; allerede behandlede filer rapporteres i resultatet.
 #[tauri::command]
 pub async fn sort_images_by_date(
+    app: tauri::AppHandle,
+    jobs: tauri::State<'_, JobRegistry>,
     paths: Vec<String>,
     method: String, // "copy" eller "move"
     target_dir: String,
     options: Option<SortConfig>,
+    job_id: Option<String>,
 ) -> Result<OperationResult, String> {
-    
+    let job = jobs.start(job_id)?;
+    let progress = progress_reporter(&app, &job.id);
+
     let config = options.unwrap_or(SortConfig {
         use_day_folder: false,
         use_month_names: false,
     });
 
-    let result = sorter::sort_images(paths, &target_dir, &method, config);
+    let result = sorter::sort_images_tracked(paths, &target_dir, &method, config, &job.token, &progress);
     Ok(result)
 }
 
 /// Sletter bilder (flytter til papirkurv hvis mulig)
 #[tauri::command]
-pub async fn delete_images(paths: Vec<String>) -> Result<OperationResult, String> {
-    let result = sorter::delete_images(paths);
+pub async fn delete_images(
+    app: tauri::AppHandle,
+    jobs: tauri::State<'_, JobRegistry>,
+    paths: Vec<String>,
+    job_id: Option<String>,
+) -> Result<OperationResult, String> {
+    let job = jobs.start(job_id)?;
+    let progress = progress_reporter(&app, &job.id);
+    let result = sorter::delete_images_tracked(paths, &job.token, &progress);
+    Ok(result)
+}
+
+/// Gir filer riktig endelse basert på innholdet (f.eks. HEIC lagret som .jpg).
+/// Sidecar-filer følger med, og navnekollisjoner løses som ved sortering.
+#[tauri::command]
+pub async fn fix_extensions(
+    app: tauri::AppHandle,
+    jobs: tauri::State<'_, JobRegistry>,
+    paths: Vec<String>,
+    job_id: Option<String>,
+) -> Result<OperationResult, String> {
+    let job = jobs.start(job_id)?;
+    let progress = progress_reporter(&app, &job.id);
+    let result = sorter::fix_extensions_tracked(paths, &job.token, &progress);
     Ok(result)
 }
 
@@ -383,3 +865,97 @@ pub async fn move_images(paths: Vec<String>, target_dir: String) -> Result<Opera
     let result = sorter::move_images(paths, &target_dir);
     Ok(result)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(path: &str) -> ImageInfo {
+        ImageInfo::describe(path, 100)
+    }
+
+    fn hashed(path: &str) -> ImageWithHash {
+        ImageWithHash { info: info(path), hash: String::new() }
+    }
+
+    fn paths(images: &[ImageInfo]) -> Vec<&str> {
+        images.iter().map(|i| i.path.as_str()).collect()
+    }
+
+    #[test]
+    fn test_match_kind_classification() {
+        let images = || vec![info("/a.jpg"), info("/b.jpg"), info("/c.jpg")];
+        let new = |subgroups: Vec<Vec<&str>>| {
+            let subgroups = subgroups.into_iter().map(|g| g.into_iter().map(String::from).collect()).collect();
+            DuplicateGroup::new(images(), vec![0; 3], vec![Transform::Identity; 3], subgroups, VerificationLevel::FullHash)
+        };
+
+        let visual = new(vec![]);
+        assert_eq!(visual.kind, MatchKind::Visual);
+        assert_eq!(visual.verification, None);
+
+        let exact = new(vec![vec!["/a.jpg", "/b.jpg", "/c.jpg"]]);
+        assert_eq!(exact.kind, MatchKind::Exact);
+        assert_eq!(exact.verification, Some(VerificationLevel::FullHash));
+
+        // Én eksakt kopi pluss et visuelt treff
+        assert_eq!(new(vec![vec!["/a.jpg", "/b.jpg"]]).kind, MatchKind::Mixed);
+        // To sett med kopier som ligner hverandre dekker ikke alle som ett sett
+        let two_sets = DuplicateGroup::new(
+            vec![info("/a.jpg"), info("/a2.jpg"), info("/b.jpg"), info("/b2.jpg")],
+            vec![0, 0, 4, 4],
+            vec![Transform::Identity; 4],
+            vec![vec!["/a.jpg".into(), "/a2.jpg".into()], vec!["/b.jpg".into(), "/b2.jpg".into()]],
+            VerificationLevel::Partial,
+        );
+        assert_eq!(two_sets.kind, MatchKind::Mixed);
+    }
+
+    #[test]
+    fn test_exact_subgroups_are_expanded() {
+        // `/a.jpg` representerer de eksakte kopiene `/a.jpg` og `/kopi/a.jpg`
+        let hashed_images = vec![hashed("/a.jpg"), hashed("/b.jpg"), hashed("/c.jpg"), hashed("/d.jpg")];
+        let mut exact_groups = HashMap::new();
+        exact_groups.insert("1".to_string(), vec![info("/a.jpg"), info("/kopi/a.jpg")]);
+        // Video uten visuell hash (ffmpeg mangler) er bare med i steg 1
+        exact_groups.insert("2".to_string(), vec![info("/film.mp4"), info("/kopi/film.mp4")]);
+        // Enkeltfiler med unik partial hash blir aldri egne grupper
+        exact_groups.insert("3".to_string(), vec![info("/e.jpg")]);
+
+        let clusters = vec![
+            vec![(0, 0, Transform::Identity), (1, 3, Transform::Rotate90)],
+            vec![(2, 0, Transform::Identity), (3, 5, Transform::Identity)],
+        ];
+        let groups = build_duplicate_groups(clusters, &hashed_images, &exact_groups, VerificationLevel::ByteCompare);
+        assert_eq!(groups.len(), 3);
+
+        let mixed = &groups[0];
+        assert_eq!(paths(&mixed.images), ["/a.jpg", "/kopi/a.jpg", "/b.jpg"]);
+        // Kopien arver representantens distanse og transformasjon
+        assert_eq!(mixed.distances, [0, 0, 3]);
+        assert_eq!(mixed.transforms, [Transform::Identity, Transform::Identity, Transform::Rotate90]);
+        assert_eq!(mixed.exact_subgroups, [vec!["/a.jpg".to_string(), "/kopi/a.jpg".to_string()]]);
+        assert_eq!(mixed.kind, MatchKind::Mixed);
+        assert_eq!(mixed.verification, Some(VerificationLevel::ByteCompare));
+
+        let visual = &groups[1];
+        assert_eq!(paths(&visual.images), ["/c.jpg", "/d.jpg"]);
+        assert_eq!(visual.kind, MatchKind::Visual);
+        assert!(visual.exact_subgroups.is_empty());
+
+        let exact = &groups[2];
+        assert_eq!(paths(&exact.images), ["/film.mp4", "/kopi/film.mp4"]);
+        assert_eq!(exact.kind, MatchKind::Exact);
+        assert_eq!(exact.distances, [0, 0]);
+    }
+
+    #[test]
+    fn test_hardlink_aliases_on_first_path() {
+        let mut images = vec![info("/a.jpg"), info("/b.jpg")];
+        let groups = vec![vec!["/a.jpg".to_string(), "/lenke/a.jpg".to_string()]];
+
+        apply_hardlink_aliases(&mut images, &groups);
+        assert_eq!(images[0].aliases, vec!["/lenke/a.jpg"]);
+        assert!(images[1].aliases.is_empty());
+    }
+}
@@ -0,0 +1,16 @@
+//! Kommandoer for å styre langvarige jobber
+
+use crate::services::jobs::JobRegistry;
+
+/// Ber en kjørende jobb (skanning, duplikatsøk, sortering) om å stoppe
+/// Returnerer false hvis jobben ikke finnes eller allerede er ferdig.
+#[tauri::command]
+pub async fn cancel_job(jobs: tauri::State<'_, JobRegistry>, job_id: String) -> Result<bool, String> {
+    Ok(jobs.cancel(&job_id))
+}
+
+/// Lister IDene til jobbene som kjører nå
+#[tauri::command]
+pub async fn list_jobs(jobs: tauri::State<'_, JobRegistry>) -> Result<Vec<String>, String> {
+    Ok(jobs.active_jobs())
+}
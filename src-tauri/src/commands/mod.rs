@@ -1,4 +1,6 @@
 //! Tauri-kommandoer som kalles fra frontend
 
 pub mod folder;
+pub mod jobs;
+pub mod tools;
 
@@ -0,0 +1,59 @@
+//! Kommandoer for eksterne verktøy (ffmpeg/ffprobe)
+
+use crate::services::tools::{self, ToolLocator, ToolSettings, ToolStatus};
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Manager};
+
+/// Fil med brukerens egne verktøystier, i appens konfigurasjonsmappe
+fn settings_file(app: &AppHandle) -> Option<PathBuf> {
+    app.path().app_config_dir().ok().map(|dir| dir.join("tools.json"))
+}
+
+/// Mapper der de medfølgende sidecar-binærene (`bundle.externalBin`) kan ligge:
+/// ved siden av den kjørbare filen (installert app og `tauri dev`) og i ressursmappen.
+fn sidecar_dirs(app: &AppHandle) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(dir) = std::env::current_exe().ok().as_deref().and_then(Path::parent) {
+        dirs.push(dir.to_path_buf());
+    }
+    if let Ok(dir) = app.path().resource_dir() {
+        dirs.push(dir);
+    }
+    dirs
+}
+
+/// Oppslaget som brukes ved oppstart, se `tools::init`
+pub fn locator(app: &AppHandle) -> ToolLocator {
+    let settings = settings_file(app).map(|file| ToolSettings::load(&file)).unwrap_or_default();
+    ToolLocator::new(sidecar_dirs(app), settings)
+}
+
+/// Hvilke verktøy som ble funnet, hvor og i hvilken versjon
+#[tauri::command]
+pub async fn get_tool_status() -> Result<ToolStatus, String> {
+    Ok(tools::status())
+}
+
+/// Brukerens lagrede innstillinger, så dialogen kan vise stiene som er valgt
+#[tauri::command]
+pub async fn get_tool_settings(app: AppHandle) -> Result<ToolSettings, String> {
+    Ok(settings_file(&app).map(|file| ToolSettings::load(&file)).unwrap_or_default())
+}
+
+/// Lagrer brukerens egne stier til ffmpeg/ffprobe og slår opp verktøyene på nytt.
+/// Tidsgrensen og prosessgrensen i filen beholdes.
+#[tauri::command]
+pub async fn set_tool_paths(
+    app: AppHandle,
+    ffmpeg_path: Option<PathBuf>,
+    ffprobe_path: Option<PathBuf>,
+) -> Result<ToolStatus, String> {
+    let file = settings_file(&app).ok_or("Fant ikke konfigurasjonsmappen")?;
+    let settings = ToolSettings {
+        ffmpeg_path: ffmpeg_path.filter(|path| !path.as_os_str().is_empty()),
+        ffprobe_path: ffprobe_path.filter(|path| !path.as_os_str().is_empty()),
+        ..ToolSettings::load(&file)
+    };
+    settings.save(&file).map_err(|e| e.to_string())?;
+    Ok(tools::update_settings(settings))
+}
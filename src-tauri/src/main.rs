@@ -9,14 +9,28 @@ fn main() {
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_shell::init())
+        .manage(services::jobs::JobRegistry::default())
+        .setup(|app| {
+            // ffmpeg/ffprobe slås opp én gang, før noen kommando trenger dem
+            services::tools::init(commands::tools::locator(app.handle()));
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             commands::folder::scan_folder,
             commands::folder::find_duplicates,
+            commands::folder::select_keepers,
             commands::folder::get_thumbnail,
             commands::folder::open_image,
+            commands::folder::get_media_metadata,
             commands::folder::sort_images_by_date,
             commands::folder::delete_images,
-            commands::folder::move_images
+            commands::folder::move_images,
+            commands::folder::fix_extensions,
+            commands::jobs::cancel_job,
+            commands::jobs::list_jobs,
+            commands::tools::get_tool_status,
+            commands::tools::get_tool_settings,
+            commands::tools::set_tool_paths
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
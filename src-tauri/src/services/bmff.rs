@@ -0,0 +1,351 @@
+//! Opptakstid og varighet direkte fra MP4/MOV (ISO-BMFF), uten ffprobe
+//!
+//! Bare boksene vi trenger tolkes: `moov/mvhd` (opprettet i UTC og varighet),
+//! Apples `moov/meta` med `mdta`-nøkler (`com.apple.quicktime.creationdate`, med
+//! tidssone) og `©day` i `moov/udta`. Andre containere (AVI, MKV, WMV) krever ffprobe.
+
+use crate::services::metadata::{self, VideoMetadata};
+use chrono::{DateTime, Datelike, FixedOffset, TimeZone, Utc};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Største `moov` vi leser inn. Vanlige opptak har noen hundre kB.
+const MAX_MOOV_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Sekunder fra QuickTime-epoken (1904-01-01) til Unix-epoken (1970-01-01)
+const QUICKTIME_EPOCH_OFFSET: i64 = 2_082_844_800;
+
+/// Nøkkelen iPhone og nyere kameraer bruker for opptakstid med tidssone
+const CREATIONDATE_KEY: &[u8] = b"com.apple.quicktime.creationdate";
+
+const DAY: &[u8; 4] = b"\xA9day";
+
+/// Leser varighet og opptakstid. Returnerer None hvis filen ikke er ISO-BMFF eller
+/// mangler `moov`. Feltene som krever dekoding (oppløsning, kodek osv.) er ikke satt.
+pub fn read_metadata(path: &Path) -> Option<VideoMetadata> {
+    let moov = read_moov(&mut File::open(path).ok()?)?;
+    let mut meta = VideoMetadata::default();
+
+    if let Some(mvhd) = child(&moov, b"mvhd") {
+        parse_mvhd(mvhd, &mut meta);
+    }
+    meta.creation_date = child(&moov, b"meta")
+        .and_then(mdta_creation_date)
+        .or_else(|| child(&moov, b"udta").and_then(udta_date));
+    Some(meta)
+}
+
+/// Går gjennom boksene på toppnivå uten å lese `mdat` (selve videoen), og leser inn `moov`.
+/// `moov` kan ligge både før og etter `mdat`.
+fn read_moov(file: &mut (impl Read + Seek)) -> Option<Vec<u8>> {
+    let file_len = file.seek(SeekFrom::End(0)).ok()?;
+    let mut pos = 0;
+    while pos + 8 <= file_len {
+        file.seek(SeekFrom::Start(pos)).ok()?;
+        let mut header = [0u8; 8];
+        file.read_exact(&mut header).ok()?;
+        let kind: [u8; 4] = header[4..8].try_into().unwrap();
+        // Første boks er `ftyp` (MP4) eller f.eks. `wide`/`mdat` (eldre QuickTime)
+        if !kind.iter().all(|b| b.is_ascii_graphic() || *b == b' ') {
+            return None;
+        }
+
+        let (size, header_len) = match u32::from_be_bytes(header[0..4].try_into().unwrap()) {
+            0 => (file_len - pos, 8),
+            1 => {
+                let mut large = [0u8; 8];
+                file.read_exact(&mut large).ok()?;
+                (u64::from_be_bytes(large), 16)
+            }
+            size => (size as u64, 8),
+        };
+        if size < header_len {
+            return None;
+        }
+
+        if &kind == b"moov" {
+            let len = (size - header_len).min(file_len - pos - header_len);
+            if len > MAX_MOOV_SIZE {
+                return None;
+            }
+            let mut moov = vec![0; len as usize];
+            file.read_exact(&mut moov).ok()?;
+            return Some(moov);
+        }
+        pos = pos.checked_add(size)?;
+    }
+    None
+}
+
+/// Barneboksene i `data` som (type, innhold). Stopper ved første ugyldige størrelse.
+fn boxes(mut data: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    std::iter::from_fn(move || {
+        if data.len() < 8 {
+            return None;
+        }
+        let (size, header_len) = match read_u32(data, 0)? {
+            0 => (data.len(), 8),
+            1 => (usize::try_from(read_u64(data, 8)?).ok()?, 16),
+            size => (size as usize, 8),
+        };
+        if size < header_len || size > data.len() {
+            return None;
+        }
+        let item = (&data[4..8], &data[header_len..size]);
+        data = &data[size..];
+        Some(item)
+    })
+}
+
+fn child<'a>(data: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+    boxes(data).find(|(k, _)| k == kind).map(|(_, content)| content)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(data.get(offset..offset + 8)?.try_into().ok()?))
+}
+
+/// `mvhd` versjon 0 har 32-bits tider og varighet, versjon 1 har 64-bits
+fn parse_mvhd(mvhd: &[u8], meta: &mut VideoMetadata) {
+    let fields = match mvhd.first() {
+        Some(0) => read_u32(mvhd, 4).zip(read_u32(mvhd, 12)).zip(read_u32(mvhd, 16)).map(|((created, scale), duration)| {
+            // Alle bit satt betyr ukjent varighet
+            (created as u64, scale, (duration != u32::MAX).then_some(duration as u64))
+        }),
+        Some(1) => read_u64(mvhd, 4)
+            .zip(read_u32(mvhd, 20))
+            .zip(read_u64(mvhd, 24))
+            .map(|((created, scale), duration)| (created, scale, (duration != u64::MAX).then_some(duration))),
+        _ => None,
+    };
+    let Some((created, timescale, duration)) = fields else {
+        return;
+    };
+
+    meta.creation_time = i64::try_from(created)
+        .ok()
+        .and_then(|seconds| Utc.timestamp_opt(seconds - QUICKTIME_EPOCH_OFFSET, 0).single())
+        .filter(|time| metadata::is_plausible_year(time.year()));
+    meta.duration_ms = duration
+        .filter(|&duration| duration > 0 && timescale > 0)
+        .map(|duration| (duration as u128 * 1000 / timescale as u128) as u64);
+}
+
+/// Apples metadata: `keys` lister nøklene, og `ilst` har verdiene med 1-basert
+/// nøkkelindeks som boks-type.
+fn mdta_creation_date(meta: &[u8]) -> Option<DateTime<FixedOffset>> {
+    let meta = meta_children(meta);
+    let hdlr = child(meta, b"hdlr")?;
+    if hdlr.get(8..12)? != b"mdta" {
+        return None;
+    }
+
+    // `keys` er en FullBox: versjon/flagg, antall, og så [størrelse][navnerom][nøkkel]
+    let keys = child(meta, b"keys")?;
+    let count = read_u32(keys, 4)?;
+    let mut offset = 8;
+    let mut index = None;
+    for i in 1..=count {
+        let size = read_u32(keys, offset)? as usize;
+        let key = keys.get(offset + 8..offset.checked_add(size)?)?;
+        if key == CREATIONDATE_KEY {
+            index = Some(i);
+            break;
+        }
+        offset += size.max(8);
+    }
+
+    let index = index?.to_be_bytes();
+    let item = boxes(child(meta, b"ilst")?).find(|(kind, _)| *kind == index)?.1;
+    metadata::parse_quicktime_date(text_value(item)?)
+}
+
+/// `©day` direkte i `udta` (QuickTime) eller i `udta/meta/ilst` (iTunes-stil)
+fn udta_date(udta: &[u8]) -> Option<DateTime<FixedOffset>> {
+    let item = child(udta, DAY).or_else(|| child(child(meta_children(child(udta, b"meta")?), b"ilst")?, DAY))?;
+    metadata::parse_quicktime_date(text_value(item)?)
+}
+
+/// I MP4 er `meta` en FullBox med 4 byte versjon/flagg foran barna, i QuickTime ikke
+fn meta_children(meta: &[u8]) -> &[u8] {
+    if meta.get(4..8) == Some(b"hdlr") {
+        meta
+    } else {
+        meta.get(4..).unwrap_or_default()
+    }
+}
+
+/// Tekst fra en metadataverdi: enten en `data`-boks (type, locale, tekst) eller
+/// QuickTime sin internasjonale tekst (lengde, språk, tekst)
+fn text_value(item: &[u8]) -> Option<&str> {
+    let bytes = match child(item, b"data") {
+        Some(data) => data.get(8..)?,
+        None => {
+            let len = u16::from_be_bytes(item.get(0..2)?.try_into().ok()?) as usize;
+            item.get(4..4 + len)?
+        }
+    };
+    std::str::from_utf8(bytes).ok().map(|text| text.trim_end_matches('\0'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn atom(kind: &[u8; 4], content: &[u8]) -> Vec<u8> {
+        let mut data = ((content.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(kind);
+        data.extend_from_slice(content);
+        data
+    }
+
+    fn mvhd_v0(created: u32, timescale: u32, duration: u32) -> Vec<u8> {
+        let mut content = vec![0; 100];
+        content[4..8].copy_from_slice(&created.to_be_bytes());
+        content[12..16].copy_from_slice(&timescale.to_be_bytes());
+        content[16..20].copy_from_slice(&duration.to_be_bytes());
+        atom(b"mvhd", &content)
+    }
+
+    fn data_atom(text: &str) -> Vec<u8> {
+        let mut content = 1u32.to_be_bytes().to_vec(); // UTF-8
+        content.extend_from_slice(&[0; 4]);
+        content.extend_from_slice(text.as_bytes());
+        atom(b"data", &content)
+    }
+
+    /// `moov/meta` slik iPhone skriver den, med to nøkler der datoen er nr. 2
+    fn apple_meta(creationdate: &str) -> Vec<u8> {
+        let mut hdlr = vec![0; 8];
+        hdlr.extend_from_slice(b"mdta");
+        hdlr.extend_from_slice(&[0; 12]);
+
+        let mut keys = vec![0, 0, 0, 0];
+        keys.extend_from_slice(&2u32.to_be_bytes());
+        for key in [b"com.apple.quicktime.make".as_slice(), CREATIONDATE_KEY] {
+            keys.extend_from_slice(&((key.len() + 8) as u32).to_be_bytes());
+            keys.extend_from_slice(b"mdta");
+            keys.extend_from_slice(key);
+        }
+
+        let mut ilst = atom(&1u32.to_be_bytes(), &data_atom("Apple"));
+        ilst.extend(atom(&2u32.to_be_bytes(), &data_atom(creationdate)));
+
+        let mut meta = atom(b"hdlr", &hdlr);
+        meta.extend(atom(b"keys", &keys));
+        meta.extend(atom(b"ilst", &ilst));
+        atom(b"meta", &meta)
+    }
+
+    /// 2023-06-14 16:22:31 UTC i sekunder siden 1904
+    const CREATED: u32 = (1_686_759_751 + QUICKTIME_EPOCH_OFFSET) as u32;
+
+    fn write(dir: &Path, name: &str, data: &[u8]) -> std::path::PathBuf {
+        let path = dir.join(name);
+        fs::write(&path, data).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_mvhd_date_and_duration() {
+        let dir = tempdir().unwrap();
+        let mut file = atom(b"ftyp", b"isom\0\0\x02\0isomiso2mp41");
+        file.extend(atom(b"mdat", &[0xAB; 1000]));
+        // `moov` etter `mdat`, som fra de fleste kameraer
+        file.extend(atom(b"moov", &mvhd_v0(CREATED, 600, 7407)));
+        let path = write(dir.path(), "klipp.mp4", &file);
+
+        let meta = read_metadata(&path).unwrap();
+        assert_eq!(meta.duration_ms, Some(12_345));
+        assert_eq!(meta.creation_time.unwrap().to_rfc3339(), "2023-06-14T16:22:31+00:00");
+        assert_eq!(meta.creation_date, None);
+    }
+
+    #[test]
+    fn test_mvhd_version_1_and_large_size() {
+        let dir = tempdir().unwrap();
+        let mut mvhd = vec![0; 112];
+        mvhd[0] = 1;
+        mvhd[4..12].copy_from_slice(&(CREATED as u64).to_be_bytes());
+        mvhd[20..24].copy_from_slice(&90_000u32.to_be_bytes());
+        mvhd[24..32].copy_from_slice(&(90_000u64 * 3600).to_be_bytes());
+
+        // `mdat` med 64-bits størrelse
+        let mut file = atom(b"ftyp", b"qt  \0\0\0\0qt  ");
+        file.extend_from_slice(&1u32.to_be_bytes());
+        file.extend_from_slice(b"mdat");
+        file.extend_from_slice(&(16u64 + 32).to_be_bytes());
+        file.extend_from_slice(&[0; 32]);
+        file.extend(atom(b"moov", &atom(b"mvhd", &mvhd)));
+        let path = write(dir.path(), "lang.mov", &file);
+
+        let meta = read_metadata(&path).unwrap();
+        assert_eq!(meta.duration_ms, Some(3_600_000));
+        assert_eq!(meta.creation_time.unwrap().timestamp(), 1_686_759_751);
+    }
+
+    #[test]
+    fn test_apple_creationdate_with_timezone() {
+        let dir = tempdir().unwrap();
+        let mut moov = mvhd_v0(CREATED, 600, 600);
+        moov.extend(apple_meta("2023-06-14T18:22:31+0200"));
+        let mut file = atom(b"ftyp", b"qt  \0\0\0\0qt  ");
+        file.extend(atom(b"moov", &moov));
+        let path = write(dir.path(), "IMG_0001.MOV", &file);
+
+        let meta = read_metadata(&path).unwrap();
+        let created = meta.creation_date.unwrap();
+        assert_eq!(created.offset().local_minus_utc(), 2 * 3600);
+        assert_eq!(created.with_timezone(&Utc), meta.creation_time.unwrap());
+        assert_eq!(meta.recorded_at().unwrap().to_string(), "2023-06-14 18:22:31");
+
+        // Sorteringen finner datoen uten ffprobe og uten fallback til endringsdato
+        let sorted = metadata::read_creation_date_with_fallback(&path, false).unwrap();
+        assert_eq!(sorted.naive_local().to_string(), "2023-06-14 18:22:31");
+    }
+
+    #[test]
+    fn test_udta_day() {
+        // QuickTime-tekst: lengde, språk, tekst
+        let text = "2021-12-24T17:00:00+0100";
+        let mut day = (text.len() as u16).to_be_bytes().to_vec();
+        day.extend_from_slice(&[0x15, 0xC7]);
+        day.extend_from_slice(text.as_bytes());
+        let udta = atom(b"udta", &atom(DAY, &day));
+        assert_eq!(udta_date(&udta[8..]).unwrap().to_rfc3339(), "2021-12-24T17:00:00+01:00");
+
+        // iTunes-stil i `udta/meta/ilst` med FullBox-`meta`
+        let mut meta = vec![0; 4];
+        meta.extend(atom(b"hdlr", &[0; 24]));
+        meta.extend(atom(b"ilst", &atom(DAY, &data_atom("2020-02-29T08:15:00+0900"))));
+        let udta = atom(b"udta", &atom(b"meta", &meta));
+        assert_eq!(udta_date(&udta[8..]).unwrap().to_rfc3339(), "2020-02-29T08:15:00+09:00");
+    }
+
+    #[test]
+    fn test_zero_date_and_other_containers() {
+        let dir = tempdir().unwrap();
+        // Kamera uten klokke
+        let mut file = atom(b"ftyp", b"isom\0\0\0\0isom");
+        file.extend(atom(b"moov", &mvhd_v0(0, 1000, 2000)));
+        let meta = read_metadata(&write(dir.path(), "uten_klokke.mp4", &file)).unwrap();
+        assert_eq!(meta.creation_time, None);
+        assert_eq!(meta.duration_ms, Some(2000));
+
+        let mut avi = b"RIFF".to_vec();
+        avi.extend_from_slice(&100u32.to_le_bytes());
+        avi.extend_from_slice(b"AVI LIST");
+        assert!(read_metadata(&write(dir.path(), "gammel.avi", &avi)).is_none());
+
+        // Avkuttet fil uten `moov`
+        let truncated = atom(b"ftyp", b"isom\0\0\0\0isom");
+        assert!(read_metadata(&write(dir.path(), "avbrutt.mp4", &truncated[..12])).is_none());
+    }
+}
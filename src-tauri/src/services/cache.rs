@@ -4,10 +4,33 @@ use std::fs;
 use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 
+/// Algoritme brukt av cache-filer fra før algoritmevalg ble innført (alltid dHash 8x8)
+const LEGACY_ALGORITHM: &str = "dhash-8";
+
+fn legacy_algorithm() -> String {
+    LEGACY_ALGORITHM.to_string()
+}
+
+/// Format på lagrede hasher. Format 1 er base64 fra img_hash-craten, som ble byttet ut
+/// med egne algoritmer i `hashing`. De gir andre bit for samme bilde, så gamle
+/// oppføringer forkastes ved lasting og bildene hashes på nytt. Format 3 hasher bildet
+/// etter at EXIF-orienteringen er brukt.
+const HASH_FORMAT: u32 = 3;
+
+fn legacy_format() -> u32 {
+    1
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct CachedHash {
     pub hash: String,
     pub mtime: u64,
+    /// Algoritme og hash-størrelse, se `hashing::algorithm_id`
+    #[serde(default = "legacy_algorithm")]
+    pub algorithm: String,
+    /// Se `HASH_FORMAT`
+    #[serde(default = "legacy_format")]
+    pub format: u32,
 }
 
 pub struct HashCache {
@@ -33,10 +56,29 @@ impl HashCache {
         }
     }
 
+    /// Nøkkel for en oppføring: algoritme + sti, slik at ulike algoritmer ikke overskriver hverandre
+    fn entry_key(algorithm: &str, path: &str) -> String {
+        format!("{}|{}", algorithm, path)
+    }
+
     fn load_cache(path: &Path) -> Result<HashMap<String, CachedHash>, Box<dyn std::error::Error>> {
         let content = fs::read_to_string(path)?;
-        let cache = serde_json::from_str(&content)?;
-        Ok(cache)
+        let cache: HashMap<String, CachedHash> = serde_json::from_str(&content)?;
+
+        // Gamle cache-filer brukte bare stien som nøkkel
+        let entries = cache
+            .into_iter()
+            .filter(|(_, entry)| entry.format == HASH_FORMAT)
+            .map(|(key, entry)| {
+                let prefix = format!("{}|", entry.algorithm);
+                if key.starts_with(&prefix) {
+                    (key, entry)
+                } else {
+                    (Self::entry_key(&entry.algorithm, &key), entry)
+                }
+            })
+            .collect();
+        Ok(entries)
     }
 
     pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
@@ -48,10 +90,10 @@ impl HashCache {
         Ok(())
     }
 
-    pub fn get(&self, path: &str, current_mtime: SystemTime) -> Option<String> {
-        if let Some(entry) = self.entries.get(path) {
+    pub fn get(&self, path: &str, current_mtime: SystemTime, algorithm: &str) -> Option<String> {
+        if let Some(entry) = self.entries.get(&Self::entry_key(algorithm, path)) {
             if let Ok(mtime_secs) = current_mtime.duration_since(UNIX_EPOCH) {
-                if entry.mtime == mtime_secs.as_secs() {
+                if entry.mtime == mtime_secs.as_secs() && entry.algorithm == algorithm {
                     return Some(entry.hash.clone());
                 }
             }
@@ -59,15 +101,110 @@ impl HashCache {
         None
     }
 
-    pub fn insert(&mut self, path: String, mtime: SystemTime, hash: String) {
+    pub fn insert(&mut self, path: String, mtime: SystemTime, algorithm: &str, hash: String) {
         if let Ok(mtime_secs) = mtime.duration_since(UNIX_EPOCH) {
             self.entries.insert(
-                path,
+                Self::entry_key(algorithm, &path),
                 CachedHash {
                     hash,
                     mtime: mtime_secs.as_secs(),
+                    algorithm: algorithm.to_string(),
+                    format: HASH_FORMAT,
                 },
             );
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_algorithms_kept_apart() {
+        let dir = tempdir().unwrap();
+        let mtime = SystemTime::now();
+        let mut cache = HashCache::new(dir.path());
+
+        cache.insert("/bilder/a.jpg".to_string(), mtime, "dhash-8", "dhash".to_string());
+        cache.insert("/bilder/a.jpg".to_string(), mtime, "phash-16", "phash".to_string());
+
+        assert_eq!(cache.get("/bilder/a.jpg", mtime, "dhash-8"), Some("dhash".to_string()));
+        assert_eq!(cache.get("/bilder/a.jpg", mtime, "phash-16"), Some("phash".to_string()));
+        assert_eq!(cache.get("/bilder/a.jpg", mtime, "ahash-8"), None);
+    }
+
+    #[test]
+    fn test_legacy_entry_without_format_is_discarded() {
+        let dir = tempdir().unwrap();
+        let mtime = UNIX_EPOCH + std::time::Duration::from_secs(1000);
+        // Oppføring fra før `algorithm` og `format` fantes
+        fs::write(
+            dir.path().join("hash_cache.json"),
+            r#"{"/bilder/a.jpg":{"hash":"ffff0000ffff0000","mtime":1000}}"#,
+        )
+        .unwrap();
+
+        let cache = HashCache::new(dir.path());
+        assert_eq!(cache.get("/bilder/a.jpg", mtime, "dhash-8"), None);
+    }
+
+    #[test]
+    fn test_entry_without_algorithm_is_dhash_8() {
+        let dir = tempdir().unwrap();
+        let mtime = UNIX_EPOCH + std::time::Duration::from_secs(1000);
+        // Gjeldende format, men uten `algorithm`
+        fs::write(
+            dir.path().join("hash_cache.json"),
+            r#"{"/bilder/a.jpg":{"hash":"ffff0000ffff0000","mtime":1000,"format":3}}"#,
+        )
+        .unwrap();
+
+        let cache = HashCache::new(dir.path());
+        assert_eq!(cache.get("/bilder/a.jpg", mtime, "dhash-8"), Some("ffff0000ffff0000".to_string()));
+        assert_eq!(cache.get("/bilder/a.jpg", mtime, "phash-8"), None);
+    }
+
+    #[test]
+    fn test_img_hash_entries_are_discarded() {
+        let dir = tempdir().unwrap();
+        let mtime = UNIX_EPOCH + std::time::Duration::from_secs(1000);
+        // Cache fra før egne hash-algoritmer: base64 og ingen format
+        fs::write(
+            dir.path().join("hash_cache.json"),
+            r#"{
+                "/bilder/a.jpg":{"hash":"gammel","mtime":1000},
+                "phash-16|/bilder/b.jpg":{"hash":"gammel","mtime":1000,"algorithm":"phash-16"}
+            }"#,
+        )
+        .unwrap();
+
+        let mut cache = HashCache::new(dir.path());
+        assert_eq!(cache.get("/bilder/a.jpg", mtime, "dhash-8"), None);
+        assert_eq!(cache.get("/bilder/b.jpg", mtime, "phash-16"), None);
+
+        // Ny hash erstatter den gamle, og gamle oppføringer skrives ikke tilbake
+        cache.insert("/bilder/a.jpg".to_string(), mtime, "dhash-8", "0123456789abcdef".to_string());
+        cache.save().unwrap();
+        let saved = fs::read_to_string(dir.path().join("hash_cache.json")).unwrap();
+        assert!(!saved.contains("gammel"));
+        assert_eq!(
+            HashCache::new(dir.path()).get("/bilder/a.jpg", mtime, "dhash-8"),
+            Some("0123456789abcdef".to_string())
+        );
+    }
+
+    #[test]
+    fn test_save_and_reload() {
+        let dir = tempdir().unwrap();
+        let mtime = SystemTime::now();
+
+        let mut cache = HashCache::new(dir.path());
+        cache.insert("/bilder/b.jpg".to_string(), mtime, "ahash-32", "abc".to_string());
+        cache.save().unwrap();
+
+        let reloaded = HashCache::new(dir.path());
+        assert_eq!(reloaded.get("/bilder/b.jpg", mtime, "ahash-32"), Some("abc".to_string()));
+    }
+}
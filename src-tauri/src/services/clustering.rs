@@ -0,0 +1,351 @@
+//! Gruppering av visuelle duplikater
+//!
+//! Tar en liste med hasher og deler dem i klynger basert på Hamming-distanse.
+//! Resultatet er deterministisk: klyngene og medlemmene er sortert på indeks.
+
+use crate::services::hashing::{DihedralHash, ImageHash, PerceptualMetric};
+use bk_tree::{BKTree, Metric};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// Hvordan bilder innenfor terskelen slås sammen til grupper
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ClusterMode {
+    /// Første ubehandlede bilde henter alle naboer innenfor terskelen (opprinnelig oppførsel).
+    /// Rask, men A~B~C-kjeder kan deles avhengig av rekkefølgen.
+    #[default]
+    Leader,
+    /// Union-find over alle par innenfor terskelen (single-link).
+    /// A~B og B~C gir én gruppe selv om A og C er langt fra hverandre.
+    Transitive,
+    /// Hvert medlem må være innenfor terskelen til alle andre medlemmer.
+    /// Strengest - egnet for arkiver der falske positiver er kostbare.
+    CompleteLink,
+}
+
+/// Deler nøklene i klynger. Returnerer indekser inn i `keys`, inkludert enkeltstående.
+/// `metric` brukes til å beskjære et BK-tre, så den må være en ekte metrikk.
+pub fn cluster<K, M>(keys: &[K], metric: M, threshold: u32, mode: ClusterMode) -> Vec<Vec<usize>>
+where
+    K: Clone + Eq + Hash,
+    M: Metric<K> + Clone,
+{
+    cluster_with(keys.len(), &MetricIndex::new(keys, metric), threshold, mode)
+}
+
+/// Som `cluster`, men rotasjons- og speilingsuavhengig. Minste distanse over de 8
+/// orienteringene er ingen metrikk for dHash og pHash (trekantulikheten holder ikke),
+/// og kan ikke beskjære et BK-tre. I stedet legges alle orienteringene av hvert bilde
+/// i et BK-tre med vanlig Hamming-distanse. Et oppslag på identitetshashen finner da
+/// hvert bilde som har minst én orientering innenfor terskelen - samme sammenligning
+/// som `DihedralHash::best_match`.
+pub fn cluster_dihedral(keys: &[DihedralHash], threshold: u32, mode: ClusterMode) -> Vec<Vec<usize>> {
+    cluster_with(keys.len(), &DihedralIndex::new(keys), threshold, mode)
+}
+
+/// Deler `len` nøkler i klynger med et eget nabooppslag, for nøkler der distansen ikke
+/// er en metrikk og derfor ikke kan slås opp i et BK-tre
+pub fn cluster_with(len: usize, neighbours: &impl Neighbours, threshold: u32, mode: ClusterMode) -> Vec<Vec<usize>> {
+    let mut clusters = match mode {
+        ClusterMode::Leader => leader_clusters(len, neighbours, threshold),
+        ClusterMode::Transitive => transitive_clusters(len, neighbours, threshold),
+        ClusterMode::CompleteLink => transitive_clusters(len, neighbours, threshold)
+            .into_iter()
+            .flat_map(|component| split_complete_link(neighbours, threshold, component))
+            .collect(),
+    };
+
+    for members in &mut clusters {
+        members.sort_unstable();
+    }
+    clusters.sort_unstable_by_key(|members| members[0]);
+    clusters
+}
+
+/// Nabooppslag for klyngingen. Indeksene peker inn i nøklene som klynges.
+pub trait Neighbours {
+    /// Alle indekser innenfor terskelen fra `idx` (inkludert `idx` selv)
+    fn find(&self, idx: usize, threshold: u32) -> Vec<usize>;
+
+    /// Om to indekser er innenfor terskelen fra hverandre
+    fn within(&self, a: usize, b: usize, threshold: u32) -> bool;
+}
+
+/// BK-tre over unike nøkler, med oppslag tilbake til alle indekser som deler nøkkelen
+struct MetricIndex<'a, K, M> {
+    keys: &'a [K],
+    metric: M,
+    tree: BKTree<K, M>,
+    indices: HashMap<K, Vec<usize>>,
+}
+
+impl<'a, K, M> MetricIndex<'a, K, M>
+where
+    K: Clone + Eq + Hash,
+    M: Metric<K> + Clone,
+{
+    fn new(keys: &'a [K], metric: M) -> Self {
+        let mut tree = BKTree::new(metric.clone());
+        let mut indices: HashMap<K, Vec<usize>> = HashMap::new();
+
+        for (idx, key) in keys.iter().enumerate() {
+            let entry = indices.entry(key.clone()).or_default();
+            if entry.is_empty() {
+                tree.add(key.clone());
+            }
+            entry.push(idx);
+        }
+
+        Self { keys, metric, tree, indices }
+    }
+}
+
+impl<K, M> Neighbours for MetricIndex<'_, K, M>
+where
+    K: Clone + Eq + Hash,
+    M: Metric<K>,
+{
+    fn find(&self, idx: usize, threshold: u32) -> Vec<usize> {
+        self.tree
+            .find(&self.keys[idx], threshold)
+            .filter_map(|(_, found)| self.indices.get(found))
+            .flatten()
+            .copied()
+            .collect()
+    }
+
+    fn within(&self, a: usize, b: usize, threshold: u32) -> bool {
+        self.metric.threshold_distance(&self.keys[a], &self.keys[b], threshold).is_some()
+    }
+}
+
+/// BK-tre over alle orienteringene til alle bildene, med oppslag fra hash til bildene
+/// som har den i en av orienteringene
+struct DihedralIndex<'a> {
+    keys: &'a [DihedralHash],
+    tree: BKTree<ImageHash, PerceptualMetric>,
+    owners: HashMap<ImageHash, Vec<usize>>,
+}
+
+impl<'a> DihedralIndex<'a> {
+    fn new(keys: &'a [DihedralHash]) -> Self {
+        let mut tree = BKTree::new(PerceptualMetric);
+        let mut owners: HashMap<ImageHash, Vec<usize>> = HashMap::new();
+
+        for (idx, key) in keys.iter().enumerate() {
+            for hash in key.orientations() {
+                let entry = owners.entry(hash.clone()).or_default();
+                if entry.is_empty() {
+                    tree.add(hash.clone());
+                }
+                // Symmetriske motiver har samme hash i flere orienteringer
+                if entry.last() != Some(&idx) {
+                    entry.push(idx);
+                }
+            }
+        }
+
+        Self { keys, tree, owners }
+    }
+}
+
+impl Neighbours for DihedralIndex<'_> {
+    fn find(&self, idx: usize, threshold: u32) -> Vec<usize> {
+        let mut found: Vec<usize> = self
+            .tree
+            .find(self.keys[idx].identity(), threshold)
+            .filter_map(|(_, hash)| self.owners.get(hash))
+            .flatten()
+            .copied()
+            .collect();
+        found.sort_unstable();
+        found.dedup();
+        found
+    }
+
+    fn within(&self, a: usize, b: usize, threshold: u32) -> bool {
+        let (a, b) = (&self.keys[a], &self.keys[b]);
+        b.best_match(a).0 <= threshold || a.best_match(b).0 <= threshold
+    }
+}
+
+fn leader_clusters(len: usize, neighbours: &impl Neighbours, threshold: u32) -> Vec<Vec<usize>> {
+    let mut visited = vec![false; len];
+    let mut clusters = Vec::new();
+
+    for i in 0..len {
+        if visited[i] {
+            continue;
+        }
+
+        let mut members = vec![i];
+        visited[i] = true;
+        for idx in neighbours.find(i, threshold) {
+            if !visited[idx] {
+                visited[idx] = true;
+                members.push(idx);
+            }
+        }
+        clusters.push(members);
+    }
+
+    clusters
+}
+
+fn transitive_clusters(len: usize, neighbours: &impl Neighbours, threshold: u32) -> Vec<Vec<usize>> {
+    let mut sets = UnionFind::new(len);
+    for i in 0..len {
+        for idx in neighbours.find(i, threshold) {
+            sets.union(i, idx);
+        }
+    }
+
+    let mut by_root: HashMap<usize, Vec<usize>> = HashMap::new();
+    for i in 0..len {
+        by_root.entry(sets.find(i)).or_default().push(i);
+    }
+    by_root.into_values().collect()
+}
+
+/// Deler en sammenhengende komponent slik at alle par i hver klynge er innenfor terskelen.
+/// Medlemmene behandles i indeksrekkefølge og legges i første klynge de passer i.
+fn split_complete_link(neighbours: &impl Neighbours, threshold: u32, mut component: Vec<usize>) -> Vec<Vec<usize>> {
+    component.sort_unstable();
+    let mut clusters: Vec<Vec<usize>> = Vec::new();
+
+    for idx in component {
+        let fits = clusters
+            .iter()
+            .position(|members| members.iter().all(|&other| neighbours.within(idx, other, threshold)));
+
+        match fits {
+            Some(pos) => clusters[pos].push(idx),
+            None => clusters.push(vec![idx]),
+        }
+    }
+
+    clusters
+}
+
+/// Enkel union-find med stikomprimering og union etter rang
+struct UnionFind {
+    parent: Vec<usize>,
+    rank: Vec<u8>,
+}
+
+impl UnionFind {
+    fn new(len: usize) -> Self {
+        Self {
+            parent: (0..len).collect(),
+            rank: vec![0; len],
+        }
+    }
+
+    fn find(&mut self, mut x: usize) -> usize {
+        while self.parent[x] != x {
+            self.parent[x] = self.parent[self.parent[x]];
+            x = self.parent[x];
+        }
+        x
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (root_a, root_b) = (self.find(a), self.find(b));
+        if root_a == root_b {
+            return;
+        }
+        match self.rank[root_a].cmp(&self.rank[root_b]) {
+            std::cmp::Ordering::Less => self.parent[root_a] = root_b,
+            std::cmp::Ordering::Greater => self.parent[root_b] = root_a,
+            std::cmp::Ordering::Equal => {
+                self.parent[root_b] = root_a;
+                self.rank[root_a] += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Absolutt differanse mellom tall - enkel metrikk for testing
+    #[derive(Clone)]
+    struct AbsDiff;
+
+    impl Metric<u32> for AbsDiff {
+        fn distance(&self, a: &u32, b: &u32) -> u32 {
+            a.abs_diff(*b)
+        }
+
+        fn threshold_distance(&self, a: &u32, b: &u32, threshold: u32) -> Option<u32> {
+            let dist = self.distance(a, b);
+            (dist <= threshold).then_some(dist)
+        }
+    }
+
+    #[test]
+    fn test_leader_splits_chain() {
+        // 0 ~ 3 ~ 6, men 0 og 6 er for langt fra hverandre
+        let keys = vec![0, 3, 6];
+        let clusters = cluster(&keys, AbsDiff, 4, ClusterMode::Leader);
+        assert_eq!(clusters, vec![vec![0, 1], vec![2]]);
+    }
+
+    #[test]
+    fn test_transitive_joins_chain() {
+        let keys = vec![0, 3, 6, 100];
+        let clusters = cluster(&keys, AbsDiff, 4, ClusterMode::Transitive);
+        assert_eq!(clusters, vec![vec![0, 1, 2], vec![3]]);
+    }
+
+    #[test]
+    fn test_complete_link_requires_all_pairs() {
+        let keys = vec![0, 3, 6, 4];
+        let clusters = cluster(&keys, AbsDiff, 4, ClusterMode::CompleteLink);
+        // 0, 3 og 4 er innenfor 4 av hverandre, 6 er 6 unna 0
+        assert_eq!(clusters, vec![vec![0, 1, 3], vec![2]]);
+    }
+
+    #[test]
+    fn test_duplicate_keys_share_cluster() {
+        let keys = vec![10, 50, 10, 10];
+        for mode in [ClusterMode::Leader, ClusterMode::Transitive, ClusterMode::CompleteLink] {
+            let clusters = cluster(&keys, AbsDiff, 0, mode);
+            assert_eq!(clusters, vec![vec![0, 2, 3], vec![1]], "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn test_order_is_deterministic() {
+        let keys: Vec<u32> = (0..50).map(|i| (i * 37) % 101).collect();
+        let first = cluster(&keys, AbsDiff, 3, ClusterMode::Transitive);
+        for _ in 0..5 {
+            assert_eq!(cluster(&keys, AbsDiff, 3, ClusterMode::Transitive), first);
+        }
+    }
+
+    /// Dihedral hash der orienteringene er gitt direkte (hex, én per orientering)
+    fn dihedral(orientations: [u64; 8]) -> DihedralHash {
+        let hex: Vec<String> = orientations.iter().map(|word| format!("{:016x}", word)).collect();
+        DihedralHash::from_hex(&hex.join(",")).unwrap()
+    }
+
+    #[test]
+    fn test_dihedral_finds_matches_a_bk_tree_would_prune() {
+        // R ligner X uendret, og Q ligner X rotert - men R og Q er 64 bit fra hverandre.
+        // Minste distanse over orienteringene bryter trekantulikheten, så et BK-tre over
+        // den ville beskjært bort X fra begge oppslagene.
+        let r = dihedral([0; 8]);
+        let x = dihedral([0, u64::MAX, 0, 0, 0, 0, 0, 0]);
+        let q = dihedral([u64::MAX; 8]);
+        let keys = vec![r, x, q];
+
+        assert_eq!(cluster_dihedral(&keys, 0, ClusterMode::Transitive), vec![vec![0, 1, 2]]);
+        assert_eq!(cluster_dihedral(&keys, 0, ClusterMode::Leader), vec![vec![0, 1], vec![2]]);
+        // R og Q er ikke like i noen orientering
+        assert_eq!(cluster_dihedral(&keys, 0, ClusterMode::CompleteLink), vec![vec![0, 1], vec![2]]);
+    }
+}
@@ -4,15 +4,21 @@
 //! Optimalisert for store bildesamlinger
 
 // use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+use crate::services::{heif, media, metadata, preview, raw, signature};
+use image::imageops::FilterType;
 use image::{DynamicImage, GenericImageView};
-use img_hash::{HashAlg, HasherConfig, ImageHash};
+#[cfg(test)]
+use image::{Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use std::f32::consts::PI;
 use std::fs::File;
 use std::io::Read;
 use std::path::Path;
 
 /// Hashe-typer tilgjengelig for duplikatdeteksjon
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub enum HashType {
     /// Eksakt filhash (SHA-256)
     Exact,
@@ -24,6 +30,43 @@ pub enum HashType {
     Average,
 }
 
+impl HashType {
+    /// Kort navn brukt i cache-nøkler og logger
+    pub fn short_name(&self) -> &'static str {
+        match self {
+            HashType::Exact => "sha256",
+            HashType::Perceptual => "phash",
+            HashType::Difference => "dhash",
+            HashType::Average => "ahash",
+        }
+    }
+}
+
+/// Standard hash-størrelse (8x8 = 64 bit)
+pub const DEFAULT_HASH_SIZE: u32 = 8;
+
+/// Støttede hash-størrelser (bredde = høyde): 8x8, 16x16 og 32x32
+pub const SUPPORTED_HASH_SIZES: &[u32] = &[8, 16, 32];
+
+/// Unik identifikator for algoritme + størrelse, f.eks. "dhash-8"
+/// Brukes for å holde cache-oppføringer fra ulike algoritmer adskilt.
+pub fn algorithm_id(hash_type: HashType, hash_size: u32) -> String {
+    format!("{}-{}", hash_type.short_name(), hash_size)
+}
+
+/// Hvor grundig eksakte duplikater er bekreftet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum VerificationLevel {
+    /// Filstørrelse + første/siste 4 KB (`compute_partial_hash`)
+    #[default]
+    Partial,
+    /// Full SHA-256 av hele filinnholdet (`compute_exact_hash`)
+    FullHash,
+    /// Byte-for-byte sammenligning mot gruppens representant
+    ByteCompare,
+}
+
 /// Resultat av en hashing-operasjon
 #[derive(Debug, Clone)]
 pub struct HashResult {
@@ -31,25 +74,59 @@ pub struct HashResult {
     pub hash_type: String,
 }
 
+/// Bufferstørrelse for strømmende hashing (konstant minnebruk per tråd)
+const HASH_BUFFER_SIZE: usize = 256 * 1024;
+
 /// Beregn eksakt SHA-256 hash av en fil
+/// Leser filen i blokker, så minnebruken er konstant selv for videoer på flere GB.
 pub fn compute_exact_hash(path: &Path) -> Result<String, Box<dyn std::error::Error>> {
     let mut file = File::open(path)?;
-    let mut buffer = Vec::new();
-    file.read_to_end(&mut buffer)?;
+    let mut buffer = vec![0u8; HASH_BUFFER_SIZE];
+
+    let mut hasher = Sha256::new();
+    loop {
+        let bytes_read = file.read(&mut buffer)?;
+        if bytes_read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..bytes_read]);
+    }
+
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Beregn eksakt SHA-256 hash via minnemapping av filen
+/// Gir samme digest som `compute_exact_hash`, men lar OS-et håndtere sidecachen.
+/// Raskest på lokale disker; bruk den bufrede varianten for nettverksdisker,
+/// der en fil som endres under lesing kan gi lesefeil i mappingen.
+pub fn compute_exact_hash_mmap(path: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    let file = File::open(path)?;
+
+    // Tomme filer kan ikke mappes på alle plattformer
+    if file.metadata()?.len() == 0 {
+        return Ok(hex::encode(Sha256::digest([])));
+    }
+
+    // SAFETY: Mappingen er skrivebeskyttet og lever kun i denne funksjonen
+    let mmap = unsafe { memmap2::Mmap::map(&file)? };
 
     let mut hasher = Sha256::new();
-    hasher.update(&buffer);
-    let result = hasher.finalize();
+    for chunk in mmap.chunks(HASH_BUFFER_SIZE) {
+        hasher.update(chunk);
+    }
 
-    Ok(hex::encode(result))
+    Ok(hex::encode(hasher.finalize()))
 }
 
+/// Blokkstørrelsen `compute_partial_hash` leser fra starten og slutten av filen
+const PARTIAL_CHUNK_SIZE: u64 = 4096;
+
 /// Leser første 4KB og siste 4KB av filen for en rask "unikhetssjekk"
 /// Dette er mye raskere enn å lese hele filen eller dekode bildet
 pub fn compute_partial_hash(path: &Path) -> Result<String, Box<dyn std::error::Error>> {
     let mut file = File::open(path)?;
     let len = file.metadata()?.len();
-    let chunk_size = 4096;
+    let chunk_size = PARTIAL_CHUNK_SIZE as usize;
     
     let mut hasher = Sha256::new();
     
@@ -72,43 +149,169 @@ pub fn compute_partial_hash(path: &Path) -> Result<String, Box<dyn std::error::E
     Ok(hex::encode(hasher.finalize()))
 }
 
-/// Forsøker å lese embedded thumbnail fra EXIF-data
-/// Dette er ekstremt mye raskere enn å dekode hele bildet
-fn read_embedded_thumbnail(_path: &Path) -> Option<DynamicImage> {
-    // Optimization temporarily disabled: rexif crate 0.7 missing thumbnail field access
-    // and kamadak-exif requires complex offset handling.
-    // Falling back to full decode for stability.
-    None
+/// Hvor mange byte `compute_partial_hash` leser fra en fil på `len` byte
+pub fn partial_hash_bytes(len: u64) -> u64 {
+    if len > PARTIAL_CHUNK_SIZE * 2 {
+        PARTIAL_CHUNK_SIZE * 2
+    } else {
+        len.min(PARTIAL_CHUNK_SIZE)
+    }
 }
 
+/// Sammenligner to filer byte for byte
+/// Leser begge filene i blokker, så minnebruken er konstant uansett filstørrelse.
+pub fn files_identical(a: &Path, b: &Path) -> Result<bool, Box<dyn std::error::Error>> {
+    let mut file_a = File::open(a)?;
+    let mut file_b = File::open(b)?;
+
+    if file_a.metadata()?.len() != file_b.metadata()?.len() {
+        return Ok(false);
+    }
+
+    let mut buf_a = vec![0u8; 64 * 1024];
+    let mut buf_b = vec![0u8; 64 * 1024];
+
+    loop {
+        let read_a = read_full(&mut file_a, &mut buf_a)?;
+        let read_b = read_full(&mut file_b, &mut buf_b)?;
+
+        if read_a != read_b || buf_a[..read_a] != buf_b[..read_b] {
+            return Ok(false);
+        }
+        if read_a == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+/// Fyller bufferet så langt filen rekker (`read` kan returnere færre bytes enn bedt om)
+fn read_full(file: &mut File, buffer: &mut [u8]) -> std::io::Result<usize> {
+    let mut total = 0;
+    while total < buffer.len() {
+        let n = file.read(&mut buffer[total..])?;
+        if n == 0 {
+            break;
+        }
+        total += n;
+    }
+    Ok(total)
+}
+
+/// Minste forhåndsvisning som brukes til hashing. pHash med 32x32 bit sampler
+/// 128x128 piksler, så mindre thumbnails ville blitt skalert opp.
+const MIN_PREVIEW_FOR_HASHING: u32 = 128;
+
 /// Laster et bilde fra fil og skalerer ned for raskere hashing
 /// Optimalisert versjon: Prøver embedded thumbnail først!
 pub fn load_image(path: &Path) -> Result<DynamicImage, Box<dyn std::error::Error>> {
-    // 1. Prøv "Fast Path" - Embedded Thumbnail
-    // Dette kan spare 100-500ms per bilde for store filer (RAW/høyoppløselig JPEG)
-    if let Some(thumb) = read_embedded_thumbnail(path) {
-        // Thumbnail er vanligvis allerede liten (f.eks. 160x120 eller 320x240)
-        // Vi sjekker likevel størrelsen før evt. nedskalering
-        let (width, height) = thumb.dimensions();
-        if width > 512 || height > 512 {
-            return Ok(thumb.resize(512, 512, image::imageops::FilterType::Nearest));
-        }
-        return Ok(thumb);
+    if media::is_video(path) {
+        return Err(format!("Videoer kan ikke hashes visuelt: {}", path.display()).into());
+    }
+
+    // HEIC/AVIF dekodes med libheif - `image` kjenner ikke formatene.
+    // libheif har allerede rotert bildet, så EXIF-orienteringen skal ikke brukes.
+    if heif::is_heif(path, signature::detect_format(path)) {
+        let img = heif::decode(path)
+            .map_err(|e| format!("Kunne ikke dekode HEIF-bildet {}: {}", path.display(), e))?;
+        return Ok(downscale_for_hashing(img));
     }
 
-    // 2. "Slow Path" - Full dekoding
-    // Fallback hvis ingen thumbnail finnes
-    let reader = image::io::Reader::open(path)?
-        .with_guessed_format()?;
+    let img = if media::is_raw(path) {
+        // RAW-data dekodes ikke - forhåndsvisningen har samme motiv og er raskere
+        raw::load_preview(path)
+            .ok_or_else(|| format!("Fant ingen forhåndsvisning i RAW-filen: {}", path.display()))?
+    } else if let Some(thumb) = preview::load_embedded_preview(path, MIN_PREVIEW_FOR_HASHING) {
+        // 1. Prøv "Fast Path" - Embedded Thumbnail (EXIF IFD1 eller MPF)
+        // Dette kan spare 100-500ms per bilde for høyoppløselige JPEG-filer
+        thumb
+    } else {
+        // 2. "Slow Path" - Full dekoding
+        // Fallback hvis ingen thumbnail finnes
+        image::ImageReader::open(path)?.with_guessed_format()?.decode()?
+    };
 
-    let img = reader.decode()?;
+    // 3. Resize for hashing, og snu bildet slik det vises. Et bilde med EXIF-rotasjon
+    // og en eksport der pikslene er rotert skal gi samme hash.
+    let mut img = downscale_for_hashing(img);
+    if let Some(orientation) = metadata::read_orientation(path) {
+        img.apply_orientation(orientation);
+    }
+    Ok(img)
+}
 
-    // 3. Resize for hashing
+/// Skalerer ned til maks 512 px - små bilder skaleres ikke opp
+fn downscale_for_hashing(img: DynamicImage) -> DynamicImage {
     let (width, height) = img.dimensions();
     if width > 512 || height > 512 {
-        Ok(img.resize(512, 512, image::imageops::FilterType::Nearest))
+        img.resize(512, 512, image::imageops::FilterType::Nearest)
     } else {
-        Ok(img)
+        img
+    }
+}
+
+/// Perceptuell hash. Bitene ligger rad for rad, med første bit som mest
+/// signifikante bit i første ord. 8x8 får plass direkte i en `u64`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ImageHash {
+    /// 8x8 = 64 bit
+    Bits64(u64),
+    /// 16x16 = 256 bit og 32x32 = 1024 bit
+    Wide(Box<[u64]>),
+}
+
+impl ImageHash {
+    /// Pakker bitene i 64-bits ord. Antallet er alltid et multiplum av 64 (8x8, 16x16, 32x32).
+    fn from_bits(bits: impl Iterator<Item = bool>) -> Self {
+        let bits: Vec<bool> = bits.collect();
+        let words: Vec<u64> = bits
+            .chunks(64)
+            .map(|chunk| chunk.iter().fold(0u64, |acc, &bit| (acc << 1) | bit as u64))
+            .collect();
+        Self::from_words(words)
+    }
+
+    fn from_words(words: Vec<u64>) -> Self {
+        if words.len() == 1 {
+            ImageHash::Bits64(words[0])
+        } else {
+            ImageHash::Wide(words.into_boxed_slice())
+        }
+    }
+
+    pub fn words(&self) -> &[u64] {
+        match self {
+            ImageHash::Bits64(word) => std::slice::from_ref(word),
+            ImageHash::Wide(words) => words,
+        }
+    }
+
+    /// Antall bit i hashen (64, 256 eller 1024)
+    pub fn bit_count(&self) -> u32 {
+        self.words().len() as u32 * 64
+    }
+
+    /// Hamming-distanse: antall bit som er ulike.
+    /// Hasher av ulik størrelse skal ikke sammenlignes; de ekstra bitene telles da som ulike.
+    pub fn dist(&self, other: &ImageHash) -> u32 {
+        let (a, b) = (self.words(), other.words());
+        let common: u32 = a.iter().zip(b).map(|(x, y)| (x ^ y).count_ones()).sum();
+        common + 64 * a.len().abs_diff(b.len()) as u32
+    }
+
+    /// Hex-streng som lagres i hash-cachen (16 tegn per 64 bit)
+    pub fn to_hex(&self) -> String {
+        self.words().iter().map(|word| format!("{:016x}", word)).collect()
+    }
+
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        if hex.is_empty() || !hex.len().is_multiple_of(16) || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        let words = (0..hex.len())
+            .step_by(16)
+            .map(|i| u64::from_str_radix(&hex[i..i + 16], 16).ok())
+            .collect::<Option<Vec<u64>>>()?;
+        Some(Self::from_words(words))
     }
 }
 
@@ -118,36 +321,111 @@ pub fn compute_perceptual_hash(
     image: &DynamicImage,
     hash_type: HashType,
 ) -> Result<ImageHash, Box<dyn std::error::Error>> {
-    // 8x8 hash er raskere og gir 64-bit hash
-    let hasher = HasherConfig::new()
-        .hash_size(8, 8)
-        .hash_alg(match hash_type {
-            HashType::Perceptual => HashAlg::DoubleGradient,
-            HashType::Difference => HashAlg::Gradient,
-            HashType::Average => HashAlg::Mean,
-            HashType::Exact => {
-                return Err("Bruk compute_exact_hash for eksakt hashing".into());
-            }
-        })
-        .to_hasher();
+    compute_perceptual_hash_with_size(image, hash_type, DEFAULT_HASH_SIZE)
+}
+
+/// Beregner perceptuell hash med valgfri størrelse (8, 16 eller 32)
+/// Større hash gir færre falske positiver, men Hamming-distansen skalerer med antall bit,
+/// så terskelen må justeres tilsvarende.
+pub fn compute_perceptual_hash_with_size(
+    image: &DynamicImage,
+    hash_type: HashType,
+    hash_size: u32,
+) -> Result<ImageHash, Box<dyn std::error::Error>> {
+    if !SUPPORTED_HASH_SIZES.contains(&hash_size) {
+        return Err(format!("Ugyldig hash-størrelse: {} (støttet: 8, 16, 32)", hash_size).into());
+    }
 
-    Ok(hasher.hash_image(image))
+    let hash = match hash_type {
+        HashType::Perceptual => perceptual_hash(image, hash_size),
+        HashType::Difference => difference_hash(image, hash_size),
+        HashType::Average => average_hash(image, hash_size),
+        HashType::Exact => {
+            return Err("Bruk compute_exact_hash for eksakt hashing".into());
+        }
+    };
+    Ok(hash)
 }
 
-/// Wrapper for ImageHash som implementerer bk_tree::Metric
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub struct ComparableHash(pub ImageHash<Box<[u8]>>);
+/// Gråtoner skalert til `width` x `height`, rad for rad
+fn grayscale_pixels(image: &DynamicImage, width: u32, height: u32) -> Vec<f32> {
+    let gray = image.to_luma8();
+    image::imageops::resize(&gray, width, height, FilterType::Triangle)
+        .pixels()
+        .map(|pixel| pixel[0] as f32)
+        .collect()
+}
+
+/// aHash: hvert bit sier om pikselen er lysere enn snittet
+fn average_hash(image: &DynamicImage, size: u32) -> ImageHash {
+    let pixels = grayscale_pixels(image, size, size);
+    let mean = pixels.iter().sum::<f32>() / pixels.len() as f32;
+    ImageHash::from_bits(pixels.iter().map(|&p| p > mean))
+}
+
+/// dHash: hvert bit sier om pikselen er lysere enn naboen til høyre
+fn difference_hash(image: &DynamicImage, size: u32) -> ImageHash {
+    let width = size as usize + 1;
+    let pixels = grayscale_pixels(image, size + 1, size);
+    ImageHash::from_bits(
+        pixels
+            .chunks(width)
+            .flat_map(|row| row.windows(2).map(|pair| pair[0] > pair[1])),
+    )
+}
+
+/// pHash: DCT av et 4x større gråtonebilde. De laveste frekvensene (øverst til venstre)
+/// beskriver motivets grove struktur, og hvert bit sier om koeffisienten er over medianen.
+fn perceptual_hash(image: &DynamicImage, size: u32) -> ImageHash {
+    let sample_size = size * 4;
+    let pixels = grayscale_pixels(image, sample_size, sample_size);
+    let coefficients = dct_low_frequencies(&pixels, sample_size as usize, size as usize);
+
+    // DC-leddet (gjennomsnittlig lysstyrke) holdes utenfor medianen
+    let mut sorted = coefficients[1..].to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let median = sorted[sorted.len() / 2];
+    ImageHash::from_bits(coefficients.iter().map(|&c| c > median))
+}
+
+/// Todimensjonal DCT-II av et `n` x `n` bilde, men bare de `keep` x `keep` laveste
+/// frekvensene beregnes. Kjøres som separable 1D-transformer: først rader, så kolonner.
+fn dct_low_frequencies(pixels: &[f32], n: usize, keep: usize) -> Vec<f32> {
+    // cos_table[k * n + i] = cos(pi / n * (i + 0.5) * k)
+    let cos_table: Vec<f32> = (0..keep)
+        .flat_map(|k| (0..n).map(move |i| (PI / n as f32 * (i as f32 + 0.5) * k as f32).cos()))
+        .collect();
+    let basis = |k: usize| &cos_table[k * n..(k + 1) * n];
+
+    // Rader: n x keep
+    let mut rows = vec![0f32; n * keep];
+    for (y, row) in pixels.chunks(n).enumerate() {
+        for k in 0..keep {
+            rows[y * keep + k] = row.iter().zip(basis(k)).map(|(p, c)| p * c).sum();
+        }
+    }
+
+    // Kolonner: keep x keep
+    let mut out = vec![0f32; keep * keep];
+    for v in 0..keep {
+        for u in 0..keep {
+            out[v * keep + u] = basis(v).iter().enumerate().map(|(y, c)| rows[y * keep + u] * c).sum();
+        }
+    }
+    out
+}
 
 /// Metrikk-implementasjon for BK-Tree
+#[derive(Debug, Clone, Copy)]
 pub struct PerceptualMetric;
 
-impl bk_tree::Metric<ComparableHash> for PerceptualMetric {
+impl bk_tree::Metric<ImageHash> for PerceptualMetric {
 
-    fn distance(&self, a: &ComparableHash, b: &ComparableHash) -> u32 {
-        a.0.dist(&b.0)
+    fn distance(&self, a: &ImageHash, b: &ImageHash) -> u32 {
+        a.dist(b)
     }
     
-    fn threshold_distance(&self, a: &ComparableHash, b: &ComparableHash, threshold: u32) -> Option<u32> {
+    fn threshold_distance(&self, a: &ImageHash, b: &ImageHash, threshold: u32) -> Option<u32> {
         let dist = self.distance(a, b);
         if dist <= threshold {
             Some(dist)
@@ -168,6 +446,104 @@ pub fn are_duplicates(hash1: &ImageHash, hash2: &ImageHash, threshold: u32) -> b
     compare_hashes(hash1, hash2) <= threshold
 }
 
+/// Rotasjon eller speiling av et bilde - de 8 symmetriene til et rektangel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Transform {
+    #[default]
+    Identity,
+    /// 90° med klokka
+    Rotate90,
+    Rotate180,
+    /// 270° med klokka (90° mot klokka)
+    Rotate270,
+    /// Speilet venstre-høyre
+    FlipHorizontal,
+    /// Speilet opp-ned
+    FlipVertical,
+    /// Speilet om diagonalen fra øverst til venstre
+    Transpose,
+    /// Speilet om diagonalen fra øverst til høyre
+    Transverse,
+}
+
+impl Transform {
+    pub const ALL: [Transform; 8] = [
+        Transform::Identity,
+        Transform::Rotate90,
+        Transform::Rotate180,
+        Transform::Rotate270,
+        Transform::FlipHorizontal,
+        Transform::FlipVertical,
+        Transform::Transpose,
+        Transform::Transverse,
+    ];
+
+    pub fn apply(self, image: &DynamicImage) -> DynamicImage {
+        match self {
+            Transform::Identity => image.clone(),
+            Transform::Rotate90 => image.rotate90(),
+            Transform::Rotate180 => image.rotate180(),
+            Transform::Rotate270 => image.rotate270(),
+            Transform::FlipHorizontal => image.fliph(),
+            Transform::FlipVertical => image.flipv(),
+            Transform::Transpose => image.rotate90().fliph(),
+            Transform::Transverse => image.rotate270().fliph(),
+        }
+    }
+}
+
+/// Hasher av et bilde i alle 8 orienteringer, i rekkefølgen til `Transform::ALL`.
+/// Brukes for å finne kopier som er rotert eller speilet (skanninger, eksporter).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DihedralHash(Box<[ImageHash]>);
+
+impl DihedralHash {
+    /// Hashen av bildet slik det er
+    pub fn identity(&self) -> &ImageHash {
+        &self.0[0]
+    }
+
+    /// Minste distanse fra `reference` til dette bildet i en av orienteringene,
+    /// og transformasjonen av dette bildet som ga den. Ved likhet vinner den
+    /// første i `Transform::ALL`, så uendrede kopier rapporteres som `Identity`.
+    pub fn best_match(&self, reference: &DihedralHash) -> (u32, Transform) {
+        Transform::ALL
+            .iter()
+            .zip(self.0.iter())
+            .map(|(&transform, hash)| (reference.identity().dist(hash), transform))
+            .fold((u32::MAX, Transform::Identity), |best, candidate| if candidate.0 < best.0 { candidate } else { best })
+    }
+
+    /// Hashene i alle orienteringer, i rekkefølgen til `Transform::ALL`
+    pub fn orientations(&self) -> &[ImageHash] {
+        &self.0
+    }
+
+    /// Hex-streng for hash-cachen: de 8 hashene skilt med komma
+    pub fn to_hex(&self) -> String {
+        self.0.iter().map(ImageHash::to_hex).collect::<Vec<_>>().join(",")
+    }
+
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let hashes = hex.split(',').map(ImageHash::from_hex).collect::<Option<Vec<_>>>()?;
+        (hashes.len() == Transform::ALL.len()).then(|| DihedralHash(hashes.into_boxed_slice()))
+    }
+}
+
+/// Beregner hashen av bildet i alle 8 orienteringer
+pub fn compute_dihedral_hash(
+    image: &DynamicImage,
+    hash_type: HashType,
+    hash_size: u32,
+) -> Result<DihedralHash, Box<dyn std::error::Error>> {
+    let hashes = Transform::ALL
+        .iter()
+        .map(|transform| compute_perceptual_hash_with_size(&transform.apply(image), hash_type, hash_size))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(DihedralHash(hashes.into_boxed_slice()))
+}
+
 /// Lager et testbilde med gradient for bedre hash-testing
 #[cfg(test)]
 fn create_gradient_image(width: u32, height: u32, start_color: Rgba<u8>, _end_color: Rgba<u8>) -> DynamicImage {
@@ -250,6 +626,182 @@ mod tests {
         assert!(compute_perceptual_hash(&img, HashType::Exact).is_err());
     }
 
+    #[test]
+    fn test_hash_sizes() {
+        let img = create_gradient_image(100, 100, Rgba([255, 0, 0, 255]), Rgba([0, 0, 255, 255]));
+
+        let small = compute_perceptual_hash_with_size(&img, HashType::Difference, 8).unwrap();
+        let large = compute_perceptual_hash_with_size(&img, HashType::Difference, 16).unwrap();
+
+        // Større hash-størrelse gir flere bit
+        assert_eq!(small.bit_count(), 64);
+        assert_eq!(large.bit_count(), 256);
+        assert!(compute_perceptual_hash_with_size(&img, HashType::Perceptual, 32).is_ok());
+        assert!(compute_perceptual_hash_with_size(&img, HashType::Difference, 12).is_err());
+    }
+
+    #[test]
+    fn test_hash_hex_roundtrip() {
+        let img = create_gradient_image(100, 100, Rgba([255, 0, 0, 255]), Rgba([0, 0, 255, 255]));
+        for size in SUPPORTED_HASH_SIZES {
+            let hash = compute_perceptual_hash_with_size(&img, HashType::Perceptual, *size).unwrap();
+            let hex = hash.to_hex();
+            assert_eq!(hex.len() as u32, size * size / 4);
+            assert_eq!(ImageHash::from_hex(&hex), Some(hash));
+        }
+        assert!(matches!(ImageHash::from_hex("00000000000000ff"), Some(ImageHash::Bits64(0xff))));
+        assert_eq!(ImageHash::from_hex("xyz"), None);
+        assert_eq!(ImageHash::from_hex(""), None);
+        // Base64 fra img_hash skal ikke tolkes som hex
+        assert_eq!(ImageHash::from_hex("AAAAAAAAAAA="), None);
+    }
+
+    #[test]
+    fn test_difference_hash_bits() {
+        // Mørkere mot høyre: hver piksel er lysere enn naboen -> alle bit satt
+        let mut img = RgbaImage::new(90, 80);
+        for (x, _, pixel) in img.enumerate_pixels_mut() {
+            let v = 255 - (x * 2) as u8;
+            *pixel = Rgba([v, v, v, 255]);
+        }
+        let hash = compute_perceptual_hash(&DynamicImage::ImageRgba8(img), HashType::Difference).unwrap();
+        assert_eq!(hash, ImageHash::Bits64(u64::MAX));
+    }
+
+    #[test]
+    fn test_hashes_survive_rescaling() {
+        // Et motiv av 10x10 blokker med tilfeldige gråtoner, i to ulike oppløsninger
+        let blocks: Vec<u8> = (0..100u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8).collect();
+        let pattern = |width: u32, height: u32| {
+            let mut img = RgbaImage::new(width, height);
+            for (x, y, pixel) in img.enumerate_pixels_mut() {
+                let v = blocks[(y * 10 / height * 10 + x * 10 / width) as usize];
+                *pixel = Rgba([v, v, v, 255]);
+            }
+            DynamicImage::ImageRgba8(img)
+        };
+        let large = pattern(400, 300);
+        let small = pattern(120, 90);
+        let other = create_gradient_image(400, 300, Rgba([0, 255, 0, 255]), Rgba([0, 0, 0, 255]));
+
+        for hash_type in [HashType::Perceptual, HashType::Difference, HashType::Average] {
+            let a = compute_perceptual_hash(&large, hash_type).unwrap();
+            let b = compute_perceptual_hash(&small, hash_type).unwrap();
+            let c = compute_perceptual_hash(&other, hash_type).unwrap();
+            assert!(compare_hashes(&a, &b) <= 6, "{:?}: nedskalert kopi skal være nær", hash_type);
+            assert!(compare_hashes(&a, &c) > 10, "{:?}: annet motiv skal være langt unna", hash_type);
+        }
+    }
+
+    #[test]
+    fn test_dihedral_hash_finds_rotated_and_mirrored_copies() {
+        // Motiv uten symmetri: ulike gråtoner i 10x10 blokker
+        let blocks: Vec<u8> = (0..100u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8).collect();
+        let mut img = RgbaImage::new(200, 160);
+        for (x, y, pixel) in img.enumerate_pixels_mut() {
+            let v = blocks[(y * 10 / 160 * 10 + x * 10 / 200) as usize];
+            *pixel = Rgba([v, v, v, 255]);
+        }
+        let original = DynamicImage::ImageRgba8(img);
+        let other = create_gradient_image(200, 160, Rgba([0, 255, 0, 255]), Rgba([0, 0, 0, 255]));
+
+        for hash_type in [HashType::Perceptual, HashType::Difference, HashType::Average] {
+            let reference = compute_dihedral_hash(&original, hash_type, 8).unwrap();
+            assert_eq!(reference.best_match(&reference), (0, Transform::Identity));
+
+            for transform in Transform::ALL {
+                let copy = compute_dihedral_hash(&transform.apply(&original), hash_type, 8).unwrap();
+                let (dist, found) = copy.best_match(&reference);
+                assert!(dist <= 4, "{:?} {:?}: distanse {}", hash_type, transform, dist);
+                // Kopien må transformeres tilbake: rotasjoner inverteres, speilinger er sin egen invers
+                let back = found.apply(&transform.apply(&original));
+                assert_eq!(back.to_rgba8().as_raw(), original.to_rgba8().as_raw(), "{:?} {:?} -> {:?}", hash_type, transform, found);
+                assert!(dihedral_distance(&reference, &copy) <= 4);
+            }
+
+            let unrelated = compute_dihedral_hash(&other, hash_type, 8).unwrap();
+            assert!(dihedral_distance(&reference, &unrelated) > 10, "{:?}: annet motiv skal være langt unna", hash_type);
+        }
+    }
+
+    fn dihedral_distance(a: &DihedralHash, b: &DihedralHash) -> u32 {
+        a.best_match(b).0.min(b.best_match(a).0)
+    }
+
+    #[test]
+    fn test_dihedral_hash_hex_roundtrip() {
+        let img = create_gradient_image(64, 64, Rgba([255, 0, 0, 255]), Rgba([0, 0, 255, 255]));
+        let hash = compute_dihedral_hash(&img, HashType::Perceptual, 16).unwrap();
+        assert_eq!(DihedralHash::from_hex(&hash.to_hex()), Some(hash.clone()));
+        // Vanlig hash i cachen er ikke en gyldig dihedral hash
+        assert_eq!(DihedralHash::from_hex(&hash.identity().to_hex()), None);
+    }
+
+    #[test]
+    fn test_algorithm_id() {
+        assert_eq!(algorithm_id(HashType::Difference, 8), "dhash-8");
+        assert_eq!(algorithm_id(HashType::Perceptual, 16), "phash-16");
+        assert_ne!(algorithm_id(HashType::Average, 8), algorithm_id(HashType::Difference, 8));
+    }
+
+    #[test]
+    fn test_partial_hash_misses_middle_but_full_checks_catch_it() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = dir.path().join("a.raw");
+        let b = dir.path().join("b.raw");
+
+        // Like første og siste 4 KB, men forskjellig i midten
+        let data_a = vec![7u8; 64 * 1024];
+        let mut data_b = data_a.clone();
+        data_b[32 * 1024] = 8;
+        std::fs::write(&a, &data_a).unwrap();
+        std::fs::write(&b, &data_b).unwrap();
+
+        assert_eq!(compute_partial_hash(&a).unwrap(), compute_partial_hash(&b).unwrap());
+        assert_eq!(partial_hash_bytes(data_a.len() as u64), 8192);
+        assert_eq!(partial_hash_bytes(100), 100);
+        assert_ne!(compute_exact_hash(&a).unwrap(), compute_exact_hash(&b).unwrap());
+        assert!(!files_identical(&a, &b).unwrap());
+
+        let c = dir.path().join("c.raw");
+        std::fs::write(&c, &data_a).unwrap();
+        assert!(files_identical(&a, &c).unwrap());
+    }
+
+    #[test]
+    fn test_exact_hash_known_digest() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("abc.bin");
+        std::fs::write(&path, b"abc").unwrap();
+
+        let expected = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        assert_eq!(compute_exact_hash(&path).unwrap(), expected);
+
+        let empty = dir.path().join("empty.bin");
+        std::fs::write(&empty, b"").unwrap();
+        let expected_empty = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        assert_eq!(compute_exact_hash(&empty).unwrap(), expected_empty);
+    }
+
+    #[test]
+    fn test_exact_hash_mmap_matches_buffered() {
+        let dir = tempfile::tempdir().unwrap();
+
+        // Større enn bufferet, og ikke et helt antall blokker
+        let path = dir.path().join("large.bin");
+        let data: Vec<u8> = (0..HASH_BUFFER_SIZE * 3 + 123).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&path, &data).unwrap();
+        assert_eq!(compute_exact_hash_mmap(&path).unwrap(), compute_exact_hash(&path).unwrap());
+
+        let abc = dir.path().join("abc.bin");
+        std::fs::write(&abc, b"abc").unwrap();
+        assert_eq!(compute_exact_hash_mmap(&abc).unwrap(), compute_exact_hash(&abc).unwrap());
+
+        let empty = dir.path().join("empty.bin");
+        std::fs::write(&empty, b"").unwrap();
+        assert_eq!(compute_exact_hash_mmap(&empty).unwrap(), compute_exact_hash(&empty).unwrap());
+    }
+
     #[test]
     fn test_hash_is_deterministic() {
         let img = create_gradient_image(100, 100, Rgba([100, 150, 200, 255]), Rgba([50, 100, 150, 255]));
@@ -257,7 +809,7 @@ mod tests {
         let hash1 = compute_perceptual_hash(&img, HashType::Difference).unwrap();
         let hash2 = compute_perceptual_hash(&img, HashType::Difference).unwrap();
         
-        assert_eq!(hash1.to_base64(), hash2.to_base64(), "Hash skal være deterministisk");
+        assert_eq!(hash1.to_hex(), hash2.to_hex(), "Hash skal være deterministisk");
     }
 
     #[test]
@@ -315,4 +867,123 @@ mod tests {
         println!("Ensfargede bilder (rød vs blå) distanse: {}", distance);
         // Ikke assert på distanse - ensfargede bilder er edge case
     }
+
+    /// Tapsfri WebP av et RGBA-bilde
+    fn encode_webp(img: &RgbaImage) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        image::codecs::webp::WebPEncoder::new_lossless(&mut bytes)
+            .encode(img.as_raw(), img.width(), img.height(), image::ExtendedColorType::Rgba8)
+            .unwrap();
+        bytes
+    }
+
+    /// Animert WebP der hvert bilde er en ferdig kodet VP8L-strøm
+    fn encode_animated_webp(frames: &[RgbaImage]) -> Vec<u8> {
+        let (width, height) = frames[0].dimensions();
+        let u24 = |v: u32| v.to_le_bytes()[..3].to_vec();
+        let chunk = |fourcc: &[u8], data: &[u8]| {
+            let mut out = fourcc.to_vec();
+            out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            out.extend_from_slice(data);
+            if data.len() % 2 == 1 {
+                out.push(0);
+            }
+            out
+        };
+
+        let mut vp8x = vec![0x12, 0, 0, 0]; // animasjon + alfa
+        vp8x.extend(u24(width - 1));
+        vp8x.extend(u24(height - 1));
+        let mut body = b"WEBP".to_vec();
+        body.extend(chunk(b"VP8X", &vp8x));
+        body.extend(chunk(b"ANIM", &[0, 0, 0, 0, 0, 0]));
+        for frame in frames {
+            // Hopp over RIFF-headeren (12 bytes) - resten er VP8L-chunken
+            let vp8l = encode_webp(frame)[12..].to_vec();
+            let mut anmf = [u24(0), u24(0), u24(width - 1), u24(height - 1), u24(100)].concat();
+            anmf.push(0);
+            anmf.extend(vp8l);
+            body.extend(chunk(b"ANMF", &anmf));
+        }
+
+        let mut out = b"RIFF".to_vec();
+        out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        out.extend(body);
+        out
+    }
+
+    #[test]
+    fn test_load_webp_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bilde.webp");
+        let original = create_gradient_image(64, 48, Rgba([255, 0, 0, 255]), Rgba([0, 0, 255, 255])).to_rgba8();
+        std::fs::write(&path, encode_webp(&original)).unwrap();
+
+        let loaded = load_image(&path).unwrap();
+        assert_eq!(loaded.dimensions(), (64, 48));
+        // Tapsfri koding - pikslene skal være identiske
+        assert_eq!(loaded.to_rgba8().as_raw(), original.as_raw());
+    }
+
+    #[test]
+    fn test_load_animated_webp_uses_first_frame() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("animasjon.webp");
+        let red = create_solid_image(16, 16, Rgba([255, 0, 0, 255])).to_rgba8();
+        let blue = create_solid_image(16, 16, Rgba([0, 0, 255, 255])).to_rgba8();
+        std::fs::write(&path, encode_animated_webp(&[red, blue])).unwrap();
+
+        let loaded = load_image(&path).unwrap();
+        assert_eq!(loaded.dimensions(), (16, 16));
+        // Animasjonen settes sammen med alfablanding, så fargen kan avvike med en verdi
+        let pixel = loaded.get_pixel(8, 8);
+        assert!(pixel[0] > 250 && pixel[2] < 5, "Forventet rødt første bilde, fikk {:?}", pixel);
+    }
+
+    #[test]
+    fn test_exif_orientation_matches_rotated_export() {
+        use image::ImageEncoder;
+
+        let dir = tempfile::tempdir().unwrap();
+        // Motiv uten symmetri: mørk venstre halvdel og lys stripe øverst
+        let mut sensor = RgbaImage::new(120, 80);
+        for (x, y, pixel) in sensor.enumerate_pixels_mut() {
+            let v = if y < 20 { 250 } else if x < 60 { 20 } else { 140 };
+            *pixel = Rgba([v, v, v, 255]);
+        }
+        let sensor = DynamicImage::ImageRgba8(sensor).to_rgb8();
+
+        // Kamerafil: pikslene liggende, Orientation = 6 (roteres 90° med klokka ved visning)
+        let camera = dir.path().join("IMG_0001.jpg");
+        let mut encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(File::create(&camera).unwrap(), 95);
+        let mut exif = b"II*\0\x08\0\0\0\x01\0\x12\x01\x03\0\x01\0\0\0\x06\0".to_vec();
+        exif.extend_from_slice(&[0; 6]);
+        encoder.set_exif_metadata(exif).unwrap();
+        encoder.write_image(sensor.as_raw(), 120, 80, image::ExtendedColorType::Rgb8).unwrap();
+
+        // Eksport: pikslene er rotert og EXIF-taggen er borte
+        let export = dir.path().join("IMG_0001_eksport.jpg");
+        image::imageops::rotate90(&sensor).save(&export).unwrap();
+
+        let camera_img = load_image(&camera).unwrap();
+        let export_img = load_image(&export).unwrap();
+        assert_eq!(camera_img.dimensions(), (80, 120));
+        assert_eq!(export_img.dimensions(), (80, 120));
+
+        let a = compute_perceptual_hash(&camera_img, HashType::Difference).unwrap();
+        let b = compute_perceptual_hash(&export_img, HashType::Difference).unwrap();
+        assert!(compare_hashes(&a, &b) <= 2, "Rotert eksport skal gi samme dHash");
+    }
+
+    #[test]
+    fn test_load_tiff_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("skann.tiff");
+        let original = create_gradient_image(64, 48, Rgba([0, 255, 0, 255]), Rgba([0, 0, 0, 255]));
+        original.save(&path).unwrap();
+
+        let loaded = load_image(&path).unwrap();
+        assert_eq!(loaded.dimensions(), (64, 48));
+        assert_eq!(loaded.to_rgba8().as_raw(), original.to_rgba8().as_raw());
+    }
 }
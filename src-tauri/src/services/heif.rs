@@ -0,0 +1,112 @@
+//! Dekoding av HEIC/HEIF og AVIF
+//!
+//! `image`-craten har ingen dekoder for disse formatene, så de går gjennom libheif.
+//! libheif lenkes statisk, men dekoderne (libde265 for HEVC, dav1d for AV1) er delte
+//! biblioteker som må være installert der appen kjører - se docs/DEVELOPMENT.md.
+
+use image::{DynamicImage, ImageBuffer};
+use libheif_rs::{ColorSpace, HeifContext, LibHeif, RgbChroma};
+use std::path::Path;
+
+/// Endelser som dekodes med libheif i stedet for `image`
+pub const HEIF_EXTENSIONS: &[&str] = &["heic", "heif", "hif", "avif"];
+
+/// Sjekker om filen skal dekodes med libheif. `detected_format` er formatet fra
+/// filinnholdet (`signature::detect_format` eller `ImageInfo::detected_format`), så
+/// omdøpte iPhone-bilder (HEIC med `.jpg`) gjenkjennes uten at filen åpnes på nytt.
+pub fn is_heif(path: &Path, detected_format: Option<&str>) -> bool {
+    let by_extension = path
+        .extension()
+        .map(|ext| HEIF_EXTENSIONS.contains(&ext.to_string_lossy().to_lowercase().as_str()))
+        .unwrap_or(false);
+    by_extension || matches!(detected_format, Some("heic") | Some("avif"))
+}
+
+/// Dekoder hovedbildet i filen. Rotasjon og beskjæring fra HEIF-containeren
+/// (`irot`/`imir`/`clap`) er allerede brukt på resultatet.
+pub fn decode(path: &Path) -> Result<DynamicImage, Box<dyn std::error::Error + Send + Sync>> {
+    let ctx = HeifContext::read_from_file(&path.to_string_lossy())?;
+    let handle = ctx.primary_image_handle()?;
+    let has_alpha = handle.has_alpha_channel();
+    let chroma = if has_alpha { RgbChroma::Rgba } else { RgbChroma::Rgb };
+    let image = LibHeif::new().decode(&handle, ColorSpace::Rgb(chroma), None)?;
+
+    let planes = image.planes();
+    let plane = planes
+        .interleaved
+        .ok_or_else(|| format!("HEIF-bildet mangler RGB-data: {}", path.display()))?;
+
+    // Radene i libheif kan ha padding (stride > bredde * kanaler)
+    let channels = if has_alpha { 4 } else { 3 };
+    let row_len = plane.width as usize * channels;
+    let mut pixels = Vec::with_capacity(row_len * plane.height as usize);
+    for row in plane.data.chunks(plane.stride).take(plane.height as usize) {
+        pixels.extend_from_slice(&row[..row_len]);
+    }
+
+    let decoded = if has_alpha {
+        ImageBuffer::from_raw(plane.width, plane.height, pixels).map(DynamicImage::ImageRgba8)
+    } else {
+        ImageBuffer::from_raw(plane.width, plane.height, pixels).map(DynamicImage::ImageRgb8)
+    };
+    decoded.ok_or_else(|| format!("Ugyldig bildestørrelse i HEIF-filen: {}", path.display()).into())
+}
+
+/// Bildestørrelse fra headeren, uten å dekode bildet
+pub fn dimensions(path: &Path) -> Option<(u32, u32)> {
+    let ctx = HeifContext::read_from_file(&path.to_string_lossy()).ok()?;
+    let handle = ctx.primary_image_handle().ok()?;
+    Some((handle.width(), handle.height()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::signature;
+    use image::GenericImageView;
+    use std::fs;
+    use std::path::PathBuf;
+    use tempfile::tempdir;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name)
+    }
+
+    #[test]
+    fn test_decode_heic() {
+        let path = fixture("alpha.heif");
+        assert!(is_heif(&path, None));
+        assert_eq!(dimensions(&path), Some((256, 256)));
+
+        let img = decode(&path).unwrap();
+        assert_eq!(img.dimensions(), (256, 256));
+        assert!(matches!(img, DynamicImage::ImageRgba8(_)));
+    }
+
+    #[test]
+    fn test_decode_avif() {
+        let path = fixture("gradient.avif");
+        assert!(is_heif(&path, None));
+
+        let img = decode(&path).unwrap();
+        assert_eq!(img.dimensions(), (16, 12));
+        // Gradienten går fra mørkt til lyst mot høyre
+        let left = img.get_pixel(0, 6);
+        let right = img.get_pixel(15, 6);
+        assert!(right[0] > left[0]);
+    }
+
+    #[test]
+    fn test_renamed_heic_is_detected() {
+        let dir = tempdir().unwrap();
+        let renamed = dir.path().join("IMG_0001.jpg");
+        fs::copy(fixture("alpha.heif"), &renamed).unwrap();
+        assert!(!is_heif(&renamed, None));
+        assert!(is_heif(&renamed, signature::detect_format(&renamed)));
+        assert!(decode(&renamed).is_ok());
+
+        let jpeg = dir.path().join("ekte.jpg");
+        fs::write(&jpeg, [0xFF, 0xD8, 0xFF, 0xE0]).unwrap();
+        assert!(!is_heif(&jpeg, signature::detect_format(&jpeg)));
+    }
+}
@@ -0,0 +1,138 @@
+//! Register over langvarige operasjoner (skanning, duplikatsøk, sortering)
+//!
+//! Hver operasjon får en jobb-ID og et `CancelToken`. Avbrytelse er kooperativ:
+//! løkkene sjekker tokenet mellom hver fil og avslutter selv.
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Feilmelding som returneres når en operasjon avbrytes av brukeren
+pub const CANCELLED_MESSAGE: &str = "Operasjonen ble avbrutt";
+
+/// Delt flagg som sier om en jobb er bedt om å stoppe
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Aktive jobber, lagt i Tauri sin state via `manage`
+#[derive(Default)]
+pub struct JobRegistry {
+    jobs: Mutex<HashMap<String, CancelToken>>,
+    next_id: AtomicU64,
+}
+
+impl JobRegistry {
+    /// Registrerer en ny jobb. Frontend kan sende sin egen ID for å kunne avbryte den;
+    /// ellers genereres en. Jobben fjernes fra registeret når `JobHandle` droppes.
+    /// Feiler hvis en jobb med samme ID allerede kjører - ellers ville `cancel_job`
+    /// og avregistreringen truffet feil jobb.
+    pub fn start(&self, job_id: Option<String>) -> Result<JobHandle<'_>, String> {
+        let id = job_id.unwrap_or_else(|| {
+            format!("job-{}", self.next_id.fetch_add(1, Ordering::Relaxed) + 1)
+        });
+        let token = CancelToken::new();
+        match self.jobs.lock().unwrap().entry(id.clone()) {
+            Entry::Occupied(_) => return Err(format!("En jobb med ID {} kjører allerede", id)),
+            Entry::Vacant(entry) => entry.insert(token.clone()),
+        };
+
+        Ok(JobHandle {
+            registry: self,
+            id,
+            token,
+        })
+    }
+
+    /// Ber en jobb om å stoppe. Returnerer false hvis jobben ikke finnes (eller er ferdig).
+    pub fn cancel(&self, job_id: &str) -> bool {
+        match self.jobs.lock().unwrap().get(job_id) {
+            Some(token) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// IDer for jobber som kjører nå
+    pub fn active_jobs(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self.jobs.lock().unwrap().keys().cloned().collect();
+        ids.sort();
+        ids
+    }
+}
+
+/// En registrert jobb. Avregistreres automatisk når den går ut av scope.
+pub struct JobHandle<'a> {
+    registry: &'a JobRegistry,
+    pub id: String,
+    pub token: CancelToken,
+}
+
+impl Drop for JobHandle<'_> {
+    fn drop(&mut self) {
+        self.registry.jobs.lock().unwrap().remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancel_running_job() {
+        let registry = JobRegistry::default();
+        let job = registry.start(Some("scan-1".to_string())).unwrap();
+
+        assert!(!job.token.is_cancelled());
+        assert!(registry.cancel("scan-1"));
+        assert!(job.token.is_cancelled());
+    }
+
+    #[test]
+    fn test_job_removed_when_finished() {
+        let registry = JobRegistry::default();
+        {
+            let job = registry.start(None).unwrap();
+            assert_eq!(registry.active_jobs(), vec![job.id.clone()]);
+        }
+        assert!(registry.active_jobs().is_empty());
+        assert!(!registry.cancel("job-1"));
+    }
+
+    #[test]
+    fn test_generated_ids_are_unique() {
+        let registry = JobRegistry::default();
+        let a = registry.start(None).unwrap();
+        let b = registry.start(None).unwrap();
+        assert_ne!(a.id, b.id);
+    }
+
+    #[test]
+    fn test_duplicate_id_is_rejected() {
+        let registry = JobRegistry::default();
+        let first = registry.start(Some("scan".to_string())).unwrap();
+        assert!(registry.start(Some("scan".to_string())).is_err());
+
+        // Det avviste forsøket skal ikke ha fjernet eller byttet ut den første jobben
+        assert!(registry.cancel("scan"));
+        assert!(first.token.is_cancelled());
+        drop(first);
+        assert!(registry.start(Some("scan".to_string())).is_ok());
+    }
+}
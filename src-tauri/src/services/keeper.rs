@@ -0,0 +1,299 @@
+//! Automatisk valg av hvilket bilde som skal beholdes i en duplikatgruppe
+//!
+//! Reglene evalueres i rekkefølge: første regel som skiller kandidatene avgjør,
+//! og senere regler brukes bare for å bryte uavgjort. Er alle regler uavgjort,
+//! beholdes det første bildet i gruppen.
+
+use crate::services::{heif, media, metadata, sidecar, signature};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// En regel for rangering av medlemmer i en duplikatgruppe
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "rule")]
+pub enum KeepRule {
+    /// Flest piksler (bredde x høyde)
+    HighestResolution,
+    /// Største fil
+    LargestFile,
+    /// Har EXIF `DateTimeOriginal` (original fra kamera, ikke eksport)
+    HasExifDate,
+    /// Har sidecar-filer (.xmp, .aae, .json, .thm)
+    HasSidecars,
+    /// Ligger under en foretrukket mappe
+    PreferredFolder { prefix: String },
+    /// Eldste opprettelsesdato (EXIF, video-metadata eller filsystem)
+    OldestDate,
+    /// Ikke en kollisjonskopi (`bilde_1.jpg` når `bilde.jpg` finnes i gruppen)
+    NoCollisionSuffix,
+}
+
+/// Standard regelrekkefølge når frontend ikke sender egne regler
+pub fn default_rules() -> Vec<KeepRule> {
+    vec![
+        KeepRule::HighestResolution,
+        KeepRule::HasExifDate,
+        KeepRule::HasSidecars,
+        KeepRule::NoCollisionSuffix,
+        KeepRule::LargestFile,
+        KeepRule::OldestDate,
+    ]
+}
+
+/// Resultat for én gruppe: hvilket bilde som beholdes og hvorfor
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeepDecision {
+    pub keep: String,
+    /// Resten av gruppen, rangert fra best til dårligst - kan sendes rett til `delete_images`
+    pub remove: Vec<String>,
+    /// Reglene som avgjorde valget, i rekkefølge
+    pub reasons: Vec<String>,
+}
+
+/// Egenskaper ved et gruppemedlem som reglene trenger
+struct MemberFacts {
+    path: String,
+    size_bytes: u64,
+    pixels: u64,
+    has_exif_date: bool,
+    sidecar_count: usize,
+    /// Sekunder siden epoch (lavere = eldre)
+    created: Option<i64>,
+    collision_copy: bool,
+}
+
+impl MemberFacts {
+    fn collect(path: &str, group: &[String], rules: &[KeepRule]) -> Self {
+        let p = Path::new(path);
+        let needs = |rule: &KeepRule| rules.contains(rule);
+
+        let pixels = if needs(&KeepRule::HighestResolution) {
+            // HEIC og RAW kan ikke leses av `image` og ville ellers alltid tapt mot JPEG-en
+            let dimensions = if heif::is_heif(p, signature::detect_format(p)) {
+                heif::dimensions(p)
+            } else if media::is_raw(p) {
+                metadata::read_raw_dimensions(p)
+            } else {
+                image::image_dimensions(p).ok()
+            };
+            dimensions.map(|(w, h)| w as u64 * h as u64).unwrap_or(0)
+        } else {
+            0
+        };
+
+        Self {
+            path: path.to_string(),
+            size_bytes: std::fs::metadata(p).map(|m| m.len()).unwrap_or(0),
+            pixels,
+            has_exif_date: needs(&KeepRule::HasExifDate)
+                && metadata::read_exif_date_original(p).is_some(),
+            sidecar_count: if needs(&KeepRule::HasSidecars) {
+                sidecar::find_sidecars(p).len()
+            } else {
+                0
+            },
+            created: if needs(&KeepRule::OldestDate) {
+                metadata::read_creation_date(p).map(|d| d.timestamp())
+            } else {
+                None
+            },
+            collision_copy: is_collision_copy(p, group),
+        }
+    }
+
+    /// Poengsum for en regel - høyere er bedre
+    fn score(&self, rule: &KeepRule) -> i64 {
+        match rule {
+            KeepRule::HighestResolution => self.pixels as i64,
+            KeepRule::LargestFile => self.size_bytes as i64,
+            KeepRule::HasExifDate => self.has_exif_date as i64,
+            KeepRule::HasSidecars => (self.sidecar_count > 0) as i64,
+            KeepRule::PreferredFolder { prefix } => {
+                Path::new(&self.path).starts_with(prefix) as i64
+            }
+            KeepRule::OldestDate => self.created.map(|t| -t).unwrap_or(i64::MIN),
+            KeepRule::NoCollisionSuffix => !self.collision_copy as i64,
+        }
+    }
+
+    fn describe(&self, rule: &KeepRule) -> String {
+        match rule {
+            KeepRule::HighestResolution => format!("Høyest oppløsning ({:.1} MP)", self.pixels as f64 / 1_000_000.0),
+            KeepRule::LargestFile => format!("Største fil ({} bytes)", self.size_bytes),
+            KeepRule::HasExifDate => "Har EXIF DateTimeOriginal".to_string(),
+            KeepRule::HasSidecars => format!("Har {} sidecar-fil(er)", self.sidecar_count),
+            KeepRule::PreferredFolder { prefix } => format!("Ligger i foretrukket mappe ({})", prefix),
+            KeepRule::OldestDate => "Eldste dato".to_string(),
+            KeepRule::NoCollisionSuffix => "Ikke en kollisjonskopi (_1, _2 ...)".to_string(),
+        }
+    }
+}
+
+/// Sjekker om filen heter `navn_N.ext` og `navn.ext` også finnes i gruppen
+/// (mønsteret sorteringen bruker ved navnekollisjoner).
+fn is_collision_copy(path: &Path, group: &[String]) -> bool {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = path.extension().map(|e| e.to_string_lossy().to_lowercase());
+
+    let base = match stem.rsplit_once('_') {
+        Some((base, counter)) if !counter.is_empty() && counter.chars().all(|c| c.is_ascii_digit()) => base,
+        _ => return false,
+    };
+
+    group.iter().any(|other| {
+        let other = Path::new(other);
+        other.file_stem().map(|s| s.to_string_lossy() == base).unwrap_or(false)
+            && other.extension().map(|e| e.to_string_lossy().to_lowercase()) == ext
+    })
+}
+
+/// Velger hvilket medlem av gruppen som skal beholdes
+pub fn select_keeper(group: &[String], rules: &[KeepRule]) -> Option<KeepDecision> {
+    if group.is_empty() {
+        return None;
+    }
+
+    let facts: Vec<MemberFacts> = group
+        .iter()
+        .map(|path| MemberFacts::collect(path, group, rules))
+        .collect();
+    let scores: Vec<Vec<i64>> = facts
+        .iter()
+        .map(|f| rules.iter().map(|rule| f.score(rule)).collect())
+        .collect();
+
+    // Ranger etter poengsum per regel (leksikografisk), stabilt så gruppens rekkefølge bryter uavgjort
+    let mut ranking: Vec<usize> = (0..group.len()).collect();
+    ranking.sort_by(|&a, &b| scores[b].cmp(&scores[a]));
+    let keeper = ranking[0];
+
+    // En regel er en begrunnelse hvis den slo ut minst én kandidat som var uavgjort så langt
+    let mut reasons = Vec::new();
+    let mut contenders: Vec<usize> = (0..group.len()).collect();
+    for (r, rule) in rules.iter().enumerate() {
+        let before = contenders.len();
+        contenders.retain(|&i| scores[i][r] == scores[keeper][r]);
+        if contenders.len() < before {
+            reasons.push(facts[keeper].describe(rule));
+        }
+        if contenders.len() == 1 {
+            break;
+        }
+    }
+
+    Some(KeepDecision {
+        keep: group[keeper].clone(),
+        remove: ranking[1..].iter().map(|&i| group[i].clone()).collect(),
+        reasons,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{self, File};
+    use tempfile::tempdir;
+
+    fn paths(items: &[&Path]) -> Vec<String> {
+        items.iter().map(|p| p.to_string_lossy().to_string()).collect()
+    }
+
+    #[test]
+    fn test_largest_file_wins() {
+        let dir = tempdir().unwrap();
+        let small = dir.path().join("a.jpg");
+        let large = dir.path().join("b.jpg");
+        fs::write(&small, b"123").unwrap();
+        fs::write(&large, b"123456").unwrap();
+
+        let decision = select_keeper(&paths(&[&small, &large]), &[KeepRule::LargestFile]).unwrap();
+        assert_eq!(decision.keep, large.to_string_lossy());
+        assert_eq!(decision.remove, vec![small.to_string_lossy().to_string()]);
+        assert_eq!(decision.reasons.len(), 1);
+    }
+
+    #[test]
+    fn test_highest_resolution_wins() {
+        let dir = tempdir().unwrap();
+        let small = dir.path().join("small.png");
+        let large = dir.path().join("large.png");
+        image::DynamicImage::new_rgb8(10, 10).save(&small).unwrap();
+        image::DynamicImage::new_rgb8(40, 30).save(&large).unwrap();
+
+        let decision = select_keeper(&paths(&[&small, &large]), &[KeepRule::HighestResolution]).unwrap();
+        assert_eq!(decision.keep, large.to_string_lossy());
+    }
+
+    #[test]
+    fn test_raw_uses_exif_dimensions() {
+        let dir = tempdir().unwrap();
+        let jpeg = dir.path().join("IMG_0001.jpg");
+        let raw = dir.path().join("IMG_0001.nef");
+        image::DynamicImage::new_rgb8(64, 48).save(&jpeg).unwrap();
+
+        // Minimal TIFF med ImageWidth = 6000 og ImageLength = 4000 i IFD0
+        let mut tiff = b"II*\0".to_vec();
+        tiff.extend(8u32.to_le_bytes());
+        tiff.extend(2u16.to_le_bytes());
+        for (tag, value) in [(0x0100u16, 6000u32), (0x0101, 4000)] {
+            tiff.extend(tag.to_le_bytes());
+            tiff.extend(4u16.to_le_bytes());
+            tiff.extend(1u32.to_le_bytes());
+            tiff.extend(value.to_le_bytes());
+        }
+        tiff.extend(0u32.to_le_bytes());
+        fs::write(&raw, tiff).unwrap();
+
+        let decision = select_keeper(&paths(&[&jpeg, &raw]), &[KeepRule::HighestResolution]).unwrap();
+        assert_eq!(decision.keep, raw.to_string_lossy());
+    }
+
+    #[test]
+    fn test_collision_suffix_and_sidecars() {
+        let dir = tempdir().unwrap();
+        let original = dir.path().join("photo.jpg");
+        let copy = dir.path().join("photo_1.jpg");
+        File::create(&original).unwrap();
+        File::create(&copy).unwrap();
+
+        let group = paths(&[&copy, &original]);
+        let decision = select_keeper(&group, &[KeepRule::NoCollisionSuffix]).unwrap();
+        assert_eq!(decision.keep, original.to_string_lossy());
+
+        // Sidecar på kopien veier tyngre når den regelen kommer først
+        File::create(dir.path().join("photo_1.xmp")).unwrap();
+        let decision = select_keeper(&group, &[KeepRule::HasSidecars, KeepRule::NoCollisionSuffix]).unwrap();
+        assert_eq!(decision.keep, copy.to_string_lossy());
+        assert_eq!(decision.reasons.len(), 1);
+    }
+
+    #[test]
+    fn test_collision_suffix_requires_base_name() {
+        // IMG_1234.jpg er ikke en kollisjonskopi når IMG.jpg ikke finnes
+        let group = vec!["/a/IMG_1234.jpg".to_string(), "/b/DSC_0001.jpg".to_string()];
+        assert!(!is_collision_copy(Path::new("/a/IMG_1234.jpg"), &group));
+    }
+
+    #[test]
+    fn test_preferred_folder_and_tie_break() {
+        let dir = tempdir().unwrap();
+        let archive = dir.path().join("arkiv");
+        let inbox = dir.path().join("innboks");
+        fs::create_dir(&archive).unwrap();
+        fs::create_dir(&inbox).unwrap();
+        let a = inbox.join("x.jpg");
+        let b = archive.join("x.jpg");
+        File::create(&a).unwrap();
+        File::create(&b).unwrap();
+
+        let rule = KeepRule::PreferredFolder { prefix: archive.to_string_lossy().to_string() };
+        let decision = select_keeper(&paths(&[&a, &b]), &[rule]).unwrap();
+        assert_eq!(decision.keep, b.to_string_lossy());
+
+        // Ingen regel skiller - første i gruppen beholdes, uten begrunnelse
+        let decision = select_keeper(&paths(&[&a, &b]), &[KeepRule::LargestFile]).unwrap();
+        assert_eq!(decision.keep, a.to_string_lossy());
+        assert!(decision.reasons.is_empty());
+    }
+}
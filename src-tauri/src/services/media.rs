@@ -0,0 +1,246 @@
+//! Register over medietyper
+//!
+//! Eneste sted som vet hvilke filendelser som er bilder og videoer. Skanner,
+//! thumbnails og hashing slår opp her i stedet for å ha egne lister.
+
+use serde::Serialize;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Stillbilder som kan dekodes (eller forsøkes dekodet) til thumbnails og hasher
+pub const IMAGE_EXTENSIONS: &[&str] = &[
+    "jpg", "jpeg", "png", "gif", "bmp", "webp", "tiff", "tif", "ico", "heic", "heif",
+    "avif",
+];
+
+/// Kamera-RAW - thumbnails og hasher lages fra den innebygde JPEG-forhåndsvisningen
+pub const RAW_EXTENSIONS: &[&str] = &["cr2", "cr3", "nef", "arw", "dng", "raf", "orf"];
+
+/// Videoer - thumbnails og fingeravtrykk (`video`) lages med ffmpeg
+pub const VIDEO_EXTENSIONS: &[&str] = &["mp4", "mov", "avi", "mkv", "webm", "wmv", "m4v"];
+
+/// Endelser på stillbildet i et Live Photo (videoen har samme navn med .mov/.mp4)
+const LIVE_PHOTO_STILL_EXTENSIONS: &[&str] = &["heic", "heif", "jpg", "jpeg"];
+
+/// Bytes som leses for å se etter animasjon i GIF/PNG/WebP
+const ANIMATION_SNIFF_LEN: usize = 4096;
+
+/// Hva slags medium en fil er
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MediaKind {
+    #[default]
+    Image,
+    /// GIF, APNG eller WebP med flere bilder
+    AnimatedImage,
+    /// Kamera-RAW
+    Raw,
+    Video,
+    /// Videodelen av et Live Photo (IMG_0001.MOV ved siden av IMG_0001.HEIC)
+    LivePhotoVideo,
+}
+
+impl MediaKind {
+    pub fn is_video(self) -> bool {
+        matches!(self, MediaKind::Video | MediaKind::LivePhotoVideo)
+    }
+}
+
+/// Medietype ut fra filendelsen alene (uten å lese filen)
+pub fn kind_for_extension(extension: &str) -> Option<MediaKind> {
+    let ext = extension.to_lowercase();
+    if IMAGE_EXTENSIONS.contains(&ext.as_str()) {
+        Some(MediaKind::Image)
+    } else if RAW_EXTENSIONS.contains(&ext.as_str()) {
+        Some(MediaKind::Raw)
+    } else if VIDEO_EXTENSIONS.contains(&ext.as_str()) {
+        Some(MediaKind::Video)
+    } else {
+        None
+    }
+}
+
+pub fn is_video_extension(extension: &str) -> bool {
+    kind_for_extension(extension) == Some(MediaKind::Video)
+}
+
+/// Sjekker om stien er en video ut fra endelsen
+pub fn is_video(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| is_video_extension(&ext.to_string_lossy()))
+        .unwrap_or(false)
+}
+
+/// Sjekker om stien er en kamera-RAW ut fra endelsen
+pub fn is_raw(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| kind_for_extension(&ext.to_string_lossy()) == Some(MediaKind::Raw))
+        .unwrap_or(false)
+}
+
+/// Finner den andre halvdelen av et RAW+JPEG-par (IMG_0001.CR2 <-> IMG_0001.JPG).
+/// Kameraet lagrer begge med samme navn, og de behandles som ett bilde ved sortering og sletting.
+pub fn raw_jpeg_partner(path: &Path) -> Option<PathBuf> {
+    let ext = path.extension()?.to_string_lossy().to_lowercase();
+    let candidates: &[&str] = if RAW_EXTENSIONS.contains(&ext.as_str()) {
+        &["jpg", "jpeg"]
+    } else if ext == "jpg" || ext == "jpeg" {
+        RAW_EXTENSIONS
+    } else {
+        return None;
+    };
+
+    // Navnet slås opp i mappelisten, ikke med `exists()`: på filsystemer uten skille
+    // mellom store og små bokstaver ville IMG_0001.jpg "finnes" selv om filen heter
+    // IMG_0001.JPG, og stien ville ikke matche den skanneren fant.
+    let stem = path.file_stem()?.to_string_lossy().to_string();
+    let parent = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let names: Vec<String> = std::fs::read_dir(parent)
+        .ok()?
+        .filter_map(Result::ok)
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .collect();
+
+    candidates.iter().find_map(|candidate| {
+        let wanted = format!("{}.{}", stem, candidate);
+        names
+            .iter()
+            .find(|name| name.eq_ignore_ascii_case(&wanted))
+            .map(|name| path.with_file_name(name))
+    })
+}
+
+/// Klassifiserer en fil. Live Photo-videoer gjenkjennes på stillbildet ved siden av.
+/// Med `read_header` leses starten av GIF/PNG/WebP for å finne animerte bilder.
+pub fn classify(path: &Path, extension: &str, read_header: bool) -> Option<MediaKind> {
+    classify_with_siblings(path, extension, read_header, |name| path.with_file_name(name).exists())
+}
+
+/// Som `classify`, men spør `sibling_exists` om et filnavn finnes i samme mappe i stedet
+/// for å gå til filsystemet. Skanneren har allerede lest mappen og slipper ekstra oppslag.
+pub fn classify_with_siblings(
+    path: &Path,
+    extension: &str,
+    read_header: bool,
+    sibling_exists: impl Fn(&str) -> bool,
+) -> Option<MediaKind> {
+    match kind_for_extension(extension)? {
+        MediaKind::Video if has_live_photo_still(path, sibling_exists) => Some(MediaKind::LivePhotoVideo),
+        MediaKind::Image if read_header && is_animated(path) => Some(MediaKind::AnimatedImage),
+        kind => Some(kind),
+    }
+}
+
+fn has_live_photo_still(video: &Path, sibling_exists: impl Fn(&str) -> bool) -> bool {
+    let ext = video.extension().unwrap_or_default().to_string_lossy().to_lowercase();
+    if ext != "mov" && ext != "mp4" {
+        return false;
+    }
+    let stem = video.file_stem().unwrap_or_default().to_string_lossy();
+    LIVE_PHOTO_STILL_EXTENSIONS.iter().any(|still| {
+        sibling_exists(&format!("{}.{}", stem, still)) || sibling_exists(&format!("{}.{}", stem, still.to_uppercase()))
+    })
+}
+
+/// Ser etter animasjon i filheaderen: NETSCAPE-loop i GIF, `acTL` i APNG og
+/// animasjonsflagget i WebP sin VP8X-header.
+pub fn is_animated(path: &Path) -> bool {
+    let mut buf = Vec::with_capacity(ANIMATION_SNIFF_LEN);
+    let read = File::open(path).and_then(|f| f.take(ANIMATION_SNIFF_LEN as u64).read_to_end(&mut buf));
+    if read.is_err() {
+        return false;
+    }
+
+    if buf.starts_with(b"GIF8") {
+        return contains(&buf, b"NETSCAPE2.0");
+    }
+    if buf.starts_with(b"\x89PNG") {
+        // acTL må komme før første IDAT
+        let idat = find(&buf, b"IDAT").unwrap_or(buf.len());
+        return find(&buf[..idat], b"acTL").is_some();
+    }
+    if buf.len() >= 21 && buf.starts_with(b"RIFF") && &buf[8..16] == b"WEBPVP8X" {
+        return buf[20] & 0x02 != 0;
+    }
+    false
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    find(haystack, needle).is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_kind_for_extension() {
+        assert_eq!(kind_for_extension("JPG"), Some(MediaKind::Image));
+        assert_eq!(kind_for_extension("mov"), Some(MediaKind::Video));
+        assert_eq!(kind_for_extension("txt"), None);
+        assert_eq!(kind_for_extension("CR3"), Some(MediaKind::Raw));
+        assert!(is_raw(Path::new("/a/DSC_0001.NEF")));
+        assert!(is_video(Path::new("/a/klipp.MP4")));
+        assert!(!is_video(Path::new("/a/bilde.jpg")));
+    }
+
+    #[test]
+    fn test_live_photo_video() {
+        let dir = tempdir().unwrap();
+        let still = dir.path().join("IMG_0001.HEIC");
+        let video = dir.path().join("IMG_0001.mov");
+        let lone = dir.path().join("IMG_0002.mov");
+        for path in [&still, &video, &lone] {
+            fs::write(path, b"").unwrap();
+        }
+
+        assert_eq!(classify(&video, "mov", false), Some(MediaKind::LivePhotoVideo));
+        assert_eq!(classify(&lone, "mov", false), Some(MediaKind::Video));
+        assert_eq!(classify(&still, "heic", false), Some(MediaKind::Image));
+    }
+
+    #[test]
+    fn test_raw_jpeg_partner() {
+        let dir = tempdir().unwrap();
+        let raw = dir.path().join("IMG_0001.CR2");
+        let jpeg = dir.path().join("IMG_0001.JPG");
+        let lone = dir.path().join("IMG_0002.jpg");
+        for path in [&raw, &jpeg, &lone] {
+            fs::write(path, b"").unwrap();
+        }
+
+        // Den faktiske stien, med filens egne store/små bokstaver
+        assert_eq!(raw_jpeg_partner(&raw), Some(jpeg.clone()));
+        assert_eq!(raw_jpeg_partner(&jpeg), Some(raw.clone()));
+        assert_eq!(raw_jpeg_partner(&lone), None);
+    }
+
+    #[test]
+    fn test_animated_images() {
+        let dir = tempdir().unwrap();
+        let animated_gif = dir.path().join("a.gif");
+        fs::write(&animated_gif, b"GIF89a\x01\x00\x01\x00\x00\x00\x00!\xff\x0bNETSCAPE2.0\x03\x01\x00\x00\x00").unwrap();
+        let still_gif = dir.path().join("b.gif");
+        fs::write(&still_gif, b"GIF89a\x01\x00\x01\x00\x00\x00\x00").unwrap();
+        let apng = dir.path().join("c.png");
+        fs::write(&apng, b"\x89PNG\r\n\x1a\n....IHDR....acTL....IDAT").unwrap();
+        let png = dir.path().join("d.png");
+        fs::write(&png, b"\x89PNG\r\n\x1a\n....IHDR....IDAT....acTL").unwrap();
+        let webp = dir.path().join("e.webp");
+        fs::write(&webp, b"RIFF\0\0\0\0WEBPVP8X\x0a\0\0\0\x02\0\0\0").unwrap();
+
+        assert_eq!(classify(&animated_gif, "gif", true), Some(MediaKind::AnimatedImage));
+        assert_eq!(classify(&animated_gif, "gif", false), Some(MediaKind::Image));
+        assert!(!is_animated(&still_gif));
+        assert!(is_animated(&apng));
+        assert!(!is_animated(&png));
+        assert!(is_animated(&webp));
+    }
+}
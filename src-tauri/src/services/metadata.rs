@@ -1,6 +1,11 @@
-//! Tjeneste for å lese metadata fra bilder (EXIF)
+//! Tjeneste for å lese metadata fra bilder (EXIF) og videoer (ffprobe)
 
-use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use crate::services::tools::{self, Tool};
+use crate::services::{bmff, media, raw};
+use chrono::{DateTime, Datelike, FixedOffset, Local, NaiveDateTime, TimeZone, Utc};
+use image::metadata::Orientation;
+use serde::Serialize;
+use serde_json::Value;
 use std::fs::File;
 use std::io::BufReader;
 use std::path::Path;
@@ -15,13 +20,13 @@ pub fn read_creation_date(path: &Path) -> Option<DateTime<Local>> {
 /// Leser opprettelsesdato med konfigurerbar fallback
 pub fn read_creation_date_with_fallback(path: &Path, use_fallback: bool) -> Option<DateTime<Local>> {
     // 1. Prøv å lese EXIF (Bilder)
-    if let Some(date) = read_exif_date(path) {
-        return Some(Local.from_local_datetime(&date).unwrap());
+    if let Some(date) = read_exif_date(path).and_then(|date| local_date(&Local, &date)) {
+        return Some(date);
     }
 
     // 2. Prøv å lese Videometadata (FFprobe)
-    if let Some(date) = read_video_date(path) {
-        return Some(Local.from_local_datetime(&date).unwrap());
+    if let Some(date) = read_video_date(path).and_then(|date| local_date(&Local, &date)) {
+        return Some(date);
     }
     
     if !use_fallback {
@@ -32,75 +37,317 @@ pub fn read_creation_date_with_fallback(path: &Path, use_fallback: bool) -> Opti
     read_file_mtime(path)
 }
 
-/// Leser opprettelsesdato fra video ved hjelp av FFprobe
+/// Tolker en dato uten tidssone som lokal tid. Når klokken stilles tilbake finnes tiden
+/// to ganger, og den første brukes. Tider som hoppes over når klokken stilles frem,
+/// finnes ikke og gir None.
+fn local_date<Tz: TimeZone>(tz: &Tz, date: &NaiveDateTime) -> Option<DateTime<Tz>> {
+    tz.from_local_datetime(date).earliest()
+}
+
+/// Metadata for visning i UI
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaMetadata {
+    /// Opptaksdato slik sorteringen ser den (uten fallback til filens endringsdato)
+    pub taken: Option<NaiveDateTime>,
+    /// Bare satt for videoer. MP4/MOV får varighet og opptakstid også uten ffprobe;
+    /// andre containere (AVI, MKV, WMV) krever ffprobe.
+    pub video: Option<VideoMetadata>,
+}
+
+/// Leser metadata for UI. Videoer leses én gang (ffprobe, eller `bmff` for MP4/MOV),
+/// og datoen hentes derfra.
+pub fn read_media_metadata(path: &Path) -> MediaMetadata {
+    let video = if media::is_video(path) { read_video_metadata(path).ok() } else { None };
+    let taken = match &video {
+        Some(video) => video.recorded_at(),
+        None => read_creation_date_with_fallback(path, false).map(|date| date.naive_local()),
+    };
+    MediaMetadata { taken, video }
+}
+
+/// Det ffprobe vet om en video. Alle felt er valgfrie - hva som finnes avhenger av kameraet.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VideoMetadata {
+    pub duration_ms: Option<u64>,
+    /// Lagret oppløsning, før `rotation` er brukt
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// Kodeknavn fra ffprobe, f.eks. "hevc" eller "h264"
+    pub codec: Option<String>,
+    /// Bilder per sekund
+    pub frame_rate: Option<f64>,
+    /// Grader med klokka videoen skal roteres ved avspilling (0, 90, 180 eller 270)
+    pub rotation: Option<u32>,
+    pub location: Option<GpsLocation>,
+    pub make: Option<String>,
+    pub model: Option<String>,
+    /// QuickTime `creationdate`: opptakstidspunktet med tidssonen der videoen ble tatt
+    pub creation_date: Option<DateTime<FixedOffset>>,
+    /// Containerens `creation_time`, alltid i UTC
+    pub creation_time: Option<DateTime<Utc>>,
+}
+
+impl VideoMetadata {
+    /// Klokkeslettet der videoen ble tatt. `creation_date` har tidssonen fra opptaket;
+    /// `creation_time` er UTC og regnes om til maskinens tidssone.
+    pub fn recorded_at(&self) -> Option<NaiveDateTime> {
+        self.creation_date
+            .map(|date| date.naive_local())
+            .or_else(|| self.creation_time.map(|time| time.with_timezone(&Local).naive_local()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GpsLocation {
+    pub latitude: f64,
+    pub longitude: f64,
+    /// Meter over havet
+    pub altitude: Option<f64>,
+}
+
+/// Leser videometadata med ffprobe. Uten ffprobe (eller hvis den feiler) leses
+/// varighet og opptakstid direkte fra MP4/MOV med `bmff`.
+pub fn read_video_metadata(path: &Path) -> Result<VideoMetadata, Box<dyn std::error::Error + Send + Sync>> {
+    let probed = tools::run(Tool::Ffprobe, |command| {
+        command
+            .args([
+                "-v", "error",
+                "-print_format", "json",
+                "-show_format",
+                "-show_streams",
+            ])
+            .arg(path);
+    })
+    .and_then(|stdout| {
+        parse_video_metadata(&stdout).ok_or_else(|| format!("Kunne ikke tolke ffprobe-svaret for {}", path.display()).into())
+    });
+    probed.or_else(|e| bmff::read_metadata(path).ok_or(e))
+}
+
+/// Leser opprettelsesdato fra video. MP4/MOV leses direkte; andre containere
+/// (AVI, MKV, WMV) krever FFprobe og gir None uten - `tools::status` viser det.
 fn read_video_date(path: &Path) -> Option<NaiveDateTime> {
-    use std::process::Command;
-
-    // TODO: For production bundled sidecars, we need to resolve the correct path.
-    // Ideally we'd use tauri's path resolver, but we are deep in a service module without AppHandle.
-    // For now, we attempt to run "ffprobe" (assuming it's in PATH or CWD).
-    // If that fails, we could try to look in relative paths, but platform-specific suffix naming makes it hard here.
-    // The "Right Way" is to pass the sidecar path from the main thread/command handler down to here.
-    // But let's stick to "ffprobe" command for now, as the user environment usually has it or we can't easily guess.
-    // BUT: The user specifically asked to BUNDLE it.
-    // Since we bundled it, "ffprobe" command WONT work unless we add the bin folder to PATH before running.
-    // We can try to guess the path relative to CWD based on known target triple?
-    
-    // Attempt 1: "ffprobe" in PATH
-    let output = Command::new("ffprobe")
-        .args(&[
-            "-v", "quiet",
-            "-print_format", "json",
-            "-show_entries", "format_tags=creation_time",
-            path.to_str()?,
-        ])
-        .output()
-        .ok();
-
-    if let Some(out) = output {
-        if out.status.success() {
-             return parse_ffmpeg_json(&out.stdout);
-        }
+    if !media::is_video(path) {
+        return None;
     }
-    
-    // Attempt 2 (Desperation): Look for local sidecar binary in expected dev location
-    // This is hacky but helps in dev mode if they downloaded binaries.
-    // In production, simpler to rely on frontend calling it, OR properly passing path.
-    // For current scope: just return None if not found.
-    // The `shell` plugin allows frontend to call specific sidecars easily.
-    // Maybe we should extract metadata in Frontend?? No, sorting happens in Backend.
-    
-    None
+    bmff::read_metadata(path)
+        .and_then(|meta| meta.recorded_at())
+        .or_else(|| read_video_metadata(path).ok()?.recorded_at())
 }
 
-fn parse_ffmpeg_json(output: &[u8]) -> Option<NaiveDateTime> {
-    let json_str = std::str::from_utf8(output).ok()?;
-    let v: serde_json::Value = serde_json::from_str(json_str).ok()?;
-    
-    let date_str = v["format"]["tags"]["creation_time"].as_str()?;
-    
-    // Datoformat fra FFmpeg er ofte ISO 8601: "2023-12-29T00:33:00.000000Z"
-    let clean_date = date_str.split('.').next().unwrap_or(date_str);
-    let clean_date = clean_date.trim_end_matches('Z');
-    
-    NaiveDateTime::parse_from_str(clean_date, "%Y-%m-%dT%H:%M:%S").ok()
+fn parse_video_metadata(output: &[u8]) -> Option<VideoMetadata> {
+    let v: Value = serde_json::from_slice(output).ok()?;
+    let format = &v["format"];
+    let tags = &format["tags"];
+    // Omslagsbilder ligger også som videostrømmer, men med `attached_pic`
+    let stream = v["streams"]
+        .as_array()
+        .and_then(|streams| {
+            streams.iter().find(|s| s["codec_type"] == "video" && s["disposition"]["attached_pic"] != 1)
+        })
+        .unwrap_or(&Value::Null);
+
+    let duration = number(&format["duration"]).or_else(|| number(&stream["duration"]));
+    let text = |key: &str| tag(tags, key).map(str::to_string);
+
+    Some(VideoMetadata {
+        duration_ms: duration.filter(|d| *d > 0.0).map(|d| (d * 1000.0).round() as u64),
+        width: stream["width"].as_u64().and_then(|w| u32::try_from(w).ok()),
+        height: stream["height"].as_u64().and_then(|h| u32::try_from(h).ok()),
+        codec: stream["codec_name"].as_str().map(str::to_string),
+        frame_rate: parse_frame_rate(&stream["avg_frame_rate"]).or_else(|| parse_frame_rate(&stream["r_frame_rate"])),
+        rotation: parse_rotation(stream),
+        location: tag(tags, "com.apple.quicktime.location.ISO6709")
+            .or_else(|| tag(tags, "location"))
+            .and_then(parse_iso6709),
+        make: text("com.apple.quicktime.make").or_else(|| text("com.android.manufacturer")).or_else(|| text("make")),
+        model: text("com.apple.quicktime.model").or_else(|| text("com.android.model")).or_else(|| text("model")),
+        creation_date: tag(tags, "com.apple.quicktime.creationdate").and_then(parse_quicktime_date),
+        // Datoformat fra FFmpeg er ofte ISO 8601: "2023-12-29T00:33:00.000000Z"
+        creation_time: tag(tags, "creation_time")
+            .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+            .map(|time| time.with_timezone(&Utc))
+            .filter(|time| is_plausible_year(time.year())),
+    })
+}
+
+/// Kameraer uten klokke skriver null, som blir 1904 (QuickTime) eller 1970 (Unix)
+pub fn is_plausible_year(year: i32) -> bool {
+    year > 1970
+}
+
+/// Opptakstid med tidssone slik QuickTime lagrer den: "2023-06-14T18:22:31+0200"
+pub fn parse_quicktime_date(value: &str) -> Option<DateTime<FixedOffset>> {
+    DateTime::parse_from_str(value.trim(), "%Y-%m-%dT%H:%M:%S%.f%z")
+        .ok()
+        .filter(|date| is_plausible_year(date.year()))
+}
+
+/// Tagger skrives med ulik store/små bokstaver av ulike kameraer
+fn tag<'a>(tags: &'a Value, key: &str) -> Option<&'a str> {
+    tags.as_object()?
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(key))
+        .and_then(|(_, value)| value.as_str())
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+}
+
+/// ffprobe skriver tall som strenger ("12.345000")
+fn number(value: &Value) -> Option<f64> {
+    value.as_f64().or_else(|| value.as_str()?.parse().ok()).filter(|n: &f64| n.is_finite())
+}
+
+/// "30000/1001" -> 29.97. "0/0" betyr ukjent.
+fn parse_frame_rate(value: &Value) -> Option<f64> {
+    let (num, den) = value.as_str()?.split_once('/')?;
+    let (num, den): (f64, f64) = (num.parse().ok()?, den.parse().ok()?);
+    (num > 0.0 && den > 0.0).then(|| num / den)
+}
+
+/// Nyere ffprobe har rotasjonen i displaymatrisen (grader mot klokka), eldre i `rotate`-taggen
+/// (grader med klokka).
+fn parse_rotation(stream: &Value) -> Option<u32> {
+    let from_matrix = stream["side_data_list"]
+        .as_array()
+        .and_then(|list| list.iter().find_map(|data| number(&data["rotation"])))
+        .map(|degrees| -degrees);
+    let degrees = from_matrix.or_else(|| number(&stream["tags"]["rotate"]))?;
+    Some((degrees.round() as i64).rem_euclid(360) as u32)
+}
+
+/// Leser en ISO 6709-posisjon som "+59.9139+010.7522+012.000/". Grader kan også være
+/// skrevet som grader og minutter (DDMM.MM) eller grader, minutter og sekunder (DDMMSS.S).
+fn parse_iso6709(value: &str) -> Option<GpsLocation> {
+    let value = value.split(['/', 'C']).next()?;
+    if !value.starts_with(['+', '-']) {
+        return None;
+    }
+    let starts: Vec<usize> = value.match_indices(['+', '-']).map(|(i, _)| i).collect();
+    let parts: Vec<&str> = starts
+        .iter()
+        .enumerate()
+        .map(|(n, &start)| &value[start..starts.get(n + 1).copied().unwrap_or(value.len())])
+        .collect();
+
+    let latitude = parse_iso6709_angle(parts.first()?, 2).filter(|lat| lat.abs() <= 90.0)?;
+    let longitude = parse_iso6709_angle(parts.get(1)?, 3).filter(|lon| lon.abs() <= 180.0)?;
+    let altitude = parts.get(2).and_then(|alt| alt.parse().ok());
+    Some(GpsLocation { latitude, longitude, altitude })
+}
+
+/// Én koordinat med fortegn. `degree_digits` er antall siffer for hele grader (2 for
+/// breddegrad, 3 for lengdegrad); flere siffer før desimaltegnet betyr minutter/sekunder.
+fn parse_iso6709_angle(part: &str, degree_digits: usize) -> Option<f64> {
+    let (sign, digits) = part.split_at(1);
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit() || b == b'.') {
+        return None;
+    }
+    let value: f64 = digits.parse().ok()?;
+    let integer_digits = digits.find('.').unwrap_or(digits.len());
+
+    let degrees = match integer_digits.checked_sub(degree_digits)? {
+        0 => value,
+        2 => {
+            let degrees = (value / 100.0).trunc();
+            degrees + (value - degrees * 100.0) / 60.0
+        }
+        4 => {
+            let degrees = (value / 10_000.0).trunc();
+            let minutes = ((value - degrees * 10_000.0) / 100.0).trunc();
+            degrees + minutes / 60.0 + (value - degrees * 10_000.0 - minutes * 100.0) / 3600.0
+        }
+        _ => return None,
+    };
+    Some(if sign == "-" { -degrees } else { degrees })
 }
 
 fn read_exif_date(path: &Path) -> Option<NaiveDateTime> {
+    // Prøv forskjellige datofelt i prioritert rekkefølge
+    read_exif_date_fields(
+        path,
+        &[
+            exif::Tag::DateTimeOriginal,
+            exif::Tag::DateTimeDigitized,
+            exif::Tag::DateTime,
+        ],
+    )
+}
+
+/// Leser kun EXIF `DateTimeOriginal` (tidspunktet bildet ble tatt)
+/// Brukes når vi må skille originaler fra eksporter/redigeringer som bare har `DateTime`.
+pub fn read_exif_date_original(path: &Path) -> Option<NaiveDateTime> {
+    read_exif_date_fields(path, &[exif::Tag::DateTimeOriginal])
+}
+
+/// Leser EXIF `Orientation` - hvordan bildet må roteres/speiles for å vises riktig.
+/// Returnerer None hvis taggen mangler eller har en ugyldig verdi.
+///
+/// HEIC/AVIF skal ikke roteres etter denne: libheif har allerede brukt `irot`/`imir`
+/// fra containeren, og EXIF-verdien i slike filer er bare informativ.
+pub fn read_orientation(path: &Path) -> Option<Orientation> {
+    let exif = read_exif(path)?;
+    let value = primary_field(&exif, exif::Tag::Orientation)?.value.get_uint(0)?;
+    Orientation::from_exif(u8::try_from(value).ok()?)
+}
+
+/// Bildestørrelse for RAW-filer, som `image` ikke kan lese. IFD0 beskriver ofte bare
+/// en liten thumbnail, så det største paret av `PixelXDimension`/`PixelYDimension` og
+/// `ImageWidth`/`ImageLength` i noen IFD brukes, sammen med den innebygde forhåndsvisningen.
+pub fn read_raw_dimensions(path: &Path) -> Option<(u32, u32)> {
+    let from_exif = read_exif(path).and_then(|exif| {
+        let ifds: Vec<exif::In> = exif.fields().map(|f| f.ifd_num).collect();
+        let pairs = [
+            (exif::Tag::PixelXDimension, exif::Tag::PixelYDimension),
+            (exif::Tag::ImageWidth, exif::Tag::ImageLength),
+        ];
+        ifds.into_iter()
+            .flat_map(|ifd| pairs.map(|(x, y)| (ifd, x, y)))
+            .filter_map(|(ifd, x, y)| {
+                let width = exif.get_field(x, ifd)?.value.get_uint(0)?;
+                let height = exif.get_field(y, ifd)?.value.get_uint(0)?;
+                Some((width, height))
+            })
+            .max_by_key(|&(w, h)| w as u64 * h as u64)
+    });
+
+    [from_exif, raw::preview_dimensions(path)]
+        .into_iter()
+        .flatten()
+        .max_by_key(|&(w, h)| w as u64 * h as u64)
+}
+
+/// Leser EXIF fra bildet. RAW-filer som `exif` ikke kjenner containeren til
+/// (ORF, RAF, CR3) leses via `raw`.
+fn read_exif(path: &Path) -> Option<exif::Exif> {
     let file = File::open(path).ok()?;
     let mut bufreader = BufReader::new(&file);
     let exifreader = exif::Reader::new();
-    let exif = exifreader.read_from_container(&mut bufreader).ok()?;
+    match exifreader.read_from_container(&mut bufreader) {
+        Ok(exif) => Some(exif),
+        Err(_) if media::is_raw(path) => raw::read_exif(path),
+        Err(_) => None,
+    }
+}
 
-    // Prøv forskjellige datofelt i prioritert rekkefølge
-    let date_fields = [
-        exif::Tag::DateTimeOriginal,
-        exif::Tag::DateTimeDigitized,
-        exif::Tag::DateTime,
-    ];
-
-    for tag in date_fields {
-        if let Some(field) = exif.get_field(tag, exif::In::PRIMARY) {
+/// Slår opp et felt i hoved-IFD-en. CR3 lagrer EXIF-IFD-en som en egen TIFF (CMT2),
+/// så der ligger f.eks. DateTimeOriginal i IFD0 - derfor faller vi tilbake til tag-nummeret.
+fn primary_field(exif: &exif::Exif, tag: exif::Tag) -> Option<&exif::Field> {
+    exif.get_field(tag, exif::In::PRIMARY).or_else(|| {
+        exif.fields()
+            .find(|f| f.ifd_num == exif::In::PRIMARY && f.tag.number() == tag.number())
+    })
+}
+
+/// Returnerer første gyldige dato blant gitte EXIF-felt
+fn read_exif_date_fields(path: &Path, date_fields: &[exif::Tag]) -> Option<NaiveDateTime> {
+    let exif = read_exif(path)?;
+
+    for &tag in date_fields {
+        if let Some(field) = primary_field(&exif, tag) {
             if let exif::Value::Ascii(ref vec) = field.value {
                 if !vec.is_empty() {
                     let s = std::str::from_utf8(&vec[0]).ok()?;
@@ -128,10 +375,195 @@ fn read_file_mtime(path: &Path) -> Option<DateTime<Local>> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chrono::{LocalResult, NaiveDate};
+    use image::{ImageEncoder, RgbImage};
     use std::fs::File;
     use std::io::Write;
     use tempfile::tempdir;
 
+    /// Little-endian EXIF med bare `Orientation` i IFD0
+    fn orientation_exif(orientation: u16) -> Vec<u8> {
+        let mut exif = b"II*\0".to_vec();
+        exif.extend_from_slice(&8u32.to_le_bytes());
+        exif.extend_from_slice(&1u16.to_le_bytes());
+        exif.extend_from_slice(&0x0112u16.to_le_bytes());
+        exif.extend_from_slice(&3u16.to_le_bytes());
+        exif.extend_from_slice(&1u32.to_le_bytes());
+        exif.extend_from_slice(&orientation.to_le_bytes());
+        exif.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+        exif
+    }
+
+    #[test]
+    fn test_read_orientation() {
+        let dir = tempdir().unwrap();
+        let img = RgbImage::new(8, 4);
+
+        let rotated = dir.path().join("portrett.jpg");
+        let mut encoder = image::codecs::jpeg::JpegEncoder::new(File::create(&rotated).unwrap());
+        encoder.set_exif_metadata(orientation_exif(6)).unwrap();
+        encoder.write_image(img.as_raw(), 8, 4, image::ExtendedColorType::Rgb8).unwrap();
+        assert_eq!(read_orientation(&rotated), Some(Orientation::Rotate90));
+
+        let plain = dir.path().join("uten_exif.jpg");
+        img.save(&plain).unwrap();
+        assert_eq!(read_orientation(&plain), None);
+    }
+
+    /// Forkortet `ffprobe -show_format -show_streams` fra en iPhone
+    const IPHONE_JSON: &str = r#"{
+        "streams": [
+            {"codec_type": "video", "codec_name": "hevc", "width": 1920, "height": 1080,
+             "avg_frame_rate": "30000/1001", "r_frame_rate": "30/1",
+             "disposition": {"attached_pic": 0},
+             "side_data_list": [{"side_data_type": "Display Matrix", "rotation": -90}]},
+            {"codec_type": "audio", "codec_name": "aac"}
+        ],
+        "format": {
+            "duration": "12.345000",
+            "tags": {
+                "creation_time": "2023-06-14T16:22:31.000000Z",
+                "com.apple.quicktime.location.ISO6709": "+59.9139+010.7522+012.000/",
+                "com.apple.quicktime.make": "Apple",
+                "com.apple.quicktime.model": "iPhone 14 Pro",
+                "com.apple.quicktime.creationdate": "2023-06-14T18:22:31+0200"
+            }
+        }
+    }"#;
+
+    #[test]
+    fn test_parse_iphone_video() {
+        let meta = parse_video_metadata(IPHONE_JSON.as_bytes()).unwrap();
+        assert_eq!(meta.duration_ms, Some(12_345));
+        assert_eq!((meta.width, meta.height), (Some(1920), Some(1080)));
+        assert_eq!(meta.codec.as_deref(), Some("hevc"));
+        assert!((meta.frame_rate.unwrap() - 29.97).abs() < 0.01);
+        // Displaymatrisen sier -90 (mot klokka) = 90 med klokka
+        assert_eq!(meta.rotation, Some(90));
+        assert_eq!(meta.make.as_deref(), Some("Apple"));
+        assert_eq!(meta.model.as_deref(), Some("iPhone 14 Pro"));
+        assert_eq!(
+            meta.location,
+            Some(GpsLocation { latitude: 59.9139, longitude: 10.7522, altitude: Some(12.0) })
+        );
+
+        let created = meta.creation_date.unwrap();
+        assert_eq!(created.offset().local_minus_utc(), 2 * 3600);
+        assert_eq!(created.with_timezone(&Utc), meta.creation_time.unwrap());
+        // Sorteringen bruker klokkeslettet der videoen ble tatt, uavhengig av maskinens tidssone
+        assert_eq!(meta.recorded_at(), Some(created.naive_local()));
+        assert_eq!(meta.recorded_at().unwrap().to_string(), "2023-06-14 18:22:31");
+    }
+
+    #[test]
+    fn test_parse_android_video() {
+        let json = r#"{
+            "streams": [
+                {"codec_type": "video", "codec_name": "mjpeg", "disposition": {"attached_pic": 1}},
+                {"codec_type": "video", "codec_name": "h264", "width": 3840, "height": 2160,
+                 "avg_frame_rate": "0/0", "r_frame_rate": "60/1", "duration": "3.5",
+                 "tags": {"rotate": "270"}}
+            ],
+            "format": {
+                "tags": {
+                    "creation_time": "1970-01-01T00:00:00.000000Z",
+                    "location": "-33.8688+151.2093/",
+                    "com.android.manufacturer": "Google",
+                    "com.android.model": "Pixel 8"
+                }
+            }
+        }"#;
+        let meta = parse_video_metadata(json.as_bytes()).unwrap();
+        // Omslagsbildet hoppes over
+        assert_eq!(meta.codec.as_deref(), Some("h264"));
+        assert_eq!(meta.duration_ms, Some(3_500));
+        assert_eq!(meta.frame_rate, Some(60.0));
+        assert_eq!(meta.rotation, Some(270));
+        assert_eq!(meta.make.as_deref(), Some("Google"));
+        assert_eq!(meta.model.as_deref(), Some("Pixel 8"));
+        assert_eq!(meta.location.unwrap().latitude, -33.8688);
+        assert_eq!(meta.location.unwrap().altitude, None);
+        // Nullstilt klokke er ingen dato
+        assert_eq!(meta.creation_time, None);
+        assert_eq!(meta.recorded_at(), None);
+    }
+
+    #[test]
+    fn test_parse_iso6709() {
+        let degrees_minutes = parse_iso6709("+5954.834+01045.132/").unwrap();
+        assert!((degrees_minutes.latitude - 59.9139).abs() < 1e-4);
+        assert!((degrees_minutes.longitude - 10.7522).abs() < 1e-4);
+
+        let with_seconds = parse_iso6709("-335212.8+1511233.5CRSWGS_84/").unwrap();
+        assert!((with_seconds.latitude + 33.8702).abs() < 1e-4);
+        assert!((with_seconds.longitude - 151.2093).abs() < 1e-4);
+
+        assert_eq!(parse_iso6709(""), None);
+        assert_eq!(parse_iso6709("59.9139+010.7522/"), None);
+        assert_eq!(parse_iso6709("+95.0+010.0/"), None);
+    }
+
+    /// Norsk tid med sommertid bare i 2024, så testen ikke avhenger av tidssonen på maskinen
+    #[derive(Debug, Clone)]
+    struct Norway2024;
+
+    impl Norway2024 {
+        const WINTER: i32 = 3600;
+        const SUMMER: i32 = 7200;
+    }
+
+    impl TimeZone for Norway2024 {
+        type Offset = FixedOffset;
+
+        fn from_offset(_offset: &FixedOffset) -> Self {
+            Norway2024
+        }
+
+        fn offset_from_local_date(&self, local: &NaiveDate) -> LocalResult<FixedOffset> {
+            self.offset_from_local_datetime(&local.and_hms_opt(0, 0, 0).unwrap())
+        }
+
+        fn offset_from_local_datetime(&self, local: &NaiveDateTime) -> LocalResult<FixedOffset> {
+            // Sommertid først, siden den gir det tidligste tidspunktet
+            let valid: Vec<FixedOffset> = [Self::SUMMER, Self::WINTER]
+                .into_iter()
+                .map(|seconds| FixedOffset::east_opt(seconds).unwrap())
+                .filter(|offset| self.offset_from_utc_datetime(&(*local - *offset)) == *offset)
+                .collect();
+            match valid[..] {
+                [offset] => LocalResult::Single(offset),
+                [earliest, latest] => LocalResult::Ambiguous(earliest, latest),
+                _ => LocalResult::None,
+            }
+        }
+
+        fn offset_from_utc_date(&self, utc: &NaiveDate) -> FixedOffset {
+            self.offset_from_utc_datetime(&utc.and_hms_opt(0, 0, 0).unwrap())
+        }
+
+        fn offset_from_utc_datetime(&self, utc: &NaiveDateTime) -> FixedOffset {
+            let at = |month, day| NaiveDate::from_ymd_opt(2024, month, day).unwrap().and_hms_opt(1, 0, 0).unwrap();
+            let summer = (at(3, 31)..at(10, 27)).contains(utc);
+            FixedOffset::east_opt(if summer { Self::SUMMER } else { Self::WINTER }).unwrap()
+        }
+    }
+
+    #[test]
+    fn test_local_date_across_dst() {
+        let date = |month, day, hour, min| NaiveDate::from_ymd_opt(2024, month, day).unwrap().and_hms_opt(hour, min, 0).unwrap();
+
+        let normal = local_date(&Norway2024, &date(6, 1, 12, 0)).unwrap();
+        assert_eq!(normal.offset().local_minus_utc(), Norway2024::SUMMER);
+
+        // 02:30 finnes ikke når klokken stilles frem fra 02:00 til 03:00
+        assert_eq!(local_date(&Norway2024, &date(3, 31, 2, 30)), None);
+
+        // 02:30 finnes to ganger når klokken stilles tilbake fra 03:00 til 02:00
+        let ambiguous = local_date(&Norway2024, &date(10, 27, 2, 30)).unwrap();
+        assert_eq!(ambiguous.offset().local_minus_utc(), Norway2024::SUMMER);
+        assert_eq!(ambiguous.naive_local(), date(10, 27, 2, 30));
+    }
+
     #[test]
     fn test_fallback_to_mtime() {
         let dir = tempdir().unwrap();
@@ -7,4 +7,16 @@ pub mod metadata;
 pub mod sorter;
 pub mod cache;
 pub mod sidecar;
-
+pub mod clustering;
+pub mod keeper;
+pub mod jobs;
+pub mod progress;
+pub mod signature;
+pub mod media;
+pub mod raw;
+pub mod heif;
+pub mod bmff;
+pub mod preview;
+pub mod video;
+pub mod tools;
+pub mod process;
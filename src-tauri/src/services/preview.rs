@@ -0,0 +1,308 @@
+//! Innebygde forhåndsvisninger i JPEG-filer
+//!
+//! Kameraer og telefoner legger ved en liten thumbnail i EXIF (IFD1, typisk 160x120)
+//! og noen ganger en større forhåndsvisning i MPF-segmentet (APP2). Å dekode dem er
+//! mye raskere enn å dekode hele bildet, så de brukes til hashing og galleri-thumbnails.
+
+use exif::{Context, In, Tag};
+use image::DynamicImage;
+use memmap2::Mmap;
+use std::fs::File;
+use std::path::Path;
+
+/// Hvor mye sideforholdet kan avvike fra hovedbildet (avrunding i små thumbnails)
+const ASPECT_TOLERANCE: f64 = 0.02;
+
+/// MP Entry-taggen i MPF-indeksen (CIPA DC-007)
+const MPF_ENTRY_TAG: u16 = 0xB002;
+
+/// Bildetyper i MPF som er forhåndsvisninger av hovedbildet (VGA og Full HD)
+const MPF_PREVIEW_TYPES: &[u32] = &[0x010001, 0x010002];
+
+/// Laster den minste innebygde forhåndsvisningen som er minst `min_size` piksler på
+/// korteste side, så den kan skaleres ned til et kvadrat på `min_size` uten å strekkes
+/// opp i noen retning. Returnerer None hvis filen ikke har noen som passer, og kalleren
+/// må da dekode hele bildet.
+///
+/// Forhåndsvisninger med et annet sideforhold enn hovedbildet forkastes. Det gjelder
+/// thumbnails med svarte kanter (160x120 for et 16:9-bilde) og thumbnails som ikke ble
+/// oppdatert da bildet ble rotert i et redigeringsprogram. Både hovedbildet og
+/// forhåndsvisningene er lagret uten EXIF-rotasjon, så sammenligningen gjelder også
+/// etter at rotasjonen er brukt.
+pub fn load_embedded_preview(path: &Path, min_size: u32) -> Option<DynamicImage> {
+    let file = File::open(path).ok()?;
+    // Safety: filen åpnes kun for lesing, og mappingen lever bare i dette kallet
+    let data = unsafe { Mmap::map(&file) }.ok()?;
+    let jpeg = best_preview(&data, min_size)?;
+    image::load_from_memory_with_format(jpeg, image::ImageFormat::Jpeg).ok()
+}
+
+fn best_preview(data: &[u8], min_size: u32) -> Option<&[u8]> {
+    if !data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        return None;
+    }
+    let main = sof_dimensions(data)?;
+
+    let mut candidates = mpf_previews(data);
+    candidates.extend(exif_thumbnail(data));
+    candidates
+        .into_iter()
+        .filter_map(|jpeg| sof_dimensions(jpeg).map(|dims| (jpeg, dims)))
+        .filter(|&(_, (width, height))| width.min(height) >= min_size && same_aspect((width, height), main))
+        .min_by_key(|&(_, (width, height))| width as u64 * height as u64)
+        .map(|(jpeg, _)| jpeg)
+}
+
+fn same_aspect(a: (u32, u32), b: (u32, u32)) -> bool {
+    if a.1 == 0 || b.1 == 0 {
+        return false;
+    }
+    let ratio_a = a.0 as f64 / a.1 as f64;
+    let ratio_b = b.0 as f64 / b.1 as f64;
+    (ratio_a - ratio_b).abs() / ratio_b <= ASPECT_TOLERANCE
+}
+
+/// Et markør-segment i JPEG-headeren. `offset` er posisjonen til `data` i filen.
+struct Segment<'a> {
+    marker: u8,
+    offset: usize,
+    data: &'a [u8],
+}
+
+/// Segmentene før bildedataene (SOS)
+fn segments(jpeg: &[u8]) -> impl Iterator<Item = Segment<'_>> {
+    let mut pos = 2;
+    std::iter::from_fn(move || {
+        if jpeg.get(pos) != Some(&0xFF) {
+            return None;
+        }
+        let marker = *jpeg.get(pos + 1)?;
+        if marker == 0xDA || marker == 0xD9 {
+            return None;
+        }
+        // Lengden inkluderer de to lengde-bytene selv
+        let len = u16::from_be_bytes([*jpeg.get(pos + 2)?, *jpeg.get(pos + 3)?]) as usize;
+        let data = jpeg.get(pos + 4..pos + 2 + len.max(2))?;
+        let segment = Segment { marker, offset: pos + 4, data };
+        pos += 2 + len.max(2);
+        Some(segment)
+    })
+}
+
+/// Bredde og høyde fra SOF-segmentet. Bare baseline og progressiv JPEG (SOF0-SOF2)
+/// regnes med, siden det er det `image` kan dekode.
+fn sof_dimensions(jpeg: &[u8]) -> Option<(u32, u32)> {
+    let sof = segments(jpeg).find(|s| (0xC0..=0xCF).contains(&s.marker) && ![0xC4, 0xC8, 0xCC].contains(&s.marker))?;
+    if sof.marker > 0xC2 {
+        return None;
+    }
+    let height = u16::from_be_bytes([*sof.data.get(1)?, *sof.data.get(2)?]) as u32;
+    let width = u16::from_be_bytes([*sof.data.get(3)?, *sof.data.get(4)?]) as u32;
+    Some((width, height))
+}
+
+/// Thumbnailen i IFD1 (JPEGInterchangeFormat). Offset er relativt til TIFF-headeren i APP1.
+fn exif_thumbnail(jpeg: &[u8]) -> Option<&[u8]> {
+    let segment = segments(jpeg).find(|s| s.marker == 0xE1 && s.data.starts_with(b"Exif\0\0"))?;
+    let tiff = &segment.data[6..];
+    let exif = exif::Reader::new().read_raw(tiff.to_vec()).ok()?;
+    let offset = exif.get_field(Tag::JPEGInterchangeFormat, In::THUMBNAIL)?.value.get_uint(0)? as usize;
+    let len = exif.get_field(Tag::JPEGInterchangeFormatLength, In::THUMBNAIL)?.value.get_uint(0)? as usize;
+    tiff.get(offset..offset.checked_add(len)?)
+}
+
+/// Forhåndsvisninger fra MPF-indeksen i APP2. Hver MP Entry er 16 bytes: attributt
+/// (bildetype i de nederste 24 bitene), størrelse og offset relativt til MPF-headeren.
+fn mpf_previews(jpeg: &[u8]) -> Vec<&[u8]> {
+    let segment = match segments(jpeg).find(|s| s.marker == 0xE2 && s.data.starts_with(b"MPF\0")) {
+        Some(segment) => segment,
+        None => return Vec::new(),
+    };
+    let header_start = segment.offset + 4;
+    let index = match exif::Reader::new().read_raw(segment.data[4..].to_vec()) {
+        Ok(index) => index,
+        Err(_) => return Vec::new(),
+    };
+    let entries = match index.get_field(Tag(Context::Tiff, MPF_ENTRY_TAG), In::PRIMARY) {
+        Some(exif::Field { value: exif::Value::Undefined(bytes, _), .. }) => bytes,
+        _ => return Vec::new(),
+    };
+
+    let read_u32 = |bytes: &[u8]| -> u32 {
+        let bytes: [u8; 4] = bytes.try_into().unwrap_or_default();
+        if index.little_endian() { u32::from_le_bytes(bytes) } else { u32::from_be_bytes(bytes) }
+    };
+
+    entries
+        .chunks_exact(16)
+        .filter_map(|entry| {
+            let image_type = read_u32(&entry[0..4]) & 0x00FF_FFFF;
+            let size = read_u32(&entry[4..8]) as usize;
+            let offset = read_u32(&entry[8..12]) as usize;
+            // Hovedbildet har alltid offset 0
+            if offset == 0 || !MPF_PREVIEW_TYPES.contains(&image_type) {
+                return None;
+            }
+            let start = header_start.checked_add(offset)?;
+            jpeg.get(start..start.checked_add(size)?)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::GenericImageView;
+    use std::fs;
+    use std::io::Cursor;
+    use tempfile::tempdir;
+
+    /// JPEG med en ensfarget flate, så det er lett å se hvilket bilde som ble dekodet
+    fn jpeg_bytes(width: u32, height: u32, gray: u8) -> Vec<u8> {
+        let img = image::RgbImage::from_pixel(width, height, image::Rgb([gray, gray, gray]));
+        let mut buf = Cursor::new(Vec::new());
+        DynamicImage::ImageRgb8(img).write_to(&mut buf, image::ImageFormat::Jpeg).unwrap();
+        buf.into_inner()
+    }
+
+    fn segment(marker: u8, data: &[u8]) -> Vec<u8> {
+        let mut out = vec![0xFF, marker];
+        out.extend_from_slice(&((data.len() + 2) as u16).to_be_bytes());
+        out.extend_from_slice(data);
+        out
+    }
+
+    /// Verdien i et IFD-felt: enten direkte i feltet eller en offset inn i datablokken
+    #[derive(Clone, Copy)]
+    enum Value {
+        Inline(u32),
+        Payload(u32),
+    }
+
+    /// Little-endian TIFF med IFD0, eventuelt IFD1, og `payload` lagt etter IFD-ene.
+    /// Feltene er (tag, type, antall, verdi).
+    fn tiff(ifd0: &[(u16, u16, u32, Value)], ifd1: &[(u16, u16, u32, Value)], payload: &[u8]) -> Vec<u8> {
+        let ifd_size = |entries: &[_]| if entries.is_empty() { 0 } else { 2 + entries.len() * 12 + 4 };
+        let ifd1_start = 8 + ifd_size(ifd0);
+        let data_start = (ifd1_start + ifd_size(ifd1)) as u32;
+
+        let mut out = b"II*\0".to_vec();
+        out.extend_from_slice(&8u32.to_le_bytes());
+        for (entries, next) in [(ifd0, if ifd1.is_empty() { 0 } else { ifd1_start as u32 }), (ifd1, 0)] {
+            if entries.is_empty() {
+                continue;
+            }
+            out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+            for &(tag, field_type, count, value) in entries {
+                out.extend_from_slice(&tag.to_le_bytes());
+                out.extend_from_slice(&field_type.to_le_bytes());
+                out.extend_from_slice(&count.to_le_bytes());
+                let value = match value {
+                    Value::Inline(value) => value,
+                    Value::Payload(offset) => data_start + offset,
+                };
+                out.extend_from_slice(&value.to_le_bytes());
+            }
+            out.extend_from_slice(&next.to_le_bytes());
+        }
+        out.extend_from_slice(payload);
+        out
+    }
+
+    /// Hovedbilde med EXIF-thumbnail i IFD1 og eventuelt en MPF-forhåndsvisning bakerst i filen
+    fn jpeg_with_previews(main: &[u8], thumbnail: &[u8], mpf_preview: Option<&[u8]>) -> Vec<u8> {
+        let mut app1 = b"Exif\0\0".to_vec();
+        app1.extend(tiff(
+            &[(0x0112, 3, 1, Value::Inline(1))],
+            &[(0x0201, 4, 1, Value::Payload(0)), (0x0202, 4, 1, Value::Inline(thumbnail.len() as u32))],
+            thumbnail,
+        ));
+
+        let mut out = vec![0xFF, 0xD8];
+        out.extend(segment(0xE1, &app1));
+        let rest = &main[2..];
+
+        if let Some(preview) = mpf_preview {
+            // MPF-headeren starter etter markør, lengde og "MPF\0"
+            let header_pos = out.len() + 8;
+            let index_len = 8 + 2 + 2 * 12 + 4 + 32;
+            let preview_offset = (index_len + rest.len()) as u32;
+
+            // Hovedbildet (offset 0) og en Full HD-forhåndsvisning
+            let mut entries = Vec::new();
+            for (attribute, size, offset) in [
+                (0x030000u32, preview_offset, 0u32),
+                (0x010002, preview.len() as u32, preview_offset),
+            ] {
+                entries.extend_from_slice(&attribute.to_le_bytes());
+                entries.extend_from_slice(&size.to_le_bytes());
+                entries.extend_from_slice(&offset.to_le_bytes());
+                entries.extend_from_slice(&[0, 0, 0, 0]);
+            }
+            let mut app2 = b"MPF\0".to_vec();
+            app2.extend(tiff(
+                &[(0xB000, 7, 4, Value::Inline(u32::from_le_bytes(*b"0100"))), (MPF_ENTRY_TAG, 7, 32, Value::Payload(0))],
+                &[],
+                &entries,
+            ));
+            out.extend(segment(0xE2, &app2));
+            assert_eq!(header_pos + preview_offset as usize, out.len() + rest.len());
+        }
+        out.extend_from_slice(rest);
+        out.extend_from_slice(mpf_preview.unwrap_or_default());
+        out
+    }
+
+    #[test]
+    fn test_exif_thumbnail() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("IMG_0001.jpg");
+        fs::write(&path, jpeg_with_previews(&jpeg_bytes(400, 300, 200), &jpeg_bytes(160, 120, 40), None)).unwrap();
+
+        let preview = load_embedded_preview(&path, 120).unwrap();
+        assert_eq!(preview.dimensions(), (160, 120));
+        assert!(preview.get_pixel(80, 60)[0] < 100, "Skal være den mørke thumbnailen");
+
+        // Den korte siden (120) er for liten for hashing på 128x128, selv om den lange er 160
+        assert!(load_embedded_preview(&path, 128).is_none());
+    }
+
+    #[test]
+    fn test_mpf_preview_preferred_when_thumbnail_too_small() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("IMG_0002.jpg");
+        let data = jpeg_with_previews(
+            &jpeg_bytes(800, 600, 200),
+            &jpeg_bytes(160, 120, 40),
+            Some(&jpeg_bytes(320, 240, 120)),
+        );
+        fs::write(&path, data).unwrap();
+
+        assert_eq!(load_embedded_preview(&path, 120).unwrap().dimensions(), (160, 120));
+        assert_eq!(load_embedded_preview(&path, 128).unwrap().dimensions(), (320, 240));
+        assert_eq!(load_embedded_preview(&path, 200).unwrap().dimensions(), (320, 240));
+        assert!(load_embedded_preview(&path, 300).is_none());
+    }
+
+    #[test]
+    fn test_preview_with_wrong_aspect_is_rejected() {
+        let dir = tempdir().unwrap();
+        // 16:9-bilde med 4:3-thumbnail (svarte kanter)
+        let letterboxed = dir.path().join("wide.jpg");
+        fs::write(&letterboxed, jpeg_with_previews(&jpeg_bytes(320, 180, 200), &jpeg_bytes(160, 120, 40), None)).unwrap();
+        assert!(load_embedded_preview(&letterboxed, 100).is_none());
+
+        // Rotert i et program som ikke oppdaterte thumbnailen
+        let rotated = dir.path().join("rotated.jpg");
+        fs::write(&rotated, jpeg_with_previews(&jpeg_bytes(300, 400, 200), &jpeg_bytes(160, 120, 40), None)).unwrap();
+        assert!(load_embedded_preview(&rotated, 100).is_none());
+
+        // Vanlig JPEG uten forhåndsvisning og filer som ikke er JPEG
+        let plain = dir.path().join("plain.jpg");
+        fs::write(&plain, jpeg_bytes(100, 100, 0)).unwrap();
+        assert!(load_embedded_preview(&plain, 50).is_none());
+        let png = dir.path().join("bilde.png");
+        fs::write(&png, b"\x89PNG\r\n\x1a\n").unwrap();
+        assert!(load_embedded_preview(&png, 50).is_none());
+    }
+}
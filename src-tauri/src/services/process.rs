@@ -0,0 +1,362 @@
+//! Kjøring av eksterne prosesser (ffmpeg/ffprobe) med tidsgrense
+//!
+//! En korrupt eller nettverksstoppet video kan få ffmpeg til å henge. Prosessen drepes
+//! når tidsgrensen går ut, stderr fanges og legges i feilmeldingen i stedet for å havne
+//! i konsollen, og antall samtidige prosesser begrenses så ikke hver rayon-tråd starter
+//! sin egen ffmpeg.
+//!
+//! På Unix startes prosessen i sin egen prosessgruppe, og hele gruppen drepes. Et
+//! shell-script eller en wrapper rundt ffmpeg etterlater seg ellers barn som holder
+//! stdout og stderr åpne etter at prosessen vi startet er borte.
+
+use std::fmt;
+use std::io::Read;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Standard tidsgrense per prosess
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Standard antall ffmpeg/ffprobe-prosesser som kan kjøre samtidig
+pub const DEFAULT_MAX_PROCESSES: usize = 4;
+
+/// Hvor ofte prosessen sjekkes mens vi venter
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Hvor lenge vi venter på at rørene lukkes etter at prosessen er ferdig, hvis
+/// tidsgrensen allerede er nådd
+const PIPE_GRACE: Duration = Duration::from_millis(100);
+
+/// Hvor mye av stderr som tas med i feilmeldingen
+const STDERR_TAIL_LINES: usize = 10;
+
+#[derive(Debug)]
+pub enum ProcessError {
+    /// Programmet kunne ikke startes (finnes ikke, mangler rettigheter)
+    Spawn { program: String, source: std::io::Error },
+    /// Prosessen brukte lengre tid enn tillatt og ble drept
+    Timeout { program: String, timeout: Duration },
+    /// Prosessen avsluttet med feil. `stderr` er de siste linjene den skrev.
+    Failed { program: String, code: Option<i32>, stderr: String },
+}
+
+impl fmt::Display for ProcessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProcessError::Spawn { program, source } => write!(f, "Kunne ikke starte {}: {}", program, source),
+            ProcessError::Timeout { program, timeout } => {
+                write!(f, "{} svarte ikke innen {} s og ble stoppet", program, timeout.as_secs_f32())
+            }
+            ProcessError::Failed { program, code, stderr } => {
+                match code {
+                    Some(code) => write!(f, "{} feilet med kode {}", program, code)?,
+                    None => write!(f, "{} ble avbrutt", program)?,
+                }
+                if !stderr.is_empty() {
+                    write!(f, ": {}", stderr)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ProcessError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ProcessError::Spawn { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Begrenser hvor mange prosesser som kjører samtidig. Kall som må vente blokkerer.
+pub struct ProcessLimiter {
+    state: Mutex<LimiterState>,
+    freed: Condvar,
+}
+
+struct LimiterState {
+    max: usize,
+    running: usize,
+}
+
+impl ProcessLimiter {
+    pub const fn new(max: usize) -> Self {
+        Self {
+            state: Mutex::new(LimiterState { max, running: 0 }),
+            freed: Condvar::new(),
+        }
+    }
+
+    /// Endrer grensen. Prosesser som allerede kjører får fortsette.
+    pub fn set_max(&self, max: usize) {
+        self.state.lock().unwrap().max = max.max(1);
+        self.freed.notify_all();
+    }
+
+    fn acquire(&self) -> Permit<'_> {
+        let mut state = self.state.lock().unwrap();
+        while state.running >= state.max {
+            state = self.freed.wait(state).unwrap();
+        }
+        state.running += 1;
+        Permit(self)
+    }
+}
+
+/// Plass i `ProcessLimiter`, frigjøres når den droppes
+struct Permit<'a>(&'a ProcessLimiter);
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        self.0.state.lock().unwrap().running -= 1;
+        self.0.freed.notify_one();
+    }
+}
+
+/// Kjører kommandoen og returnerer stdout. Venter på ledig plass i `limiter` først,
+/// og dreper prosessen hvis den ikke er ferdig innen `timeout`.
+pub fn run(mut command: Command, timeout: Duration, limiter: &ProcessLimiter) -> Result<Vec<u8>, ProcessError> {
+    let program = command.get_program().to_string_lossy().to_string();
+    let _permit = limiter.acquire();
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|source| ProcessError::Spawn { program: program.clone(), source })?;
+
+    // Rørene leses i egne tråder, ellers kan prosessen blokkere på full buffer
+    let stdout = child.stdout.take().map(read_in_background);
+    let stderr = child.stderr.take().map(read_in_background);
+
+    let deadline = Instant::now() + timeout;
+    // Gruppen drepes før prosessen høstes med `wait`. Så lenge den ikke er høstet, kan
+    // ikke ID-en brukes av en ny prosessgruppe.
+    let status = loop {
+        match has_exited(&mut child) {
+            Ok(true) => {
+                // Barn som fortsatt kjører etter at prosessen er ferdig, holder rørene åpne
+                kill_group(&child);
+                break child.wait().ok();
+            }
+            Ok(false) if Instant::now() >= deadline => {
+                kill_group(&child);
+                let _ = child.kill();
+                let _ = child.wait();
+                break None;
+            }
+            Ok(false) => thread::sleep(POLL_INTERVAL),
+            Err(_) => break child.wait().ok(),
+        }
+    };
+
+    // Et barn utenfor prosessgruppen (eller på Windows) kan fortsatt holde rørene åpne.
+    // Da ventes det ikke lenger enn tidsgrensen, og lesetråden avslutter når røret lukkes.
+    let wait_until = deadline.max(Instant::now() + PIPE_GRACE);
+    let collect = |reader: Option<Receiver<Vec<u8>>>| {
+        reader
+            .and_then(|r| r.recv_timeout(wait_until.saturating_duration_since(Instant::now())).ok())
+            .unwrap_or_default()
+    };
+    let stdout = collect(stdout);
+    let stderr = collect(stderr);
+
+    match status {
+        None => Err(ProcessError::Timeout { program, timeout }),
+        Some(status) if status.success() => Ok(stdout),
+        Some(status) => Err(ProcessError::Failed {
+            program,
+            code: status.code(),
+            stderr: stderr_tail(&stderr),
+        }),
+    }
+}
+
+/// Om prosessen har avsluttet, uten å høste den. Prosessen blir liggende som zombie
+/// til `wait`, og holder på ID-en til prosessgruppen så lenge.
+#[cfg(unix)]
+fn has_exited(child: &mut Child) -> std::io::Result<bool> {
+    // SAFETY: siginfo_t er ren data, og waitid skriver bare i den. Uten ferdige
+    // prosesser nullstiller waitid strukturen, så si_signo er 0.
+    let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+    let result = unsafe {
+        libc::waitid(
+            libc::P_PID,
+            child.id() as libc::id_t,
+            &mut info,
+            libc::WEXITED | libc::WNOHANG | libc::WNOWAIT,
+        )
+    };
+    if result == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(info.si_signo == libc::SIGCHLD)
+}
+
+/// Uten prosessgrupper er det ingen gruppe-ID å holde på, så `try_wait` kan høste prosessen
+#[cfg(not(unix))]
+fn has_exited(child: &mut Child) -> std::io::Result<bool> {
+    Ok(child.try_wait()?.is_some())
+}
+
+/// Dreper alle prosesser i prosessgruppen til `child`. Må kalles før `child` er høstet:
+/// etter det kan ID-en være gjenbrukt av en annen prosessgruppe.
+#[cfg(unix)]
+fn kill_group(child: &Child) {
+    // SAFETY: killpg tar bare to heltall og har ingen krav til minnet
+    unsafe {
+        libc::killpg(child.id() as libc::pid_t, libc::SIGKILL);
+    }
+}
+
+#[cfg(not(unix))]
+fn kill_group(_child: &Child) {}
+
+fn read_in_background(mut pipe: impl Read + Send + 'static) -> Receiver<Vec<u8>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buffer = Vec::new();
+        let _ = pipe.read_to_end(&mut buffer);
+        let _ = sender.send(buffer);
+    });
+    receiver
+}
+
+/// De siste linjene av stderr - ffmpeg skriver den egentlige feilen til slutt
+fn stderr_tail(stderr: &[u8]) -> String {
+    let text = String::from_utf8_lossy(stderr);
+    let lines: Vec<&str> = text.lines().map(str::trim).filter(|line| !line.is_empty()).collect();
+    lines[lines.len().saturating_sub(STDERR_TAIL_LINES)..].join("\n")
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+    use tempfile::tempdir;
+
+    /// Falsk ffmpeg som kjører `body` som shell-script
+    fn fake_ffmpeg(dir: &Path, body: &str) -> PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+        let path = dir.join("ffmpeg");
+        fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_returns_stdout() {
+        let dir = tempdir().unwrap();
+        let ffmpeg = fake_ffmpeg(dir.path(), "echo \"bilde $1\"; echo 'støy' >&2");
+        let limiter = ProcessLimiter::new(1);
+
+        let mut command = Command::new(&ffmpeg);
+        command.arg("42");
+        let stdout = run(command, DEFAULT_TIMEOUT, &limiter).unwrap();
+        assert_eq!(stdout, b"bilde 42\n");
+    }
+
+    #[test]
+    fn test_failure_includes_stderr() {
+        let dir = tempdir().unwrap();
+        let ffmpeg = fake_ffmpeg(
+            dir.path(),
+            "echo 'ffmpeg version 6.1' >&2; echo 'moov atom not found' >&2; echo 'Invalid data found' >&2; exit 183",
+        );
+
+        let error = run(Command::new(&ffmpeg), DEFAULT_TIMEOUT, &ProcessLimiter::new(1)).unwrap_err();
+        assert!(matches!(error, ProcessError::Failed { code: Some(183), .. }));
+        let message = error.to_string();
+        assert!(message.contains("kode 183"), "{}", message);
+        assert!(message.contains("moov atom not found\nInvalid data found"), "{}", message);
+    }
+
+    #[test]
+    fn test_hanging_process_is_killed() {
+        let dir = tempdir().unwrap();
+        // Skriver litt og henger så, som ffmpeg på en video som stopper på nettverket
+        let ffmpeg = fake_ffmpeg(dir.path(), "echo 'starter'; exec sleep 30");
+
+        let started = Instant::now();
+        let error = run(Command::new(&ffmpeg), Duration::from_millis(200), &ProcessLimiter::new(1)).unwrap_err();
+        assert!(matches!(error, ProcessError::Timeout { .. }));
+        assert!(started.elapsed() < Duration::from_secs(10), "Prosessen skal drepes, ikke ventes ut");
+    }
+
+    #[test]
+    fn test_hanging_grandchild_is_killed() {
+        let dir = tempdir().unwrap();
+        // Uten `exec` kjører sleep som barn av shellet og arver stdout og stderr
+        let ffmpeg = fake_ffmpeg(dir.path(), "echo 'starter'; sleep 30; echo 'ferdig'");
+
+        let started = Instant::now();
+        let error = run(Command::new(&ffmpeg), Duration::from_millis(200), &ProcessLimiter::new(1)).unwrap_err();
+        assert!(matches!(error, ProcessError::Timeout { .. }));
+        assert!(started.elapsed() < Duration::from_secs(10), "Barnet til prosessen skal også drepes");
+    }
+
+    #[test]
+    fn test_background_child_does_not_block_output() {
+        let dir = tempdir().unwrap();
+        // Prosessen avslutter med en gang, men etterlater et barn som holder stdout åpen
+        let ffmpeg = fake_ffmpeg(dir.path(), "sleep 30 & echo 'ferdig'");
+
+        let started = Instant::now();
+        let stdout = run(Command::new(&ffmpeg), DEFAULT_TIMEOUT, &ProcessLimiter::new(1)).unwrap();
+        assert_eq!(stdout, b"ferdig\n");
+        assert!(started.elapsed() < Duration::from_secs(10), "Skal ikke vente på barnet");
+    }
+
+    #[test]
+    fn test_missing_program() {
+        let dir = tempdir().unwrap();
+        let error = run(Command::new(dir.path().join("ffmpeg")), DEFAULT_TIMEOUT, &ProcessLimiter::new(1)).unwrap_err();
+        assert!(matches!(error, ProcessError::Spawn { .. }));
+    }
+
+    #[test]
+    fn test_concurrency_limit() {
+        let dir = tempdir().unwrap();
+        // Hver prosess registrerer seg i en fil mens den kjører
+        let log = dir.path().join("kjører.log");
+        let ffmpeg = fake_ffmpeg(
+            dir.path(),
+            &format!("echo start >> '{0}'; sleep 0.2; echo slutt >> '{0}'", log.display()),
+        );
+        let limiter = Arc::new(ProcessLimiter::new(2));
+
+        let workers: Vec<_> = (0..5)
+            .map(|_| {
+                let (ffmpeg, limiter) = (ffmpeg.clone(), limiter.clone());
+                thread::spawn(move || run(Command::new(ffmpeg), DEFAULT_TIMEOUT, &limiter).unwrap())
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+
+        // Aldri mer enn to "start" uten en "slutt" imellom
+        let mut running = 0;
+        let mut max_running = 0;
+        for line in fs::read_to_string(&log).unwrap().lines() {
+            running += if line == "start" { 1 } else { -1 };
+            max_running = max_running.max(running);
+        }
+        assert_eq!(running, 0);
+        assert_eq!(max_running, 2);
+    }
+}
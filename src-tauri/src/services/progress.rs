@@ -0,0 +1,253 @@
+//! Fremdriftsrapportering for langvarige operasjoner
+//!
+//! Tjenestene rapporterer til en `ProgressReporter`, som samler tellere og sender
+//! `ProgressEvent` videre til en sink (i appen: Tauri-eventet "progress").
+//! Utsendingen strupes, så tusenvis av filer i sekundet ikke oversvømmer frontend.
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Minste tid mellom to utsendte events innenfor samme fase
+pub const DEFAULT_EMIT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Fasene en jobb kan være i
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ProgressPhase {
+    Scan,
+    ExactStage,
+    Verify,
+    VisualStage,
+    Clustering,
+    Sort,
+    Delete,
+    Rename,
+}
+
+/// Payload for "progress"-eventet
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProgressEvent {
+    pub job_id: String,
+    pub phase: ProgressPhase,
+    pub done: u64,
+    /// Totalt antall filer i fasen, hvis kjent (skanning vet ikke dette på forhånd)
+    pub total: Option<u64>,
+    pub bytes_processed: u64,
+    pub errors: u64,
+    pub current_file: Option<String>,
+    /// Estimert gjenstående tid i sekunder, basert på snittfart i fasen
+    pub eta_seconds: Option<f64>,
+    /// True for siste event i en fase
+    pub finished: bool,
+}
+
+type Sink = Box<dyn Fn(&ProgressEvent) + Send + Sync>;
+
+struct PhaseState {
+    phase: ProgressPhase,
+    total: Option<u64>,
+    started: Instant,
+    last_emit: Option<Instant>,
+}
+
+/// Samler fremdrift fra (potensielt parallelle) løkker og sender strupede events
+pub struct ProgressReporter {
+    job_id: String,
+    sink: Option<Sink>,
+    interval: Duration,
+    state: Mutex<PhaseState>,
+    done: AtomicU64,
+    bytes: AtomicU64,
+    errors: AtomicU64,
+}
+
+impl ProgressReporter {
+    pub fn new(job_id: impl Into<String>, sink: impl Fn(&ProgressEvent) + Send + Sync + 'static) -> Self {
+        Self::build(job_id.into(), Some(Box::new(sink)))
+    }
+
+    /// Reporter som ikke sender noe (for tester og kall uten frontend)
+    pub fn disabled() -> Self {
+        Self::build(String::new(), None)
+    }
+
+    fn build(job_id: String, sink: Option<Sink>) -> Self {
+        Self {
+            job_id,
+            sink,
+            interval: DEFAULT_EMIT_INTERVAL,
+            state: Mutex::new(PhaseState {
+                phase: ProgressPhase::Scan,
+                total: None,
+                started: Instant::now(),
+                last_emit: None,
+            }),
+            done: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+        }
+    }
+
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn job_id(&self) -> &str {
+        &self.job_id
+    }
+
+    /// Starter en ny fase og nullstiller tellerne. Sender alltid et event.
+    pub fn start_phase(&self, phase: ProgressPhase, total: Option<u64>) {
+        {
+            let mut state = self.state.lock().unwrap();
+            state.phase = phase;
+            state.total = total;
+            state.started = Instant::now();
+            state.last_emit = None;
+        }
+        self.done.store(0, Ordering::Relaxed);
+        self.bytes.store(0, Ordering::Relaxed);
+        self.errors.store(0, Ordering::Relaxed);
+        self.emit(None, true, false);
+    }
+
+    /// Registrerer én ferdig fil
+    pub fn advance(&self, bytes: u64, current_file: Option<&str>) {
+        self.done.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+        self.emit(current_file, false, false);
+    }
+
+    /// Registrerer én fil som feilet (teller også som behandlet)
+    pub fn error(&self, current_file: Option<&str>) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+        self.advance(0, current_file);
+    }
+
+    /// Avslutter fasen. Sender alltid et siste event.
+    pub fn finish_phase(&self) {
+        self.emit(None, true, true);
+    }
+
+    fn emit(&self, current_file: Option<&str>, force: bool, finished: bool) {
+        let sink = match &self.sink {
+            Some(sink) => sink,
+            None => return,
+        };
+
+        let event = {
+            let mut state = self.state.lock().unwrap();
+            let now = Instant::now();
+            if !force {
+                if let Some(last) = state.last_emit {
+                    if now.duration_since(last) < self.interval {
+                        return;
+                    }
+                }
+            }
+            state.last_emit = Some(now);
+
+            let done = self.done.load(Ordering::Relaxed);
+            ProgressEvent {
+                job_id: self.job_id.clone(),
+                phase: state.phase,
+                done,
+                total: state.total,
+                bytes_processed: self.bytes.load(Ordering::Relaxed),
+                errors: self.errors.load(Ordering::Relaxed),
+                current_file: current_file.map(|f| f.to_string()),
+                eta_seconds: estimate_eta(now.duration_since(state.started), done, state.total),
+                finished,
+            }
+        };
+
+        sink(&event);
+    }
+}
+
+/// Gjenstående tid = snittid per fil * filer igjen
+fn estimate_eta(elapsed: Duration, done: u64, total: Option<u64>) -> Option<f64> {
+    let total = total?;
+    if done == 0 {
+        return None;
+    }
+    let remaining = total.saturating_sub(done);
+    Some(elapsed.as_secs_f64() / done as f64 * remaining as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn collecting_reporter(interval: Duration) -> (ProgressReporter, Arc<Mutex<Vec<ProgressEvent>>>) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink_events = events.clone();
+        let reporter = ProgressReporter::new("job-1", move |e: &ProgressEvent| {
+            sink_events.lock().unwrap().push(e.clone());
+        })
+        .with_interval(interval);
+        (reporter, events)
+    }
+
+    #[test]
+    fn test_events_are_throttled() {
+        let (reporter, events) = collecting_reporter(Duration::from_secs(60));
+
+        reporter.start_phase(ProgressPhase::VisualStage, Some(1000));
+        for _ in 0..1000 {
+            reporter.advance(10, Some("a.jpg"));
+        }
+        reporter.finish_phase();
+
+        let events = events.lock().unwrap();
+        // Kun start og slutt; alle advance-kall er innenfor intervallet
+        assert_eq!(events.len(), 2);
+        let last = events.last().unwrap();
+        assert!(last.finished);
+        assert_eq!(last.done, 1000);
+        assert_eq!(last.total, Some(1000));
+        assert_eq!(last.bytes_processed, 10_000);
+        assert_eq!(last.job_id, "job-1");
+    }
+
+    #[test]
+    fn test_phase_resets_counters() {
+        let (reporter, events) = collecting_reporter(Duration::ZERO);
+
+        reporter.start_phase(ProgressPhase::ExactStage, Some(2));
+        reporter.advance(5, None);
+        reporter.error(Some("ødelagt.jpg"));
+        reporter.start_phase(ProgressPhase::Clustering, None);
+
+        let events = events.lock().unwrap();
+        let exact_last = &events[2];
+        assert_eq!(exact_last.phase, ProgressPhase::ExactStage);
+        assert_eq!(exact_last.errors, 1);
+        assert_eq!(exact_last.current_file.as_deref(), Some("ødelagt.jpg"));
+
+        let clustering = events.last().unwrap();
+        assert_eq!(clustering.phase, ProgressPhase::Clustering);
+        assert_eq!(clustering.done, 0);
+        assert_eq!(clustering.errors, 0);
+    }
+
+    #[test]
+    fn test_estimate_eta() {
+        assert_eq!(estimate_eta(Duration::from_secs(10), 10, Some(30)), Some(20.0));
+        assert_eq!(estimate_eta(Duration::from_secs(10), 0, Some(30)), None);
+        assert_eq!(estimate_eta(Duration::from_secs(10), 10, None), None);
+    }
+
+    #[test]
+    fn test_disabled_reporter_is_silent() {
+        let reporter = ProgressReporter::disabled();
+        reporter.start_phase(ProgressPhase::Sort, Some(1));
+        reporter.advance(1, Some("x.jpg"));
+        reporter.finish_phase();
+    }
+}
@@ -0,0 +1,385 @@
+//! Kamera-RAW (CR2, CR3, NEF, ARW, DNG, RAF, ORF)
+//!
+//! RAW-data dekodes ikke. Alle formatene har en innebygd JPEG-forhåndsvisning,
+//! og den brukes til thumbnails og hashing. EXIF leses fra TIFF-strukturen
+//! (eller tilsvarende bokser i CR3/RAF) for datering.
+
+use image::DynamicImage;
+use memmap2::Mmap;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::Cursor;
+use std::path::Path;
+
+/// Maks antall IFD-er som følges, i tilfelle ødelagte filer med løkker i IFD-kjeden
+const MAX_IFDS: usize = 32;
+
+/// CR3-forhåndsvisningen (PRVW) ligger tidlig i filen, foran selve bildedataene
+const CR3_SEARCH_LEN: usize = 8 * 1024 * 1024;
+
+/// Hvordan RAW-filen er bygget opp
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RawLayout {
+    /// CR2, NEF, ARW, DNG og ORF (ORF har egen magic, men ellers vanlig TIFF)
+    Tiff,
+    /// Fujifilm RAF: egen header med peker til en vanlig JPEG med EXIF
+    Raf,
+    /// Canon CR3: ISO-BMFF med EXIF i CMT-bokser og forhåndsvisning i PRVW
+    Cr3,
+}
+
+fn layout(data: &[u8]) -> Option<RawLayout> {
+    match data.get(..4)? {
+        b"II*\0" | b"MM\0*" | b"IIRO" | b"IIRS" | b"MMOR" => Some(RawLayout::Tiff),
+        _ if data.starts_with(b"FUJIFILMCCD-RAW") => Some(RawLayout::Raf),
+        _ if data.get(4..12) == Some(b"ftypcrx ") => Some(RawLayout::Cr3),
+        _ => None,
+    }
+}
+
+fn map_file(path: &Path) -> Option<Mmap> {
+    let file = File::open(path).ok()?;
+    // Safety: filen åpnes kun for lesing, og mappingen lever bare i dette kallet
+    unsafe { Mmap::map(&file) }.ok()
+}
+
+/// Henter den største innebygde JPEG-forhåndsvisningen
+pub fn extract_preview(path: &Path) -> Option<Vec<u8>> {
+    let data = map_file(path)?;
+    preview_from_bytes(&data).map(|jpeg| jpeg.to_vec())
+}
+
+/// Dekoder forhåndsvisningen til et bilde
+pub fn load_preview(path: &Path) -> Option<DynamicImage> {
+    let jpeg = extract_preview(path)?;
+    image::load_from_memory_with_format(&jpeg, image::ImageFormat::Jpeg).ok()
+}
+
+/// Størrelsen på forhåndsvisningen, uten å dekode den
+pub fn preview_dimensions(path: &Path) -> Option<(u32, u32)> {
+    let data = map_file(path)?;
+    let jpeg = preview_from_bytes(&data)?;
+    image::ImageReader::with_format(Cursor::new(jpeg), image::ImageFormat::Jpeg)
+        .into_dimensions()
+        .ok()
+}
+
+fn preview_from_bytes(data: &[u8]) -> Option<&[u8]> {
+    match layout(data)? {
+        RawLayout::Tiff => tiff_previews(data).into_iter().max_by_key(|jpeg| jpeg.len()),
+        RawLayout::Raf => raf_jpeg(data),
+        RawLayout::Cr3 => cr3_preview(data),
+    }
+}
+
+/// Leser EXIF fra en RAW-fil
+pub fn read_exif(path: &Path) -> Option<exif::Exif> {
+    let data = map_file(path)?;
+    let reader = exif::Reader::new();
+
+    match layout(&data)? {
+        RawLayout::Tiff => {
+            // Bare starten av filen med metadataene kopieres, ikke rådataene etter dem
+            let extent = exif_extent(&Tiff { data: &data, little_endian: data.starts_with(b"II") });
+            let mut tiff = data[..extent].to_vec();
+            // ORF bruker "IIRO"/"MMOR" i stedet for TIFF-magic; resten er standard TIFF
+            if tiff.starts_with(b"II") {
+                tiff[2..4].copy_from_slice(&[0x2A, 0x00]);
+            } else {
+                tiff[2..4].copy_from_slice(&[0x00, 0x2A]);
+            }
+            reader.read_raw(tiff).ok()
+        }
+        RawLayout::Raf => {
+            let jpeg = raf_jpeg(&data)?;
+            reader.read_from_container(&mut Cursor::new(jpeg)).ok()
+        }
+        RawLayout::Cr3 => {
+            // CMT2 er EXIF-IFD (DateTimeOriginal), CMT1 er IFD0 (DateTime)
+            ["CMT2", "CMT1"]
+                .iter()
+                .filter_map(|name| bmff_box(&data, name.as_bytes(), CR3_SEARCH_LEN))
+                .find_map(|tiff| reader.read_raw(tiff.to_vec()).ok())
+        }
+    }
+}
+
+/// Enkel TIFF-leser med riktig byte-rekkefølge
+struct Tiff<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+}
+
+impl<'a> Tiff<'a> {
+    fn u16(&self, pos: usize) -> Option<u16> {
+        let b: [u8; 2] = self.data.get(pos..pos + 2)?.try_into().ok()?;
+        Some(if self.little_endian { u16::from_le_bytes(b) } else { u16::from_be_bytes(b) })
+    }
+
+    fn u32(&self, pos: usize) -> Option<u32> {
+        let b: [u8; 4] = self.data.get(pos..pos + 4)?.try_into().ok()?;
+        Some(if self.little_endian { u32::from_le_bytes(b) } else { u32::from_be_bytes(b) })
+    }
+
+    /// Første verdi i en IFD-oppføring (SHORT eller LONG)
+    fn entry_value(&self, entry: usize) -> Option<u32> {
+        match self.u16(entry + 2)? {
+            3 => self.u16(entry + 8).map(u32::from),
+            4 | 13 => self.u32(entry + 8),
+            _ => None,
+        }
+    }
+
+    /// Alle LONG-verdier i en oppføring (brukes for SubIFDs)
+    fn entry_values(&self, entry: usize) -> Vec<u32> {
+        let count = self.u32(entry + 4).unwrap_or(0) as usize;
+        if count <= 1 {
+            return self.entry_value(entry).into_iter().collect();
+        }
+        let start = match self.u32(entry + 8) {
+            Some(offset) => offset as usize,
+            None => return Vec::new(),
+        };
+        (0..count.min(MAX_IFDS)).filter_map(|i| self.u32(start + i * 4)).collect()
+    }
+
+    fn slice(&self, offset: Option<u32>, len: Option<u32>) -> Option<&'a [u8]> {
+        let start = offset? as usize;
+        self.data.get(start..start.checked_add(len? as usize)?)
+    }
+}
+
+/// Finner alle JPEG-er i IFD-kjeden og SubIFD-ene som kan dekodes
+fn tiff_previews(data: &[u8]) -> Vec<&[u8]> {
+    let tiff = Tiff { data, little_endian: data.starts_with(b"II") };
+    let mut queue: Vec<usize> = tiff.u32(4).map(|o| o as usize).into_iter().collect();
+    let mut visited = HashSet::new();
+    let mut previews = Vec::new();
+
+    while let Some(ifd) = queue.pop() {
+        if ifd == 0 || visited.len() >= MAX_IFDS || !visited.insert(ifd) {
+            continue;
+        }
+        let count = match tiff.u16(ifd) {
+            Some(count) => count as usize,
+            None => continue,
+        };
+
+        let (mut jpeg_offset, mut jpeg_len) = (None, None);
+        let (mut strip_offset, mut strip_len, mut compression) = (None, None, None);
+
+        for i in 0..count {
+            let entry = ifd + 2 + i * 12;
+            match tiff.u16(entry) {
+                Some(0x0201) => jpeg_offset = tiff.entry_value(entry),
+                Some(0x0202) => jpeg_len = tiff.entry_value(entry),
+                Some(0x0103) => compression = tiff.entry_value(entry),
+                // Kun én strip - flere strips er rådata, ikke en JPEG
+                Some(0x0111) if tiff.u32(entry + 4) == Some(1) => strip_offset = tiff.entry_value(entry),
+                Some(0x0117) if tiff.u32(entry + 4) == Some(1) => strip_len = tiff.entry_value(entry),
+                Some(0x014A) => queue.extend(tiff.entry_values(entry).into_iter().map(|o| o as usize)),
+                _ => {}
+            }
+        }
+
+        previews.extend(tiff.slice(jpeg_offset, jpeg_len));
+        if matches!(compression, Some(6) | Some(7)) {
+            previews.extend(tiff.slice(strip_offset, strip_len));
+        }
+        if let Some(next) = tiff.u32(ifd + 2 + count * 12) {
+            queue.push(next as usize);
+        }
+    }
+
+    previews.retain(|jpeg| is_decodable_jpeg(jpeg));
+    previews
+}
+
+/// Hvor mye av starten av filen EXIF-leseren trenger: IFD-kjeden, EXIF-, GPS- og
+/// Interop-IFD-ene og verdiene som ligger utenfor oppføringene. kamadak-exif krever at
+/// alle disse er med, men ikke bilde- og rådataene som følger etter (ofte 25-100 MB).
+fn exif_extent(tiff: &Tiff) -> usize {
+    // (IFD, om neste IFD i kjeden skal følges) - under-IFD-ene har ingen kjede
+    let mut queue: Vec<(usize, bool)> = tiff.u32(4).map(|o| (o as usize, true)).into_iter().collect();
+    let mut visited = HashSet::new();
+    let mut end = 8;
+
+    while let Some((ifd, in_chain)) = queue.pop() {
+        if ifd == 0 || visited.len() >= MAX_IFDS || !visited.insert(ifd) {
+            continue;
+        }
+        let count = match tiff.u16(ifd) {
+            Some(count) => count as usize,
+            None => continue,
+        };
+        let next = ifd + 2 + count * 12;
+        end = end.max(next + 4);
+
+        for i in 0..count {
+            let entry = ifd + 2 + i * 12;
+            let len = tiff
+                .u16(entry + 2)
+                .map(tiff_type_size)
+                .zip(tiff.u32(entry + 4))
+                .map_or(0, |(size, count)| size.saturating_mul(count as usize));
+            if len > 4 {
+                if let Some(offset) = tiff.u32(entry + 8) {
+                    end = end.max((offset as usize).saturating_add(len));
+                }
+            }
+            if matches!(tiff.u16(entry), Some(0x8769 | 0x8825 | 0xA005)) {
+                queue.extend(tiff.entry_value(entry).map(|child| (child as usize, false)));
+            }
+        }
+
+        if in_chain {
+            queue.extend(tiff.u32(next).map(|o| (o as usize, true)));
+        }
+    }
+
+    end.min(tiff.data.len())
+}
+
+/// Byte per verdi for en TIFF-type, 0 for ukjente typer (som i kamadak-exif)
+fn tiff_type_size(typ: u16) -> usize {
+    match typ {
+        1 | 2 | 6 | 7 => 1,
+        3 | 8 => 2,
+        4 | 9 | 11 => 4,
+        5 | 10 | 12 => 8,
+        _ => 0,
+    }
+}
+
+/// RAF-header: offset og lengde til JPEG-en ligger big-endian på byte 84 og 88
+fn raf_jpeg(data: &[u8]) -> Option<&[u8]> {
+    let offset = u32::from_be_bytes(data.get(84..88)?.try_into().ok()?) as usize;
+    let len = u32::from_be_bytes(data.get(88..92)?.try_into().ok()?) as usize;
+    let jpeg = data.get(offset..offset.checked_add(len)?)?;
+    is_decodable_jpeg(jpeg).then_some(jpeg)
+}
+
+/// CR3: PRVW-boksen inneholder en liten header etterfulgt av en JPEG
+fn cr3_preview(data: &[u8]) -> Option<&[u8]> {
+    let content = bmff_box(data, b"PRVW", CR3_SEARCH_LEN)?;
+    let start = content.windows(3).take(64).position(|w| w == [0xFF, 0xD8, 0xFF])?;
+    let jpeg = &content[start..];
+    is_decodable_jpeg(jpeg).then_some(jpeg)
+}
+
+/// Innholdet i første boks med gitt navn (uten størrelse og navn)
+fn bmff_box<'a>(data: &'a [u8], name: &[u8], search_len: usize) -> Option<&'a [u8]> {
+    let haystack = &data[..data.len().min(search_len)];
+    let pos = haystack.windows(4).position(|w| w == name)?;
+    let size = u32::from_be_bytes(data.get(pos.checked_sub(4)?..pos)?.try_into().ok()?) as usize;
+    data.get(pos + 4..(pos - 4).checked_add(size)?)
+}
+
+/// Sjekker at dataene er en baseline/progressiv JPEG. Tapsfri JPEG (SOF3),
+/// som CR2 og DNG bruker for selve rådataene, kan ikke dekodes av `image`.
+fn is_decodable_jpeg(data: &[u8]) -> bool {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return false;
+    }
+    let mut pos = 2;
+    while pos + 4 <= data.len() {
+        if data[pos] != 0xFF {
+            return false;
+        }
+        match data[pos + 1] {
+            0xC0..=0xC2 => return true,
+            0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF | 0xDA => return false,
+            _ => {}
+        }
+        let len = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        pos += 2 + len;
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn jpeg_bytes(width: u32, height: u32) -> Vec<u8> {
+        let mut buf = std::io::Cursor::new(Vec::new());
+        DynamicImage::new_rgb8(width, height)
+            .write_to(&mut buf, image::ImageFormat::Jpeg)
+            .unwrap();
+        buf.into_inner()
+    }
+
+    /// Minimal little-endian TIFF: IFD0 med JPEG-peker og EXIF-IFD med DateTimeOriginal
+    fn tiff_with_preview(magic: &[u8; 4], jpeg: &[u8]) -> Vec<u8> {
+        let entry = |tag: u16, typ: u16, count: u32, value: u32| {
+            let mut e = tag.to_le_bytes().to_vec();
+            e.extend(typ.to_le_bytes());
+            e.extend(count.to_le_bytes());
+            e.extend(value.to_le_bytes());
+            e
+        };
+        let (ifd0, exif_ifd, date, jpeg_start) = (8u32, 50u32, 68u32, 88u32);
+
+        let mut data = magic.to_vec();
+        data.extend(ifd0.to_le_bytes());
+        data.extend(3u16.to_le_bytes());
+        data.extend(entry(0x0201, 4, 1, jpeg_start));
+        data.extend(entry(0x0202, 4, 1, jpeg.len() as u32));
+        data.extend(entry(0x8769, 4, 1, exif_ifd));
+        data.extend(0u32.to_le_bytes());
+        data.extend(1u16.to_le_bytes());
+        data.extend(entry(0x9003, 2, 20, date));
+        data.extend(0u32.to_le_bytes());
+        data.extend(b"2021:05:06 07:08:09\0");
+        assert_eq!(data.len(), jpeg_start as usize);
+        data.extend(jpeg);
+        data
+    }
+
+    #[test]
+    fn test_tiff_preview_and_exif() {
+        let dir = tempdir().unwrap();
+        let jpeg = jpeg_bytes(32, 16);
+        for (name, magic) in [("a.nef", b"II*\0"), ("b.orf", b"IIRO")] {
+            let path = dir.path().join(name);
+            fs::write(&path, tiff_with_preview(magic, &jpeg)).unwrap();
+
+            assert_eq!(extract_preview(&path).unwrap(), jpeg);
+            assert_eq!(load_preview(&path).unwrap().to_rgb8().dimensions(), (32, 16));
+
+            // EXIF-leseren får bare metadataene, ikke JPEG-en etter dem
+            let data = fs::read(&path).unwrap();
+            assert_eq!(exif_extent(&Tiff { data: &data, little_endian: true }), 88);
+
+            let exif = read_exif(&path).unwrap();
+            let field = exif.get_field(exif::Tag::DateTimeOriginal, exif::In::PRIMARY).unwrap();
+            match &field.value {
+                exif::Value::Ascii(values) => assert_eq!(values[0], b"2021:05:06 07:08:09"),
+                other => panic!("Uventet verdi: {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_raf_preview() {
+        let jpeg = jpeg_bytes(8, 8);
+        let mut data = b"FUJIFILMCCD-RAW 0201FF383501".to_vec();
+        data.resize(84, 0);
+        data.extend(100u32.to_be_bytes());
+        data.extend((jpeg.len() as u32).to_be_bytes());
+        data.resize(100, 0);
+        data.extend(&jpeg);
+
+        assert_eq!(preview_from_bytes(&data), Some(jpeg.as_slice()));
+    }
+
+    #[test]
+    fn test_lossless_jpeg_is_skipped() {
+        // SOI + SOF3 (tapsfri) - rådata i CR2/DNG
+        assert!(!is_decodable_jpeg(&[0xFF, 0xD8, 0xFF, 0xC3, 0x00, 0x02]));
+        assert!(is_decodable_jpeg(&jpeg_bytes(4, 4)));
+        assert_eq!(preview_from_bytes(b"not a raw file"), None);
+    }
+}
@@ -1,7 +1,16 @@
 //! Filskanner for å finne bilder i mapper
 
-use std::path::Path;
-use walkdir::WalkDir;
+use crate::services::jobs::{CancelToken, CANCELLED_MESSAGE};
+use crate::services::media::{self, MediaKind};
+use crate::services::progress::{ProgressPhase, ProgressReporter};
+use crate::services::{heif, sidecar, signature};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use walkdir::{DirEntry, WalkDir};
 
 /// Representerer et bilde funnet under skanning
 #[derive(Debug, Clone)]
@@ -10,23 +19,354 @@ pub struct ImageInfo {
     pub filename: String,
     pub extension: String,
     pub size_bytes: u64,
+    /// Enhet + inode, brukt til å kjenne igjen hardlenker og samme fil via symlenker
+    pub file_id: Option<FileId>,
+    /// Andre stier til samme fil (hardlenker, symlenkede mapper, bind mounts)
+    pub aliases: Vec<String>,
+    /// Formatet funnet i filinnholdet (kun når `sniff_content` er på)
+    pub detected_format: Option<String>,
+    /// Filendelsen stemmer ikke med innholdet (f.eks. HEIC lagret som .jpg)
+    pub extension_mismatch: bool,
+    pub kind: MediaKind,
+    /// Sist endret, sekunder siden epoch
+    pub modified: Option<i64>,
+    /// Opprettet i filsystemet, sekunder siden epoch (ikke tilgjengelig på alle filsystemer)
+    pub created: Option<i64>,
+    /// Bredde og høyde fra filheaderen (kun når `read_headers` er på)
+    pub dimensions: Option<(u32, u32)>,
+    pub has_sidecar: bool,
 }
 
-/// Støttede bildeformater
-const SUPPORTED_EXTENSIONS: &[&str] = &[
-    // Bilder
-    "jpg", "jpeg", "png", "gif", "bmp", "webp", "tiff", "tif", "ico", "heic", "heif",
-    // Video
-    "mp4", "mov", "avi", "mkv", "webm", "wmv", "m4v",
+/// Identifiserer en fil uavhengig av stien: to stier med lik `FileId` deler samme data.
+///
+/// Kun implementert på Unix. På Windows er `from_metadata` alltid None, fordi volum-
+/// serienummer og filindeks ikke er stabile i `std` ennå. Der gjenkjennes verken
+/// hardlenker eller mapper som nås via flere stier, og hver sti behandles som en egen
+/// fil. Symlenkeløkker oppdages fortsatt av WalkDir.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FileId {
+    pub device: u64,
+    pub inode: u64,
+}
+
+impl FileId {
+    /// Henter enhet og inode fra metadata. Se typen for hvorfor dette er None utenfor Unix.
+    #[cfg(unix)]
+    pub fn from_metadata(metadata: &Metadata) -> Option<Self> {
+        use std::os::unix::fs::MetadataExt;
+        Some(Self {
+            device: metadata.dev(),
+            inode: metadata.ino(),
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn from_metadata(_metadata: &Metadata) -> Option<Self> {
+        None
+    }
+
+    /// Som `from_metadata`, men følger symlenker fra en sti
+    pub fn of(path: &Path) -> Option<Self> {
+        std::fs::metadata(path).ok().as_ref().and_then(Self::from_metadata)
+    }
+}
+
+/// Standard antall filer per batch ved strømming til frontend
+pub const DEFAULT_BATCH_SIZE: usize = 500;
+
+/// Maks tid en ufullstendig batch holdes tilbake (trege nettverksdisker finner få filer i sekundet)
+pub const BATCH_FLUSH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Oppsummering etter en strømmende skanning
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScanTotals {
+    /// Antall unike filer (hardlenker telles én gang)
+    pub image_count: usize,
+    pub total_size_bytes: u64,
+    /// Stier som peker til samme fil. Første sti er den som ble rapportert, resten er aliaser.
+    pub hardlink_groups: Vec<Vec<String>>,
+    /// Symlenker som peker tilbake til en overordnet mappe (hoppes over)
+    pub symlink_loops: Vec<String>,
+}
+
+/// Mapper som alltid hoppes over når `use_default_excludes` er på:
+/// versjonskontroll, Synology/QNAP-thumbnails og papirkurver, OS-mapper og vår egen cache.
+pub const DEFAULT_EXCLUDES: &[&str] = &[
+    ".git",
+    "node_modules",
+    "@eaDir",
+    "#recycle",
+    "#snapshot",
+    "@Recycle",
+    ".@__thumb",
+    ".thumbnails",
+    ".Trash-*",
+    "$RECYCLE.BIN",
+    "System Volume Information",
+    ".Spotlight-V100",
+    ".fseventsd",
+    "imagesorter-thumbnails",
 ];
 
+/// Filtre for skanning. Glob-mønstre matches mot stien relativt til rotmappen
+/// og mot fil-/mappenavnet alene, uten hensyn til store/små bokstaver.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ScanOptions {
+    /// Filer og mapper som matcher hoppes over (mapper hoppes over i sin helhet)
+    pub exclude: Vec<String>,
+    /// Hvis ikke tom: kun filer som matcher minst ett mønster tas med
+    pub include: Vec<String>,
+    /// Maks dybde under rotmappen (1 = kun filer direkte i mappen)
+    pub max_depth: Option<usize>,
+    pub min_size_bytes: Option<u64>,
+    pub max_size_bytes: Option<u64>,
+    /// Ta med skjulte filer og mapper (navn som starter med '.'). På som standard, slik
+    /// skanningen alltid har vært - kjente søppelmapper som `.git` fanges av `DEFAULT_EXCLUDES`.
+    pub include_hidden: bool,
+    pub follow_symlinks: bool,
+    /// Hopp over kjente søppelmapper, se `DEFAULT_EXCLUDES`
+    pub use_default_excludes: bool,
+    /// Les filsignaturen for å finne det faktiske formatet. Tar også med filer med
+    /// ukjent eller manglende endelse når innholdet er et støttet format.
+    pub sniff_content: bool,
+    /// Les bildeheaderen for dimensjoner og for å gjenkjenne animerte bilder
+    pub read_headers: bool,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            exclude: Vec::new(),
+            include: Vec::new(),
+            max_depth: None,
+            min_size_bytes: None,
+            max_size_bytes: None,
+            include_hidden: true,
+            follow_symlinks: true,
+            use_default_excludes: true,
+            sniff_content: false,
+            read_headers: false,
+        }
+    }
+}
+
+/// Kompilerte filtre fra `ScanOptions`
+struct ScanFilter {
+    exclude: GlobSet,
+    include: Option<GlobSet>,
+    include_hidden: bool,
+    min_size: u64,
+    max_size: u64,
+}
+
+impl ScanFilter {
+    fn new(options: &ScanOptions) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut excludes: Vec<&str> = options.exclude.iter().map(|s| s.as_str()).collect();
+        if options.use_default_excludes {
+            excludes.extend_from_slice(DEFAULT_EXCLUDES);
+        }
+
+        let include = if options.include.is_empty() {
+            None
+        } else {
+            Some(build_globset(options.include.iter().map(|s| s.as_str()))?)
+        };
+
+        Ok(Self {
+            exclude: build_globset(excludes.into_iter())?,
+            include,
+            include_hidden: options.include_hidden,
+            min_size: options.min_size_bytes.unwrap_or(0),
+            max_size: options.max_size_bytes.unwrap_or(u64::MAX),
+        })
+    }
+
+    /// Om en fil eller mappe skal hoppes over (rotmappen selv filtreres aldri)
+    fn is_excluded(&self, entry: &DirEntry, root: &Path) -> bool {
+        if entry.depth() == 0 {
+            return false;
+        }
+
+        let name = entry.file_name().to_string_lossy();
+        if !self.include_hidden && name.starts_with('.') {
+            return true;
+        }
+
+        let relative = entry.path().strip_prefix(root).unwrap_or(entry.path());
+        self.exclude.is_match(relative) || self.exclude.is_match(name.as_ref())
+    }
+
+    fn is_included(&self, entry: &DirEntry, root: &Path, size: u64) -> bool {
+        if size < self.min_size || size > self.max_size {
+            return false;
+        }
+
+        match &self.include {
+            Some(include) => {
+                let relative = entry.path().strip_prefix(root).unwrap_or(entry.path());
+                include.is_match(relative) || include.is_match(entry.file_name())
+            }
+            None => true,
+        }
+    }
+}
+
+fn build_globset<'a>(patterns: impl Iterator<Item = &'a str>) -> Result<GlobSet, Box<dyn std::error::Error>> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = GlobBuilder::new(pattern)
+            .case_insensitive(true)
+            .build()
+            .map_err(|e| format!("Ugyldig mønster '{}': {}", pattern, e))?;
+        builder.add(glob);
+    }
+    Ok(builder.build()?)
+}
+
 /// Sjekker om en filendelse er støttet
 pub fn is_supported_extension(ext: &str) -> bool {
-    SUPPORTED_EXTENSIONS.contains(&ext.to_lowercase().as_str())
+    media::kind_for_extension(ext).is_some()
+}
+
+fn unix_seconds(time: std::io::Result<SystemTime>) -> Option<i64> {
+    let time = time.ok()?;
+    Some(match time.duration_since(UNIX_EPOCH) {
+        Ok(after) => after.as_secs() as i64,
+        Err(before) => -(before.duration().as_secs() as i64),
+    })
+}
+
+/// Avgjør om en fil skal tas med. Returnerer formatet funnet i innholdet (hvis sniffing
+/// er på) og om endelsen avviker fra det, eller None hvis filen ikke er et støttet format.
+fn classify_file(path: &Path, extension: &str, sniff: bool) -> Option<(Option<&'static str>, bool)> {
+    let supported = is_supported_extension(extension);
+    if !sniff {
+        return supported.then_some((None, false));
+    }
+
+    match signature::detect_format(path) {
+        Some(format) if is_supported_extension(format) => {
+            Some((Some(format), !signature::extension_matches(extension, format)))
+        }
+        // Ukjent innhold: stol på endelsen (f.eks. formater uten kjent signatur)
+        _ => supported.then_some((None, false)),
+    }
 }
 
 /// Skanner en mappe rekursivt og returnerer alle bilder
 pub fn scan_directory(path: &str) -> Result<Vec<ImageInfo>, Box<dyn std::error::Error>> {
+    scan_directory_tracked(path, &ScanOptions::default(), &CancelToken::new(), &ProgressReporter::disabled())
+}
+
+/// Som `scan_directory`, men rapporterer fremdrift og avbryter med feil når `cancel` settes
+pub fn scan_directory_tracked(
+    path: &str,
+    options: &ScanOptions,
+    cancel: &CancelToken,
+    progress: &ProgressReporter,
+) -> Result<Vec<ImageInfo>, Box<dyn std::error::Error>> {
+    let mut images = Vec::new();
+    let totals =
+        scan_directory_streaming(path, options, cancel, progress, DEFAULT_BATCH_SIZE, |batch| images.extend(batch))?;
+
+    // Aliasene er først kjent når hele treet er gått gjennom
+    let mut aliases: HashMap<String, Vec<String>> = totals
+        .hardlink_groups
+        .into_iter()
+        .map(|mut group| (group.remove(0), group))
+        .collect();
+    for image in &mut images {
+        if let Some(paths) = aliases.remove(&image.path) {
+            image.aliases = paths;
+        }
+    }
+
+    Ok(images)
+}
+
+/// Slår sammen stier som peker til samme fil (samme enhet + inode).
+/// Returnerer de unike stiene (første forekomst beholdes) og gruppene med mer enn én sti.
+/// Stier som ikke kan leses beholdes uendret.
+pub fn collapse_hardlinks(paths: Vec<String>) -> (Vec<String>, Vec<Vec<String>>) {
+    let mut unique = Vec::with_capacity(paths.len());
+    let mut groups: Vec<Vec<String>> = Vec::new();
+    let mut seen: HashMap<FileId, usize> = HashMap::new();
+
+    for path in paths {
+        match FileId::of(Path::new(&path)) {
+            Some(id) => match seen.get(&id) {
+                Some(&group) => groups[group].push(path),
+                None => {
+                    seen.insert(id, groups.len());
+                    groups.push(vec![path.clone()]);
+                    unique.push(path);
+                }
+            },
+            None => unique.push(path),
+        }
+    }
+
+    groups.retain(|group| group.len() > 1);
+    (unique, groups)
+}
+
+/// Filnavnene i mappene skanneren står i, så sidecar- og Live Photo-oppslag er én
+/// `read_dir` per mappe i stedet for flere `exists()` per fil. Bare mappene på stien
+/// fra rotmappen holdes på, så minnebruken følger dybden og ikke antall mapper.
+#[derive(Default)]
+struct DirectoryListings {
+    stack: Vec<DirectoryNames>,
+}
+
+/// Filnavn i én mappe, med små bokstaver
+struct DirectoryNames {
+    dir: PathBuf,
+    names: HashSet<String>,
+}
+
+impl DirectoryNames {
+    /// Sammenligner uten hensyn til store/små bokstaver (IMG_0001.XMP, IMG_0001.xmp)
+    fn contains(&self, name: &str) -> bool {
+        self.names.contains(&name.to_lowercase())
+    }
+}
+
+impl DirectoryListings {
+    fn listing(&mut self, dir: &Path) -> &DirectoryNames {
+        while self.stack.last().is_some_and(|top| !dir.starts_with(&top.dir)) {
+            self.stack.pop();
+        }
+        let already_read = self.stack.last().is_some_and(|top| top.dir == dir);
+        if !already_read {
+            let names = std::fs::read_dir(dir)
+                .map(|entries| {
+                    entries
+                        .filter_map(Result::ok)
+                        .map(|entry| entry.file_name().to_string_lossy().to_lowercase())
+                        .collect()
+                })
+                .unwrap_or_default();
+            self.stack.push(DirectoryNames { dir: dir.to_path_buf(), names });
+        }
+        self.stack.last().unwrap()
+    }
+}
+
+/// Skanner rekursivt og sender funne filer i batcher underveis
+/// En batch sendes når den har `batch_size` filer, eller når `BATCH_FLUSH_INTERVAL`
+/// har gått siden forrige batch. Siste (ufullstendige) batch sendes før retur.
+pub fn scan_directory_streaming<F>(
+    path: &str,
+    options: &ScanOptions,
+    cancel: &CancelToken,
+    progress: &ProgressReporter,
+    batch_size: usize,
+    mut on_batch: F,
+) -> Result<ScanTotals, Box<dyn std::error::Error>>
+where
+    F: FnMut(Vec<ImageInfo>),
+{
     let path = Path::new(path);
 
     if !path.exists() {
@@ -37,35 +377,151 @@ pub fn scan_directory(path: &str) -> Result<Vec<ImageInfo>, Box<dyn std::error::
         return Err(format!("Stien er ikke en mappe: {}", path.display()).into());
     }
 
-    let mut images = Vec::new();
+    let filter = ScanFilter::new(options)?;
+    let batch_size = batch_size.max(1);
+    let mut batch = Vec::with_capacity(batch_size.min(DEFAULT_BATCH_SIZE));
+    let mut last_flush = Instant::now();
+    let mut totals = ScanTotals::default();
+    progress.start_phase(ProgressPhase::Scan, None);
+
+    let mut walker = WalkDir::new(path).follow_links(options.follow_symlinks);
+    if let Some(depth) = options.max_depth {
+        walker = walker.max_depth(depth);
+    }
+
+    // Filer og mapper som allerede er sett, for å oppdage hardlenker og mapper som
+    // nås flere ganger (symlenke eller bind mount til en mappe som skannes fra før)
+    let mut seen_files: HashMap<FileId, usize> = HashMap::new();
+    let mut link_groups: Vec<Vec<String>> = Vec::new();
+    let mut seen_dirs: HashSet<FileId> = HashSet::new();
+    let mut symlink_loops = Vec::new();
+    let mut directories = DirectoryListings::default();
+
+    let entries = walker.into_iter().filter_entry(|entry| {
+        if filter.is_excluded(entry, path) {
+            return false;
+        }
+        if entry.file_type().is_dir() {
+            // Mappen er allerede skannet via en annen sti - hopp over hele undertreet
+            if let Some(id) = entry.metadata().ok().as_ref().and_then(FileId::from_metadata) {
+                return seen_dirs.insert(id);
+            }
+        }
+        true
+    });
+
+    for entry in entries {
+        if cancel.is_cancelled() {
+            return Err(CANCELLED_MESSAGE.into());
+        }
+
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                // WalkDir oppdager symlenker som peker til en overordnet mappe
+                if err.loop_ancestor().is_some() {
+                    if let Some(loop_path) = err.path() {
+                        symlink_loops.push(loop_path.to_string_lossy().to_string());
+                    }
+                }
+                continue;
+            }
+        };
 
-    for entry in WalkDir::new(path).follow_links(true).into_iter().flatten() {
         let entry_path = entry.path();
 
         if entry_path.is_file() {
-            if let Some(ext) = entry_path.extension() {
-                let ext_lower = ext.to_string_lossy().to_lowercase();
+            let ext_lower = entry_path
+                .extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
 
-                if SUPPORTED_EXTENSIONS.contains(&ext_lower.as_str()) {
-                    if let Ok(metadata) = entry.metadata() {
+            // Billig endelsessjekk før vi eventuelt åpner filen for sniffing
+            if options.sniff_content || is_supported_extension(&ext_lower) {
+                if let Some(metadata) = entry
+                    .metadata()
+                    .ok()
+                    .filter(|m| filter.is_included(&entry, path, m.len()))
+                {
+                    if let Some((detected_format, extension_mismatch)) =
+                        classify_file(entry_path, &ext_lower, options.sniff_content)
+                    {
                         let filename = entry_path
                             .file_name()
                             .map(|s| s.to_string_lossy().to_string())
                             .unwrap_or_default();
 
-                        images.push(ImageInfo {
-                            path: entry_path.to_string_lossy().to_string(),
+                        let path_str = entry_path.to_string_lossy().to_string();
+                        let file_id = FileId::from_metadata(&metadata);
+
+                        if let Some(id) = file_id {
+                            if let Some(&group) = seen_files.get(&id) {
+                                link_groups[group].push(path_str);
+                                continue;
+                            }
+                            seen_files.insert(id, link_groups.len());
+                            link_groups.push(vec![path_str.clone()]);
+                        }
+
+                        // Innholdet avgjør typen når endelsen er feil
+                        let kind_ext = match detected_format {
+                            Some(format) if extension_mismatch => format,
+                            _ => ext_lower.as_str(),
+                        };
+                        let siblings = directories.listing(entry_path.parent().unwrap_or(path));
+                        let kind = media::classify_with_siblings(entry_path, kind_ext, options.read_headers, |name| {
+                            siblings.contains(name)
+                        })
+                        .unwrap_or_default();
+                        let has_sidecar = sidecar::has_sidecar(entry_path, |name| siblings.contains(name));
+                        let dimensions = if !options.read_headers || kind.is_video() || kind == MediaKind::Raw {
+                            None
+                        } else if heif::HEIF_EXTENSIONS.contains(&kind_ext) {
+                            heif::dimensions(entry_path)
+                        } else {
+                            image::image_dimensions(entry_path).ok()
+                        };
+
+                        progress.advance(metadata.len(), Some(&path_str));
+                        totals.image_count += 1;
+                        totals.total_size_bytes += metadata.len();
+
+                        batch.push(ImageInfo {
+                            path: path_str,
                             filename,
                             extension: ext_lower,
                             size_bytes: metadata.len(),
+                            file_id,
+                            aliases: Vec::new(),
+                            detected_format: detected_format.map(str::to_string),
+                            extension_mismatch,
+                            kind,
+                            modified: unix_seconds(metadata.modified()),
+                            created: unix_seconds(metadata.created()),
+                            dimensions,
+                            has_sidecar,
                         });
                     }
                 }
             }
         }
+
+        if !batch.is_empty() && (batch.len() >= batch_size || last_flush.elapsed() >= BATCH_FLUSH_INTERVAL) {
+            on_batch(std::mem::take(&mut batch));
+            last_flush = Instant::now();
+        }
     }
 
-    Ok(images)
+    if !batch.is_empty() {
+        on_batch(batch);
+    }
+
+    link_groups.retain(|group| group.len() > 1);
+    totals.hardlink_groups = link_groups;
+    totals.symlink_loops = symlink_loops;
+
+    progress.finish_phase();
+    Ok(totals)
 }
 
 #[cfg(test)]
@@ -77,15 +533,15 @@ mod tests {
 
     #[test]
     fn test_supported_extensions() {
-        assert!(SUPPORTED_EXTENSIONS.contains(&"jpg"));
-        assert!(SUPPORTED_EXTENSIONS.contains(&"jpeg"));
-        assert!(SUPPORTED_EXTENSIONS.contains(&"png"));
-        assert!(SUPPORTED_EXTENSIONS.contains(&"gif"));
-        assert!(SUPPORTED_EXTENSIONS.contains(&"webp"));
-        assert!(!SUPPORTED_EXTENSIONS.contains(&"txt"));
-        assert!(!SUPPORTED_EXTENSIONS.contains(&"pdf"));
-        assert!(SUPPORTED_EXTENSIONS.contains(&"mp4"));
-        assert!(SUPPORTED_EXTENSIONS.contains(&"mov"));
+        assert!(media::IMAGE_EXTENSIONS.contains(&"jpg"));
+        assert!(media::IMAGE_EXTENSIONS.contains(&"jpeg"));
+        assert!(media::IMAGE_EXTENSIONS.contains(&"png"));
+        assert!(media::IMAGE_EXTENSIONS.contains(&"gif"));
+        assert!(media::IMAGE_EXTENSIONS.contains(&"webp"));
+        assert!(!is_supported_extension("txt"));
+        assert!(!is_supported_extension("pdf"));
+        assert!(media::VIDEO_EXTENSIONS.contains(&"mp4"));
+        assert!(media::VIDEO_EXTENSIONS.contains(&"mov"));
     }
 
     #[test]
@@ -171,6 +627,312 @@ mod tests {
         assert!(img.path.ends_with("testfile.jpg"));
     }
 
+    #[test]
+    fn test_scan_cancelled() {
+        let dir = tempdir().unwrap();
+        File::create(dir.path().join("image1.jpg")).unwrap();
+
+        let cancel = CancelToken::new();
+        cancel.cancel();
+
+        let result = scan_directory_tracked(
+            dir.path().to_str().unwrap(),
+            &ScanOptions::default(),
+            &cancel,
+            &ProgressReporter::disabled(),
+        );
+        assert_eq!(result.unwrap_err().to_string(), CANCELLED_MESSAGE);
+    }
+
+    #[test]
+    fn test_scan_reports_progress() {
+        use crate::services::progress::ProgressEvent;
+        use std::sync::{Arc, Mutex};
+
+        let dir = tempdir().unwrap();
+        File::create(dir.path().join("a.jpg")).unwrap().write_all(b"1234").unwrap();
+        File::create(dir.path().join("b.png")).unwrap().write_all(b"56").unwrap();
+
+        let events: Arc<Mutex<Vec<ProgressEvent>>> = Arc::new(Mutex::new(Vec::new()));
+        let sink_events = events.clone();
+        let progress = ProgressReporter::new("scan", move |e: &ProgressEvent| {
+            sink_events.lock().unwrap().push(e.clone());
+        });
+
+        scan_directory_tracked(dir.path().to_str().unwrap(), &ScanOptions::default(), &CancelToken::new(), &progress)
+            .unwrap();
+
+        let events = events.lock().unwrap();
+        let last = events.last().unwrap();
+        assert!(last.finished);
+        assert_eq!(last.phase, ProgressPhase::Scan);
+        assert_eq!(last.done, 2);
+        assert_eq!(last.bytes_processed, 6);
+    }
+
+    #[test]
+    fn test_scan_streams_batches() {
+        let dir = tempdir().unwrap();
+        for i in 0..5 {
+            File::create(dir.path().join(format!("img{}.jpg", i))).unwrap().write_all(b"abc").unwrap();
+        }
+
+        let mut batches: Vec<usize> = Vec::new();
+        let totals = scan_directory_streaming(
+            dir.path().to_str().unwrap(),
+            &ScanOptions::default(),
+            &CancelToken::new(),
+            &ProgressReporter::disabled(),
+            2,
+            |batch| batches.push(batch.len()),
+        )
+        .unwrap();
+
+        assert_eq!(batches.iter().sum::<usize>(), 5);
+        assert!(batches.iter().all(|&len| len <= 2));
+        assert_eq!(totals.image_count, 5);
+        assert_eq!(totals.total_size_bytes, 15);
+    }
+
+    fn scan_with(dir: &Path, options: &ScanOptions) -> Vec<String> {
+        let mut names: Vec<String> = scan_directory_tracked(
+            dir.to_str().unwrap(),
+            options,
+            &CancelToken::new(),
+            &ProgressReporter::disabled(),
+        )
+        .unwrap()
+        .into_iter()
+        .map(|img| img.filename)
+        .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_default_excludes_and_hidden() {
+        let dir = tempdir().unwrap();
+        for junk in ["@eaDir", ".git", "node_modules", ".hidden"] {
+            fs::create_dir(dir.path().join(junk)).unwrap();
+            File::create(dir.path().join(junk).join(format!("{}.jpg", junk.trim_start_matches(['.', '@'])))).unwrap();
+        }
+        File::create(dir.path().join("photo.jpg")).unwrap();
+        File::create(dir.path().join(".secret.jpg")).unwrap();
+
+        assert_eq!(
+            scan_with(dir.path(), &ScanOptions::default()),
+            vec![".secret.jpg", "hidden.jpg", "photo.jpg"]
+        );
+
+        let visible = ScanOptions {
+            include_hidden: false,
+            ..ScanOptions::default()
+        };
+        assert_eq!(scan_with(dir.path(), &visible), vec!["photo.jpg"]);
+
+        let everything = ScanOptions {
+            use_default_excludes: false,
+            ..ScanOptions::default()
+        };
+        assert_eq!(scan_with(dir.path(), &everything).len(), 6);
+    }
+
+    #[test]
+    fn test_include_exclude_globs() {
+        let dir = tempdir().unwrap();
+        let private = dir.path().join("Privat");
+        fs::create_dir(&private).unwrap();
+        File::create(dir.path().join("a.jpg")).unwrap();
+        File::create(dir.path().join("b.PNG")).unwrap();
+        File::create(private.join("c.jpg")).unwrap();
+
+        let options = ScanOptions {
+            exclude: vec!["privat".to_string()],
+            ..ScanOptions::default()
+        };
+        assert_eq!(scan_with(dir.path(), &options), vec!["a.jpg", "b.PNG"]);
+
+        let options = ScanOptions {
+            include: vec!["*.png".to_string()],
+            ..ScanOptions::default()
+        };
+        assert_eq!(scan_with(dir.path(), &options), vec!["b.PNG"]);
+    }
+
+    #[test]
+    fn test_depth_and_size_limits() {
+        let dir = tempdir().unwrap();
+        let sub = dir.path().join("sub");
+        fs::create_dir(&sub).unwrap();
+        File::create(dir.path().join("small.jpg")).unwrap().write_all(b"1").unwrap();
+        File::create(dir.path().join("large.jpg")).unwrap().write_all(b"1234567890").unwrap();
+        File::create(sub.join("deep.jpg")).unwrap().write_all(b"12345").unwrap();
+
+        let shallow = ScanOptions { max_depth: Some(1), ..ScanOptions::default() };
+        assert_eq!(scan_with(dir.path(), &shallow), vec!["large.jpg", "small.jpg"]);
+
+        let sized = ScanOptions {
+            min_size_bytes: Some(2),
+            max_size_bytes: Some(6),
+            ..ScanOptions::default()
+        };
+        assert_eq!(scan_with(dir.path(), &sized), vec!["deep.jpg"]);
+    }
+
+    #[test]
+    fn test_invalid_glob_is_error() {
+        let dir = tempdir().unwrap();
+        let options = ScanOptions { exclude: vec!["[".to_string()], ..ScanOptions::default() };
+        let result = scan_directory_tracked(
+            dir.path().to_str().unwrap(),
+            &options,
+            &CancelToken::new(),
+            &ProgressReporter::disabled(),
+        );
+        assert!(result.unwrap_err().to_string().contains("Ugyldig mønster"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_hardlinks_are_collapsed() {
+        let dir = tempdir().unwrap();
+        let original = dir.path().join("a.jpg");
+        let link = dir.path().join("b.jpg");
+        fs::write(&original, b"12345").unwrap();
+        fs::hard_link(&original, &link).unwrap();
+        File::create(dir.path().join("c.jpg")).unwrap();
+
+        let images = scan_directory(dir.path().to_str().unwrap()).unwrap();
+        assert_eq!(images.len(), 2);
+
+        let linked = images.iter().find(|img| !img.aliases.is_empty()).unwrap();
+        let paths: Vec<&str> = vec![linked.path.as_str(), linked.aliases[0].as_str()];
+        assert!(paths.contains(&original.to_str().unwrap()));
+        assert!(paths.contains(&link.to_str().unwrap()));
+        assert_eq!(linked.file_id, FileId::of(&original));
+
+        let (unique, groups) = collapse_hardlinks(vec![
+            original.to_string_lossy().to_string(),
+            dir.path().join("c.jpg").to_string_lossy().to_string(),
+            link.to_string_lossy().to_string(),
+        ]);
+        assert_eq!(unique.len(), 2);
+        assert_eq!(groups, vec![vec![original.to_string_lossy().to_string(), link.to_string_lossy().to_string()]]);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_symlink_loop_and_revisited_dir() {
+        let dir = tempdir().unwrap();
+        let photos = dir.path().join("photos");
+        fs::create_dir(&photos).unwrap();
+        File::create(photos.join("a.jpg")).unwrap();
+        // Løkke tilbake til roten, og en ekstra vei inn til samme mappe
+        std::os::unix::fs::symlink(dir.path(), photos.join("loop")).unwrap();
+        std::os::unix::fs::symlink(&photos, dir.path().join("shortcut")).unwrap();
+
+        let totals = scan_directory_streaming(
+            dir.path().to_str().unwrap(),
+            &ScanOptions::default(),
+            &CancelToken::new(),
+            &ProgressReporter::disabled(),
+            DEFAULT_BATCH_SIZE,
+            |_| {},
+        )
+        .unwrap();
+
+        assert_eq!(totals.image_count, 1);
+        // Hvilken vei inn til photos som skannes først avhenger av katalogrekkefølgen
+        assert_eq!(totals.symlink_loops.len(), 1);
+        assert!(totals.symlink_loops[0].ends_with("loop"));
+    }
+
+    #[test]
+    fn test_sniff_content() {
+        let dir = tempdir().unwrap();
+        let png = b"\x89PNG\r\n\x1a\n0000";
+        fs::write(dir.path().join("renamed.jpg"), png).unwrap();
+        fs::write(dir.path().join("export"), png).unwrap();
+        fs::write(dir.path().join("notes.txt"), b"hei").unwrap();
+        fs::write(dir.path().join("real.png"), png).unwrap();
+
+        assert_eq!(scan_with(dir.path(), &ScanOptions::default()), vec!["real.png", "renamed.jpg"]);
+
+        let options = ScanOptions { sniff_content: true, ..ScanOptions::default() };
+        let images = scan_directory_tracked(
+            dir.path().to_str().unwrap(),
+            &options,
+            &CancelToken::new(),
+            &ProgressReporter::disabled(),
+        )
+        .unwrap();
+        assert_eq!(images.len(), 3);
+
+        let renamed = images.iter().find(|img| img.filename == "renamed.jpg").unwrap();
+        assert_eq!(renamed.detected_format.as_deref(), Some("png"));
+        assert!(renamed.extension_mismatch);
+
+        let export = images.iter().find(|img| img.filename == "export").unwrap();
+        assert_eq!(export.extension, "");
+        assert!(export.extension_mismatch);
+
+        let real = images.iter().find(|img| img.filename == "real.png").unwrap();
+        assert!(!real.extension_mismatch);
+    }
+
+    #[test]
+    fn test_media_details() {
+        let dir = tempdir().unwrap();
+        image::DynamicImage::new_rgb8(40, 30).save(dir.path().join("photo.png")).unwrap();
+        File::create(dir.path().join("photo.xmp")).unwrap();
+        File::create(dir.path().join("clip.mp4")).unwrap();
+
+        let options = ScanOptions { read_headers: true, ..ScanOptions::default() };
+        let images = scan_directory_tracked(
+            dir.path().to_str().unwrap(),
+            &options,
+            &CancelToken::new(),
+            &ProgressReporter::disabled(),
+        )
+        .unwrap();
+
+        let photo = images.iter().find(|img| img.filename == "photo.png").unwrap();
+        assert_eq!(photo.kind, MediaKind::Image);
+        assert_eq!(photo.dimensions, Some((40, 30)));
+        assert!(photo.has_sidecar);
+        assert!(photo.modified.is_some());
+
+        let clip = images.iter().find(|img| img.filename == "clip.mp4").unwrap();
+        assert_eq!(clip.kind, MediaKind::Video);
+        assert_eq!(clip.dimensions, None);
+        assert!(!clip.has_sidecar);
+
+        // Uten headerlesing hentes ikke dimensjoner
+        let images = scan_directory(dir.path().to_str().unwrap()).unwrap();
+        assert!(images.iter().all(|img| img.dimensions.is_none()));
+    }
+
+    #[test]
+    fn test_sidecars_and_live_photos_across_directories() {
+        let dir = tempdir().unwrap();
+        let trip = dir.path().join("tur");
+        let nested = trip.join("dag1");
+        fs::create_dir_all(&nested).unwrap();
+        for file in ["a.jpg", "a.XMP", "tur/b.jpg", "tur/dag1/IMG_1.HEIC", "tur/dag1/IMG_1.mov", "tur/c.jpg", "tur/c.jpg.json"] {
+            File::create(dir.path().join(file)).unwrap();
+        }
+        // Samme navn i en annen mappe skal ikke telle
+        File::create(nested.join("b.xmp")).unwrap();
+
+        let images = scan_directory(dir.path().to_str().unwrap()).unwrap();
+        let find = |name: &str| images.iter().find(|img| img.filename == name).unwrap();
+
+        assert!(find("a.jpg").has_sidecar);
+        assert!(!find("b.jpg").has_sidecar);
+        assert!(find("c.jpg").has_sidecar);
+        assert_eq!(find("IMG_1.mov").kind, MediaKind::LivePhotoVideo);
+    }
+
     #[test]
     fn test_scan_file_not_directory() {
         let dir = tempdir().unwrap();
@@ -7,40 +7,41 @@ const SIDECAR_EXTENSIONS: &[&str] = &["xmp", "aae", "json", "thm"];
 
 /// Finner alle sidecar-filer som hører til gitte filsti
 pub fn find_sidecars(image_path: &Path) -> Vec<PathBuf> {
-    let mut sidecars = Vec::new();
-    
-    // Sjekk at vi har en gyldig filsti
-    if let Some(stem) = image_path.file_stem() {
-        if let Some(parent) = image_path.parent() {
-            // Sjekk for hver støttet filendelse
-            for ext in SIDECAR_EXTENSIONS {
-                // Prøv med nøyaktig samme stem (image.jpg -> image.xmp)
-                let sidecar_path = parent.join(format!("{}.{}", stem.to_string_lossy(), ext));
-                if sidecar_path.exists() {
-                     sidecars.push(sidecar_path);
-                     continue; 
-                }
-                
-                // Prøv med uppercase extension (image.XMP)
-                let sidecar_path_upper = parent.join(format!("{}.{}", stem.to_string_lossy(), ext.to_uppercase()));
-                if sidecar_path_upper.exists() {
-                    sidecars.push(sidecar_path_upper);
-                    continue;
-                }
-                
-                // TODO: Google Photos JSON kan ha formatet image.jpg.json
-                // Sjekk også "image.jpg.json" (fullt filnavn + ext)
-                if let Some(filename) = image_path.file_name() {
-                     let sidecar_path_full = parent.join(format!("{}.{}", filename.to_string_lossy(), ext));
-                     if sidecar_path_full.exists() {
-                         sidecars.push(sidecar_path_full);
-                     }
-                }
-            }
-        }
-    }
-    
-    sidecars
+    let Some(parent) = image_path.parent() else {
+        return Vec::new();
+    };
+    sidecar_names(image_path, |name| parent.join(name).exists())
+        .into_iter()
+        .map(|name| parent.join(name))
+        .collect()
+}
+
+/// Om filen har minst én sidecar. `sibling_exists` svarer på om et filnavn finnes i
+/// samme mappe - skanneren slår opp i en mappeliste i stedet for å spørre filsystemet.
+pub fn has_sidecar(image_path: &Path, sibling_exists: impl Fn(&str) -> bool) -> bool {
+    !sidecar_names(image_path, sibling_exists).is_empty()
+}
+
+/// Navnene på sidecar-filene som finnes, høyst én per endelse
+fn sidecar_names(image_path: &Path, sibling_exists: impl Fn(&str) -> bool) -> Vec<String> {
+    let (Some(stem), Some(filename)) = (image_path.file_stem(), image_path.file_name()) else {
+        return Vec::new();
+    };
+    let (stem, filename) = (stem.to_string_lossy(), filename.to_string_lossy());
+
+    SIDECAR_EXTENSIONS
+        .iter()
+        .filter_map(|ext| {
+            // image.xmp, image.XMP, og Google Photos sitt image.jpg.json
+            [
+                format!("{}.{}", stem, ext),
+                format!("{}.{}", stem, ext.to_uppercase()),
+                format!("{}.{}", filename, ext),
+            ]
+            .into_iter()
+            .find(|name| sibling_exists(name))
+        })
+        .collect()
 }
 
 #[cfg(test)]
@@ -0,0 +1,196 @@
+//! Gjenkjenning av filformat fra innholdet (magiske bytes)
+//!
+//! Filendelsen lyver ofte: omdøpte HEIC-filer med `.jpg`, eksporter uten endelse
+//! fra chat-apper og `.jpg_original` fra exiftool. Her leses de første bytene i
+//! filen og sammenlignes med kjente signaturer.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// Antall bytes som leses fra starten av filen. Nok til å se `ftyp`-boksen
+/// med kompatible merker i ISO-BMFF (HEIC/AVIF/MP4/MOV) og doctype i Matroska.
+const SNIFF_LEN: usize = 64;
+
+/// Gjenkjenner filformatet fra innholdet. Returnerer kanonisk filendelse
+/// (f.eks. "jpg", "heic", "mp4"), eller None hvis formatet er ukjent.
+pub fn detect_format(path: &Path) -> Option<&'static str> {
+    let mut file = File::open(path).ok()?;
+    let mut buf = [0u8; SNIFF_LEN];
+    let mut len = 0;
+    // read() kan returnere færre bytes enn bufferet selv om filen er lengre
+    while len < SNIFF_LEN {
+        match file.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(_) => return None,
+        }
+    }
+    detect_format_from_bytes(&buf[..len])
+}
+
+/// Som `detect_format`, men fra bytes som allerede er lest
+pub fn detect_format_from_bytes(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        return Some("jpg");
+    }
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Some("png");
+    }
+    if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        return Some("gif");
+    }
+    if bytes.starts_with(b"II*\0") || bytes.starts_with(b"MM\0*") {
+        return Some("tiff");
+    }
+    // Olympus ORF er TIFF med egen magic
+    if bytes.starts_with(b"IIRO") || bytes.starts_with(b"IIRS") || bytes.starts_with(b"MMOR") {
+        return Some("orf");
+    }
+    if bytes.starts_with(b"FUJIFILMCCD-RAW") {
+        return Some("raf");
+    }
+    if bytes.starts_with(&[0x00, 0x00, 0x01, 0x00]) {
+        return Some("ico");
+    }
+    if bytes.len() >= 12 && bytes.starts_with(b"RIFF") {
+        return match &bytes[8..12] {
+            b"WEBP" => Some("webp"),
+            b"AVI " => Some("avi"),
+            _ => None,
+        };
+    }
+    if bytes.len() >= 12 && &bytes[4..8] == b"ftyp" {
+        return detect_iso_bmff(bytes);
+    }
+    if bytes.starts_with(&[0x1A, 0x45, 0xDF, 0xA3]) {
+        // Matroska og WebM deler EBML-header; doctype står tidlig i headeren
+        let is_webm = bytes.windows(4).any(|w| w == b"webm");
+        return Some(if is_webm { "webm" } else { "mkv" });
+    }
+    if bytes.starts_with(&[0x30, 0x26, 0xB2, 0x75, 0x8E, 0x66, 0xCF, 0x11]) {
+        return Some("wmv");
+    }
+    // BMP sist: "BM" er bare to bytes og gir lett falske treff
+    if bytes.len() >= 14 && bytes.starts_with(b"BM") {
+        return Some("bmp");
+    }
+    None
+}
+
+/// Leser hovedmerket og de kompatible merkene i `ftyp`-boksen
+fn detect_iso_bmff(bytes: &[u8]) -> Option<&'static str> {
+    let box_size = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+    let end = box_size.clamp(12, bytes.len());
+    let major = &bytes[8..12];
+    // Kompatible merker starter etter major brand (4) og minor version (4)
+    let compatible: Vec<&[u8]> = bytes.get(16..end).unwrap_or_default().chunks_exact(4).collect();
+    let has_brand = |brand: &[u8]| major == brand || compatible.contains(&brand);
+
+    if has_brand(b"avif") || has_brand(b"avis") {
+        return Some("avif");
+    }
+    if [b"heic", b"heix", b"hevc", b"hevx", b"heim", b"heis", b"mif1", b"msf1"]
+        .iter()
+        .any(|brand| major == *brand)
+    {
+        return Some("heic");
+    }
+    if major == b"crx " {
+        return Some("cr3");
+    }
+    if major == b"qt  " {
+        return Some("mov");
+    }
+    if major == b"M4V " || major == b"M4VH" {
+        return Some("m4v");
+    }
+    // isom, iso2, mp41, mp42, avc1, 3gp4 osv.
+    Some("mp4")
+}
+
+/// Sjekker om filendelsen passer med formatet som ble funnet i innholdet.
+/// Synonymer som jpeg/jpg, tif/tiff og heif/heic regnes som like.
+pub fn extension_matches(extension: &str, detected: &str) -> bool {
+    canonical_extension(extension) == detected
+        // MP4-familien: MOV/M4V-containere er ofte merket som generisk isom
+        || (is_mp4_family(detected) && is_mp4_family(&canonical_extension(extension)))
+        // CR2, NEF, ARW og DNG er vanlige TIFF-filer og kan ikke skilles fra TIFF på signaturen
+        || (detected == "tiff" && TIFF_BASED_RAW.contains(&canonical_extension(extension).as_str()))
+}
+
+const TIFF_BASED_RAW: &[&str] = &["cr2", "nef", "arw", "dng"];
+
+fn canonical_extension(extension: &str) -> String {
+    match extension.to_lowercase().as_str() {
+        "jpeg" | "jpe" | "jfif" => "jpg".to_string(),
+        "tif" => "tiff".to_string(),
+        "heif" | "hif" => "heic".to_string(),
+        other => other.to_string(),
+    }
+}
+
+fn is_mp4_family(extension: &str) -> bool {
+    matches!(extension, "mp4" | "m4v" | "mov")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn ftyp(major: &[u8; 4], compatible: &[&[u8; 4]]) -> Vec<u8> {
+        let size = 16 + 4 * compatible.len();
+        let mut bytes = (size as u32).to_be_bytes().to_vec();
+        bytes.extend_from_slice(b"ftyp");
+        bytes.extend_from_slice(major);
+        bytes.extend_from_slice(&[0, 0, 0, 0]);
+        for brand in compatible {
+            bytes.extend_from_slice(*brand);
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_detect_common_signatures() {
+        assert_eq!(detect_format_from_bytes(&[0xFF, 0xD8, 0xFF, 0xE1]), Some("jpg"));
+        assert_eq!(detect_format_from_bytes(b"\x89PNG\r\n\x1a\n\0\0"), Some("png"));
+        assert_eq!(detect_format_from_bytes(b"GIF89a"), Some("gif"));
+        assert_eq!(detect_format_from_bytes(b"RIFF\0\0\0\0WEBPVP8 "), Some("webp"));
+        assert_eq!(detect_format_from_bytes(b"RIFF\0\0\0\0AVI LIST"), Some("avi"));
+        assert_eq!(detect_format_from_bytes(b"II*\0\x08\0\0\0"), Some("tiff"));
+        assert_eq!(detect_format_from_bytes(b"hello world"), None);
+        assert_eq!(detect_format_from_bytes(b""), None);
+    }
+
+    #[test]
+    fn test_detect_iso_bmff_brands() {
+        assert_eq!(detect_format_from_bytes(&ftyp(b"heic", &[b"mif1", b"heic"])), Some("heic"));
+        assert_eq!(detect_format_from_bytes(&ftyp(b"mif1", &[b"avif", b"miaf"])), Some("avif"));
+        assert_eq!(detect_format_from_bytes(&ftyp(b"mif1", &[b"heic"])), Some("heic"));
+        assert_eq!(detect_format_from_bytes(&ftyp(b"qt  ", &[b"qt  "])), Some("mov"));
+        assert_eq!(detect_format_from_bytes(&ftyp(b"crx ", &[b"crx ", b"isom"])), Some("cr3"));
+        assert_eq!(detect_format_from_bytes(&ftyp(b"isom", &[b"iso2", b"mp41"])), Some("mp4"));
+    }
+
+    #[test]
+    fn test_extension_matches() {
+        assert!(extension_matches("JPEG", "jpg"));
+        assert!(extension_matches("tif", "tiff"));
+        assert!(extension_matches("mov", "mp4"));
+        assert!(extension_matches("NEF", "tiff"));
+        assert!(!extension_matches("tiff", "orf"));
+        assert!(!extension_matches("jpg", "heic"));
+        assert!(!extension_matches("", "jpg"));
+    }
+
+    #[test]
+    fn test_detect_from_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("IMG_0001.jpg");
+        fs::write(&path, ftyp(b"heic", &[b"mif1"])).unwrap();
+        assert_eq!(detect_format(&path), Some("heic"));
+        assert_eq!(detect_format(&dir.path().join("mangler.jpg")), None);
+    }
+}
@@ -1,6 +1,9 @@
-use std::path::Path;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 use std::fs;
-use crate::services::metadata;
+use crate::services::{media, metadata, signature};
+use crate::services::jobs::CancelToken;
+use crate::services::progress::{ProgressPhase, ProgressReporter};
 use chrono::Datelike;
 use serde::{Serialize, Deserialize};
 use trash;
@@ -12,6 +15,11 @@ pub struct OperationResult {
     pub success: usize,
     pub errors: usize,
     pub error_messages: Vec<String>,
+    /// Satt hvis operasjonen ble avbrutt før alle filer var behandlet
+    pub cancelled: bool,
+    /// RAW+JPEG-partnere til slettede filer som ble latt være fordi de ikke var med i
+    /// forespørselen. Frontend kan spørre brukeren om de også skal slettes.
+    pub remaining_partners: Vec<String>,
 }
 
 impl OperationResult {
@@ -21,6 +29,8 @@ impl OperationResult {
             success: 0,
             errors: 0,
             error_messages: Vec::new(),
+            cancelled: false,
+            remaining_partners: Vec::new(),
         }
     }
 
@@ -41,11 +51,117 @@ pub struct SortConfig {
     pub use_month_names: bool,
 }
 
+/// Finner et ledig filnavn i `dir` for `source`: img.jpg -> img_1.jpg -> img_2.jpg ...
+fn unique_destination(dir: &Path, source: &Path) -> PathBuf {
+    unique_destination_where(dir, source, |path| path.exists())
+}
+
+/// Som `unique_destination`, men holder av navnet til begge halvdelene av et RAW+JPEG-par
+/// slik at de får samme nye navn (IMG_0001_1.CR2 + IMG_0001_1.JPG)
+fn pair_destination(dir: &Path, source: &Path) -> PathBuf {
+    match media::raw_jpeg_partner(source).and_then(|p| p.extension().map(|e| e.to_os_string())) {
+        Some(partner_ext) => unique_destination_where(dir, source, |path| {
+            path.exists() || path.with_extension(&partner_ext).exists()
+        }),
+        None => unique_destination(dir, source),
+    }
+}
+
+fn unique_destination_where(dir: &Path, source: &Path, taken: impl Fn(&Path) -> bool) -> PathBuf {
+    let stem = source.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = source.extension().unwrap_or_default().to_string_lossy();
+    let mut dest_path = dir.join(source.file_name().unwrap_or_default());
+
+    let mut counter = 1;
+    while taken(&dest_path) {
+        let new_filename = if ext.is_empty() {
+            format!("{}_{}", stem, counter)
+        } else {
+            format!("{}_{}.{}", stem, counter, ext)
+        };
+        dest_path = dir.join(new_filename);
+        counter += 1;
+    }
+    dest_path
+}
+
+/// Nøkkel for å kjenne igjen en sti: kanonisk mappe + filnavn. Mappen finnes fortsatt
+/// etter at filen er flyttet eller slettet, så nøkkelen kan lages både før og etter.
+fn path_key(path: &Path) -> PathBuf {
+    let parent = path.parent().unwrap_or(Path::new(""));
+    fs::canonicalize(parent)
+        .unwrap_or_else(|_| parent.to_path_buf())
+        .join(path.file_name().unwrap_or_default())
+}
+
+/// Nytt navn for en sidecar når hovedfilen flyttes til `dest_path` (som kan ha fått nytt navn)
+fn sidecar_destination(sidecar: &Path, source_path: &Path, dest_path: &Path) -> Option<PathBuf> {
+    let sidecar_ext = sidecar.extension()?.to_string_lossy();
+    let sidecar_filename = sidecar.file_name().unwrap_or_default().to_string_lossy();
+    let source_filename = source_path.file_name().unwrap_or_default().to_string_lossy();
+
+    if sidecar_filename.starts_with(&*source_filename) {
+        // Case: image.jpg.json (sidecar inneholder hele originalnavnet)
+        // Da bør vi bygge nytt navn basert på dest_path filnavn + extension
+        let dest_filename = dest_path.file_name().unwrap_or_default().to_string_lossy();
+        Some(dest_path.with_file_name(format!("{}.{}", dest_filename, sidecar_ext)))
+    } else {
+        // Case: image.xmp (sidecar har bare samme stem)
+        Some(dest_path.with_extension(&*sidecar_ext))
+    }
+}
+
+/// Flytter/kopierer den andre halvdelen av et RAW+JPEG-par etter hovedfilen, slik at
+/// paret havner i samme mappe med samme navn. Returnerer partnerens opprinnelige sti.
+fn transfer_raw_partner(source_path: &Path, dest_path: &Path, copy: bool) -> Result<Option<PathBuf>, String> {
+    let partner = match media::raw_jpeg_partner(source_path) {
+        Some(partner) => partner,
+        None => return Ok(None),
+    };
+    let dest_partner = dest_path.with_extension(partner.extension().unwrap_or_default());
+    if dest_partner.exists() {
+        return Err(format!("RAW+JPEG-partner finnes allerede i målmappen: {:?}", dest_partner));
+    }
+
+    let op_result = if copy {
+        fs::copy(&partner, &dest_partner).map(|_| ())
+    } else {
+        fs::rename(&partner, &dest_partner)
+    };
+    if let Err(e) = op_result {
+        return Err(format!("Kunne ikke flytte RAW+JPEG-partner {:?}: {}", partner, e));
+    }
+
+    for sidecar in crate::services::sidecar::find_sidecars(&partner) {
+        if let Some(dest_sidecar_path) = sidecar_destination(&sidecar, &partner, &dest_partner) {
+            let _ = if copy {
+                fs::copy(&sidecar, &dest_sidecar_path).map(|_| ())
+            } else {
+                fs::rename(&sidecar, &dest_sidecar_path)
+            };
+        }
+    }
+    Ok(Some(partner))
+}
+
 pub fn sort_images(
     paths: Vec<String>,
     target_dir: &str,
     method: &str, // "copy" eller "move"
     config: SortConfig
+) -> OperationResult {
+    sort_images_tracked(paths, target_dir, method, config, &CancelToken::new(), &ProgressReporter::disabled())
+}
+
+/// Som `sort_images`, men rapporterer fremdrift og stopper før neste fil når `cancel` settes.
+/// Filer som allerede er flyttet/kopiert blir liggende, og telles i resultatet.
+pub fn sort_images_tracked(
+    paths: Vec<String>,
+    target_dir: &str,
+    method: &str,
+    config: SortConfig,
+    cancel: &CancelToken,
+    progress: &ProgressReporter,
 ) -> OperationResult {
     let mut result = OperationResult::new();
     result.processed = paths.len();
@@ -61,13 +177,29 @@ pub fn sort_images(
         "Juli", "August", "September", "Oktober", "November", "Desember"
     ];
 
+    progress.start_phase(ProgressPhase::Sort, Some(paths.len() as u64));
+    // RAW+JPEG-partnere som allerede er flyttet sammen med hovedfilen
+    let mut handled_partners: HashSet<PathBuf> = HashSet::new();
+
     for path_str in paths {
+        if cancel.is_cancelled() {
+            result.cancelled = true;
+            break;
+        }
+
         let source_path = Path::new(&path_str);
+        if handled_partners.contains(&path_key(source_path)) {
+            result.add_success();
+            progress.advance(0, Some(&path_str));
+            continue;
+        }
         
         if !source_path.exists() {
              result.add_error(format!("Fil finnes ikke: {}", path_str));
+             progress.error(Some(&path_str));
              continue;
         }
+        let size_bytes = fs::metadata(source_path).map(|m| m.len()).unwrap_or(0);
 
         // VIKTIG: Endret etter brukerønske. Alltid strict mode (ingen fallback til mtime).
         let date_opt = metadata::read_creation_date_with_fallback(source_path, false);
@@ -101,25 +233,11 @@ pub fn sort_images(
 
         if let Err(e) = fs::create_dir_all(&dest_dir) {
              result.add_error(format!("Kunne ikke opprette mappe {:?}: {}", dest_dir, e));
+             progress.error(Some(&path_str));
              continue;
         }
 
-        let filename = source_path.file_name().unwrap_or_default();
-        let mut dest_path = dest_dir.join(filename);
-
-        // Håndter filnavn-kollisjoner: img.jpg -> img_1.jpg
-        let mut counter = 1;
-        while dest_path.exists() {
-            let stem = source_path.file_stem().unwrap_or_default().to_string_lossy();
-            let ext = source_path.extension().unwrap_or_default().to_string_lossy();
-            let new_filename = if ext.is_empty() {
-                format!("{}_{}", stem, counter)
-            } else {
-                format!("{}_{}.{}", stem, counter, ext)
-            };
-            dest_path = dest_dir.join(new_filename);
-            counter += 1;
-        }
+        let dest_path = pair_destination(&dest_dir, source_path);
 
         let op_result = if method == "move" {
             fs::rename(source_path, &dest_path)
@@ -130,70 +248,170 @@ pub fn sort_images(
         match op_result {
             Ok(_) => {
                 result.add_success();
+                progress.advance(size_bytes, Some(&path_str));
                 
                 // Håndter sidecar-filer (kun hvis hovedfil ble flyttet/kopiert OK)
-                let sidecars = crate::services::sidecar::find_sidecars(source_path);
-                for sidecar in sidecars {
-                    // Bestem nytt navn for sidecar basert på dest_path (for å matche evt rename av hovedfil)
-                    if let Some(sidecar_ext) = sidecar.extension() {
-                         let sidecar_ext_str = sidecar_ext.to_string_lossy();
-                         
-                         let sidecar_filename_original = sidecar.file_name().unwrap_or_default().to_string_lossy();
-                         let source_filename_original = source_path.file_name().unwrap_or_default().to_string_lossy();
-                         
-                         let dest_sidecar_path = if sidecar_filename_original.starts_with(&*source_filename_original) {
-                             // Case: image.jpg.json (sidecar inneholder hele originalnavnet)
-                             // Da bør vi bygge nytt navn basert på dest_path filnavn + extension
-                             let dest_filename = dest_path.file_name().unwrap_or_default().to_string_lossy();
-                             dest_dir.join(format!("{}.{}", dest_filename, sidecar_ext_str))
-                         } else {
-                             // Case: image.xmp (sidecar har bare samme stem)
-                             dest_path.with_extension(&*sidecar_ext_str)
-                         };
-
-                         if method == "move" {
-                             let _ = fs::rename(&sidecar, &dest_sidecar_path);
-                         } else {
-                             let _ = fs::copy(&sidecar, &dest_sidecar_path);
-                         }
+                for sidecar in crate::services::sidecar::find_sidecars(source_path) {
+                    if let Some(dest_sidecar_path) = sidecar_destination(&sidecar, source_path, &dest_path) {
+                        if method == "move" {
+                            let _ = fs::rename(&sidecar, &dest_sidecar_path);
+                        } else {
+                            let _ = fs::copy(&sidecar, &dest_sidecar_path);
+                        }
                     }
                 }
+
+                // RAW+JPEG-par er ett bilde og skal ikke splittes
+                match transfer_raw_partner(source_path, &dest_path, method != "move") {
+                    Ok(Some(partner)) => { handled_partners.insert(path_key(&partner)); },
+                    Ok(None) => {},
+                    Err(msg) => result.add_error(msg),
+                }
             },
-            Err(e) => result.add_error(format!("Kunne ikke {} fil {}: {}", method, path_str, e)),
+            Err(e) => {
+                result.add_error(format!("Kunne ikke {} fil {}: {}", method, path_str, e));
+                progress.error(Some(&path_str));
+            }
         }
     }
 
+    progress.finish_phase();
     result
 }
 
 pub fn delete_images(paths: Vec<String>) -> OperationResult {
+    delete_images_tracked(paths, &CancelToken::new(), &ProgressReporter::disabled())
+}
+
+/// Som `delete_images`, men rapporterer fremdrift og kan avbrytes mellom filer.
+///
+/// RAW+JPEG-par slettes bare sammen når begge halvdelene er med i `paths`. RAW hashes fra
+/// den innebygde forhåndsvisningen, så RAW og JPEG havner i samme duplikatgruppe, og
+/// partneren kan være bildet brukeren beholder. Partnere utenfor forespørselen slettes
+/// derfor ikke, men listes i `remaining_partners`.
+pub fn delete_images_tracked(paths: Vec<String>, cancel: &CancelToken, progress: &ProgressReporter) -> OperationResult {
     let mut result = OperationResult::new();
     result.processed = paths.len();
+    progress.start_phase(ProgressPhase::Delete, Some(paths.len() as u64));
+    let requested: HashSet<PathBuf> = paths.iter().map(|p| path_key(Path::new(p))).collect();
 
     for path_str in paths {
+        if cancel.is_cancelled() {
+            result.cancelled = true;
+            break;
+        }
+
         let path = Path::new(&path_str);
         if !path.exists() {
              result.add_error(format!("Fil finnes ikke: {}", path_str));
+             progress.error(Some(&path_str));
              continue;
         }
+        let size_bytes = fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+
+        // Partneren må slås opp før filen er borte
+        let partner = media::raw_jpeg_partner(path);
 
         // Prøv å bruke trash først
         match trash::delete(path) {
             Ok(_) => {
                 result.add_success();
+                progress.advance(size_bytes, Some(&path_str));
                 // Slett også sidecars
                 let sidecars = crate::services::sidecar::find_sidecars(path);
                 for sidecar in sidecars {
                     let _ = trash::delete(sidecar); // Ignorer feil for sidecars
                 }
+                // Partnere som er valgt slettes i sin egen runde
+                if let Some(partner) = partner.filter(|p| !requested.contains(&path_key(p))) {
+                    result.remaining_partners.push(partner.to_string_lossy().to_string());
+                }
             },
             Err(e) => {
                 // Hvis trash feiler, logg feilen - vi sletter IKKE permanent automatisk som fallback
                 // for sikkerhets skyld.
                 result.add_error(format!("Kunne ikke flytte til papirkurv: {}. Permanent sletting ikke utført av sikkerhetshensyn.", e));
+                progress.error(Some(&path_str));
+            }
+        }
+    }
+    progress.finish_phase();
+    result
+}
+
+pub fn fix_extensions(paths: Vec<String>) -> OperationResult {
+    fix_extensions_tracked(paths, &CancelToken::new(), &ProgressReporter::disabled())
+}
+
+/// Gir filene endelsen som matcher innholdet (IMG_001.jpg som egentlig er HEIC -> IMG_001.heic).
+/// Filer som allerede har riktig endelse telles som vellykket uten endring.
+pub fn fix_extensions_tracked(paths: Vec<String>, cancel: &CancelToken, progress: &ProgressReporter) -> OperationResult {
+    let mut result = OperationResult::new();
+    result.processed = paths.len();
+    progress.start_phase(ProgressPhase::Rename, Some(paths.len() as u64));
+
+    for path_str in paths {
+        if cancel.is_cancelled() {
+            result.cancelled = true;
+            break;
+        }
+
+        let source_path = Path::new(&path_str);
+        if !source_path.exists() {
+            result.add_error(format!("Fil finnes ikke: {}", path_str));
+            progress.error(Some(&path_str));
+            continue;
+        }
+
+        let format = match signature::detect_format(source_path) {
+            Some(format) => format,
+            None => {
+                result.add_error(format!("Ukjent filformat: {}", path_str));
+                progress.error(Some(&path_str));
+                continue;
+            }
+        };
+
+        let ext = source_path.extension().unwrap_or_default().to_string_lossy();
+        if signature::extension_matches(&ext, format) {
+            result.add_success();
+            progress.advance(0, Some(&path_str));
+            continue;
+        }
+
+        let stem = source_path.file_stem().unwrap_or_default().to_string_lossy();
+        let renamed = source_path.with_file_name(format!("{}.{}", stem, format));
+        let dest_path = unique_destination(source_path.parent().unwrap_or(Path::new("")), &renamed);
+
+        match fs::rename(source_path, &dest_path) {
+            Ok(_) => {
+                result.add_success();
+                progress.advance(0, Some(&path_str));
+
+                // Kun sidecars med hele filnavnet (image.jpg.json) må følge med.
+                // Sidecars med samme stem (image.xmp) er fortsatt riktige, og kan tilhøre
+                // en annen fil med samme stem (f.eks. image.jpg ved siden av image.jpg_original).
+                let source_filename = source_path.file_name().unwrap_or_default().to_string_lossy();
+                for sidecar in crate::services::sidecar::find_sidecars(source_path) {
+                    let full_name = sidecar
+                        .file_name()
+                        .map(|n| n.to_string_lossy().starts_with(&*source_filename))
+                        .unwrap_or(false);
+                    if full_name {
+                        if let Some(dest_sidecar_path) = sidecar_destination(&sidecar, source_path, &dest_path) {
+                            let _ = fs::rename(&sidecar, &dest_sidecar_path);
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                result.add_error(format!("Kunne ikke endre navn på {}: {}", path_str, e));
+                progress.error(Some(&path_str));
             }
         }
     }
+
+    progress.finish_phase();
     result
 }
 
@@ -208,53 +426,37 @@ pub fn move_images(paths: Vec<String>, target_dir: &str) -> OperationResult {
          return result;
     }
 
+    let mut handled_partners: HashSet<PathBuf> = HashSet::new();
+
     for path_str in paths {
         let source_path = Path::new(&path_str);
+        if handled_partners.contains(&path_key(source_path)) {
+            result.add_success();
+            continue;
+        }
         if !source_path.exists() {
             result.add_error(format!("Fil finnes ikke: {}", path_str));
             continue;
         }
 
-        let filename = source_path.file_name().unwrap_or_default();
-        let mut dest_path = target_path.join(filename);
-
-        // Kollisjonshåndtering
-        let mut counter = 1;
-        while dest_path.exists() {
-            let stem = source_path.file_stem().unwrap_or_default().to_string_lossy();
-            let ext = source_path.extension().unwrap_or_default().to_string_lossy();
-             let new_filename = if ext.is_empty() {
-                format!("{}_{}", stem, counter)
-            } else {
-                format!("{}_{}.{}", stem, counter, ext)
-            };
-            dest_path = target_path.join(new_filename);
-            counter += 1;
-        }
+        let dest_path = pair_destination(target_path, source_path);
 
         match fs::rename(source_path, &dest_path) {
             Ok(_) => {
                 result.add_success();
                 
                 // Håndter sidecar-filer
-                let sidecars = crate::services::sidecar::find_sidecars(source_path);
-                for sidecar in sidecars {
-                     if let Some(sidecar_ext) = sidecar.extension() {
-                         let sidecar_ext_str = sidecar_ext.to_string_lossy();
-                         
-                         let sidecar_filename_original = sidecar.file_name().unwrap_or_default().to_string_lossy();
-                         let source_filename_original = source_path.file_name().unwrap_or_default().to_string_lossy();
-                         
-                         let dest_sidecar_path = if sidecar_filename_original.starts_with(&*source_filename_original) {
-                             let dest_filename = dest_path.file_name().unwrap_or_default().to_string_lossy();
-                             target_path.join(format!("{}.{}", dest_filename, sidecar_ext_str))
-                         } else {
-                             dest_path.with_extension(&*sidecar_ext_str)
-                         };
-                         
-                         let _ = fs::rename(&sidecar, &dest_sidecar_path);
+                for sidecar in crate::services::sidecar::find_sidecars(source_path) {
+                    if let Some(dest_sidecar_path) = sidecar_destination(&sidecar, source_path, &dest_path) {
+                        let _ = fs::rename(&sidecar, &dest_sidecar_path);
                     }
                 }
+
+                match transfer_raw_partner(source_path, &dest_path, false) {
+                    Ok(Some(partner)) => { handled_partners.insert(path_key(&partner)); },
+                    Ok(None) => {},
+                    Err(msg) => result.add_error(msg),
+                }
             },
             Err(e) => result.add_error(format!("Kunne ikke flytte fil {}: {}", path_str, e)),
         }
@@ -340,6 +542,69 @@ mod tests {
         assert!(!source.join("photo.xmp").exists());
     }
 
+    #[test]
+    fn test_move_raw_jpeg_pair() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("source");
+        let target = temp_dir.path().join("target");
+        fs::create_dir(&source).unwrap();
+        fs::create_dir(&target).unwrap();
+        create_dummy_file(&target, "IMG_0001.JPG");
+
+        let raw = create_dummy_file(&source, "IMG_0001.CR2");
+        let jpeg = create_dummy_file(&source, "IMG_0001.JPG");
+
+        // Begge halvdelene er valgt - paret flyttes én gang og begge telles som vellykket
+        let result = move_images(
+            vec![raw.to_string_lossy().to_string(), jpeg.to_string_lossy().to_string()],
+            target.to_str().unwrap(),
+        );
+
+        assert_eq!(result.success, 2);
+        assert_eq!(result.errors, 0);
+        // Kollisjon på CR2-navnet gir nytt navn til hele paret
+        assert!(target.join("IMG_0001_1.CR2").exists());
+        assert!(target.join("IMG_0001_1.JPG").exists());
+        assert!(!raw.exists());
+        assert!(!jpeg.exists());
+    }
+
+    #[test]
+    fn test_delete_keeps_partner_chosen_as_keeper() {
+        use crate::services::keeper::{self, KeepRule};
+
+        let temp_dir = TempDir::new().unwrap();
+        let raw = create_dummy_file(temp_dir.path(), "IMG_0001.NEF");
+        let jpeg = temp_dir.path().join("IMG_0001.JPG");
+        fs::write(&jpeg, "større enn RAW-filen").unwrap();
+
+        // RAW og JPEG havner i samme duplikatgruppe; JPEG-en beholdes
+        let group = vec![raw.to_string_lossy().to_string(), jpeg.to_string_lossy().to_string()];
+        let decision = keeper::select_keeper(&group, &[KeepRule::LargestFile]).unwrap();
+        assert_eq!(decision.keep, jpeg.to_string_lossy());
+
+        let result = delete_images(decision.remove);
+        assert_eq!(result.success, 1);
+        assert_eq!(result.errors, 0);
+        assert!(!raw.exists());
+        assert!(jpeg.exists(), "Bildet som beholdes skal ikke slettes som partner");
+        assert_eq!(result.remaining_partners, vec![jpeg.to_string_lossy().to_string()]);
+    }
+
+    #[test]
+    fn test_delete_raw_jpeg_pair_when_both_selected() {
+        let temp_dir = TempDir::new().unwrap();
+        let raw = create_dummy_file(temp_dir.path(), "IMG_0002.CR2");
+        let jpeg = create_dummy_file(temp_dir.path(), "IMG_0002.jpg");
+
+        let result = delete_images(vec![jpeg.to_string_lossy().to_string(), raw.to_string_lossy().to_string()]);
+        assert_eq!(result.success, 2);
+        assert_eq!(result.errors, 0);
+        assert!(!raw.exists());
+        assert!(!jpeg.exists());
+        assert!(result.remaining_partners.is_empty());
+    }
+
     #[test]
     fn test_move_with_sidecar_rename() {
         let temp_dir = TempDir::new().unwrap();
@@ -414,6 +679,67 @@ mod tests {
         assert!(expected_path.exists(), "Filen skal flyttes til 'Uten dato' mappe når EXIF mangler og fallback er av");
     }
 
+    #[test]
+    fn test_sort_cancelled_before_start() {
+        let temp_dir = TempDir::new().unwrap();
+        let source_dir = temp_dir.path().join("source");
+        let target_dir = temp_dir.path().join("target");
+        fs::create_dir(&source_dir).unwrap();
+        fs::create_dir(&target_dir).unwrap();
+
+        let file_path = create_dummy_file(&source_dir, "a.jpg");
+        let config = SortConfig {
+            use_day_folder: false,
+            use_month_names: false,
+        };
+
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        let result = sort_images_tracked(
+            vec![file_path.to_string_lossy().to_string()],
+            target_dir.to_str().unwrap(),
+            "move",
+            config,
+            &cancel,
+            &ProgressReporter::disabled(),
+        );
+
+        assert!(result.cancelled);
+        assert_eq!(result.success, 0);
+        assert!(file_path.exists(), "Ingen filer skal flyttes etter avbrytelse");
+    }
+
+    #[test]
+    fn test_fix_extensions() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+
+        // PNG-innhold med .jpg-endelse, og en JSON-sidecar med hele filnavnet
+        let wrong = dir.join("photo.jpg");
+        fs::write(&wrong, b"\x89PNG\r\n\x1a\n0000").unwrap();
+        let json = create_dummy_file(dir, "photo.jpg.json");
+        // Kollisjon: photo.png finnes allerede
+        create_dummy_file(dir, "photo.png");
+        // Riktig endelse skal ikke røres
+        let correct = dir.join("ok.jpeg");
+        fs::write(&correct, [0xFF, 0xD8, 0xFF, 0xE0]).unwrap();
+        let unknown = create_dummy_file(dir, "tom.jpg");
+
+        let result = fix_extensions(vec![
+            wrong.to_string_lossy().to_string(),
+            correct.to_string_lossy().to_string(),
+            unknown.to_string_lossy().to_string(),
+        ]);
+
+        assert_eq!(result.success, 2);
+        assert_eq!(result.errors, 1);
+        assert!(!wrong.exists());
+        assert!(dir.join("photo_1.png").exists());
+        assert!(!json.exists());
+        assert!(dir.join("photo_1.png.json").exists());
+        assert!(correct.exists());
+    }
+
     // Merk: Vi tester ikke delete_images med trash crate her da det krever GUI environment
     // og kan være flaky i test-miljøer.
     // Vi tester heller ikke move_images_collision her da den er dekket over.
@@ -3,6 +3,8 @@
 //! Genererer thumbnails på forespørsel og cacher dem for raskere lasting.
 
 // use image::GenericImageView;
+use crate::services::tools::{self, Tool};
+use crate::services::{heif, media, metadata, preview, raw, signature};
 use sha2::{Digest, Sha256};
 use std::fs::{self, File};
 use std::io::Read;
@@ -11,6 +13,10 @@ use std::path::{Path, PathBuf};
 /// Standard thumbnail-størrelse
 pub const THUMBNAIL_SIZE: u32 = 200;
 
+/// Økes når thumbnails lages annerledes, slik at gamle i cachen ikke brukes.
+/// Versjon 2: EXIF-orientering brukes.
+const THUMBNAIL_VERSION: u32 = 2;
+
 /// Henter eller genererer en thumbnail for et bilde
 /// Returnerer stien til thumbnail-filen
 pub fn get_or_create_thumbnail(
@@ -29,57 +35,63 @@ pub fn get_or_create_thumbnail(
     // Sørg for at cache-mappen finnes
     fs::create_dir_all(cache_dir)?;
 
-    let ext = image_path
-        .extension()
-        .and_then(|e| e.to_str())
-        .map(|e| e.to_lowercase())
-        .unwrap_or_default();
-
-    let video_extensions = ["mp4", "mov", "avi", "mkv", "webm", "wmv", "m4v"];
-
-    if video_extensions.contains(&ext.as_str()) {
+    if media::is_video(image_path) {
         generate_video_thumbnail(image_path, &thumbnail_path)?;
     } else {
+        // Innholdet sniffes én gang og brukes både til dekoding og rotasjon
+        let is_heif = heif::is_heif(image_path, signature::detect_format(image_path));
+
         // Last og resize bildet (Opprinnelig logikk)
-        let img = load_image(image_path)?;
-        let thumbnail = img.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE);
-        // Lagre som JPEG med god komprimering
-        thumbnail.save(&thumbnail_path)?;
+        let img = load_image(image_path, is_heif)?;
+        let mut thumbnail = img.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE);
+        // Roter etter skalering - billigere enn å rotere hele bildet.
+        // libheif har allerede rotert HEIC/AVIF.
+        if !is_heif {
+            if let Some(orientation) = metadata::read_orientation(image_path) {
+                thumbnail.apply_orientation(orientation);
+            }
+        }
+        // Lagre som JPEG med god komprimering (JPEG har ingen alfakanal)
+        image::DynamicImage::ImageRgb8(thumbnail.to_rgb8()).save(&thumbnail_path)?;
     }
 
     Ok(thumbnail_path)
 }
 
 fn generate_video_thumbnail(input: &Path, output: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    use std::process::Command;
-    
     // Bruk ffmpeg til å hente ut en frame
     // -y: overskriv
+    // -v error: bare feil på stderr, så de havner i feilmeldingen
     // -ss: seek til 1 sekund (unngå svart start-frame)
     // -i: input
     // -vframes 1: kun ett bilde
     // -q:v 2: god kvalitet jpeg
-    
-    let status = Command::new("ffmpeg")
-        .args(&[
-            "-y",
-            "-ss", "00:00:01",
-            "-i", input.to_str().unwrap_or_default(), // todo: handle formatting error?
-            "-vframes", "1",
-            "-q:v", "2",
-            output.to_str().unwrap_or_default(),
-        ])
-        .status()?;
-
-    if !status.success() {
-        return Err("Feil ved generering av video-thumbnail (ffmpeg feilet)".into());
-    }
-    
+    tools::run(Tool::Ffmpeg, |command| {
+        command
+            .args(["-y", "-v", "error", "-ss", "00:00:01", "-i"])
+            .arg(input)
+            .args(["-vframes", "1", "-q:v", "2"])
+            .arg(output);
+    })
+    .map_err(|e| format!("Feil ved generering av video-thumbnail: {}", e))?;
+
     Ok(())
 }
 
-/// Laster et bilde fra fil
-fn load_image(path: &Path) -> Result<image::DynamicImage, Box<dyn std::error::Error + Send + Sync>> {
+/// Laster et bilde fra fil. `is_heif` avgjør om libheif brukes, se `heif::is_heif`.
+fn load_image(path: &Path, is_heif: bool) -> Result<image::DynamicImage, Box<dyn std::error::Error + Send + Sync>> {
+    if media::is_raw(path) {
+        return raw::load_preview(path)
+            .ok_or_else(|| format!("Fant ingen forhåndsvisning i RAW-filen: {}", path.display()).into());
+    }
+    if is_heif {
+        return heif::decode(path);
+    }
+    // Innebygd forhåndsvisning holder hvis den er minst like stor som thumbnailen
+    if let Some(img) = preview::load_embedded_preview(path, THUMBNAIL_SIZE) {
+        return Ok(img);
+    }
+
     let mut file = File::open(path)?;
     let mut buffer = Vec::new();
     file.read_to_end(&mut buffer)?;
@@ -102,6 +114,7 @@ pub fn generate_cache_key(path: &Path) -> Result<String, Box<dyn std::error::Err
     let mut hasher = Sha256::new();
     hasher.update(path.to_string_lossy().as_bytes());
     hasher.update(mtime.to_le_bytes());
+    hasher.update(THUMBNAIL_VERSION.to_le_bytes());
     let result = hasher.finalize();
 
     Ok(hex::encode(&result[..16])) // Bruk kun første 16 bytes for kortere filnavn
@@ -129,7 +142,7 @@ pub fn clear_cache(cache_dir: &Path) -> Result<usize, Box<dyn std::error::Error
 #[cfg(test)]
 mod tests {
     use super::*;
-    use image::{Rgba, RgbaImage};
+    use image::{GenericImageView, Rgba, RgbaImage};
     use tempfile::tempdir;
 
     /// Lager et test-bilde som kan lagres til disk
@@ -272,6 +285,111 @@ mod tests {
         assert!(height < width, "Bredt bilde skal gi thumbnail som er bredere enn det er høyt");
     }
 
+    /// Gråtone-TIFF med én side per (bredde, høyde)
+    fn create_multipage_tiff(pages: &[(u32, u32)]) -> Vec<u8> {
+        let mut out = b"II*\0".to_vec();
+        out.extend_from_slice(&8u32.to_le_bytes());
+        for (i, &(width, height)) in pages.iter().enumerate() {
+            let data_offset = out.len() + 2 + 9 * 12 + 4;
+            let pixel_count = width * height;
+            // (tag, type, verdi) - type 3 = SHORT, 4 = LONG
+            let entries: [(u16, u16, u32); 9] = [
+                (256, 4, width),
+                (257, 4, height),
+                (258, 3, 8),
+                (259, 3, 1),
+                (262, 3, 1),
+                (273, 4, data_offset as u32),
+                (277, 3, 1),
+                (278, 4, height),
+                (279, 4, pixel_count),
+            ];
+            out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+            for (tag, field_type, value) in entries {
+                out.extend_from_slice(&tag.to_le_bytes());
+                out.extend_from_slice(&field_type.to_le_bytes());
+                out.extend_from_slice(&1u32.to_le_bytes());
+                if field_type == 3 {
+                    out.extend_from_slice(&(value as u16).to_le_bytes());
+                    out.extend_from_slice(&[0, 0]);
+                } else {
+                    out.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+            let next_ifd = if i + 1 < pages.len() { data_offset as u32 + pixel_count } else { 0 };
+            out.extend_from_slice(&next_ifd.to_le_bytes());
+            out.resize(out.len() + pixel_count as usize, 128);
+        }
+        out
+    }
+
+    #[test]
+    fn test_webp_thumbnail() {
+        let dir = tempdir().unwrap();
+        let cache_dir = dir.path().join("cache");
+        let image_path = dir.path().join("bilde.webp");
+
+        // Tapsfri WebP med alfakanal
+        let img = create_test_image(400, 100).to_rgba8();
+        let mut bytes = Vec::new();
+        image::codecs::webp::WebPEncoder::new_lossless(&mut bytes)
+            .encode(img.as_raw(), 400, 100, image::ExtendedColorType::Rgba8)
+            .unwrap();
+        fs::write(&image_path, bytes).unwrap();
+
+        let thumbnail_path = get_or_create_thumbnail(&image_path, &cache_dir).unwrap();
+        let thumb_img = image::open(&thumbnail_path).unwrap();
+        assert_eq!(thumb_img.dimensions(), (THUMBNAIL_SIZE, 50));
+    }
+
+    #[test]
+    fn test_tiff_thumbnail() {
+        let dir = tempdir().unwrap();
+        let cache_dir = dir.path().join("cache");
+        let image_path = dir.path().join("skann.tif");
+
+        create_test_image(300, 150).save_with_format(&image_path, image::ImageFormat::Tiff).unwrap();
+
+        let thumbnail_path = get_or_create_thumbnail(&image_path, &cache_dir).unwrap();
+        let thumb_img = image::open(&thumbnail_path).unwrap();
+        assert_eq!(thumb_img.dimensions(), (THUMBNAIL_SIZE, 100));
+    }
+
+    #[test]
+    fn test_multipage_tiff_uses_first_page() {
+        let dir = tempdir().unwrap();
+        let cache_dir = dir.path().join("cache");
+        let image_path = dir.path().join("faks.tiff");
+
+        // Første side er bred, andre side er høy
+        fs::write(&image_path, create_multipage_tiff(&[(40, 10), (10, 40)])).unwrap();
+
+        let thumbnail_path = get_or_create_thumbnail(&image_path, &cache_dir).unwrap();
+        let thumb_img = image::open(&thumbnail_path).unwrap();
+        assert_eq!(thumb_img.dimensions(), (THUMBNAIL_SIZE, 50));
+    }
+
+    #[test]
+    fn test_exif_orientation_applied() {
+        use image::ImageEncoder;
+
+        let dir = tempdir().unwrap();
+        let cache_dir = dir.path().join("cache");
+        let image_path = dir.path().join("portrett.jpg");
+
+        // Liggende piksler med Orientation = 8 (roteres 90° mot klokka ved visning)
+        let img = create_test_image(400, 300).to_rgb8();
+        let mut encoder = image::codecs::jpeg::JpegEncoder::new(File::create(&image_path).unwrap());
+        let mut exif = b"II*\0\x08\0\0\0\x01\0\x12\x01\x03\0\x01\0\0\0\x08\0".to_vec();
+        exif.extend_from_slice(&[0; 6]);
+        encoder.set_exif_metadata(exif).unwrap();
+        encoder.write_image(img.as_raw(), 400, 300, image::ExtendedColorType::Rgb8).unwrap();
+
+        let thumbnail_path = get_or_create_thumbnail(&image_path, &cache_dir).unwrap();
+        let thumb_img = image::open(&thumbnail_path).unwrap();
+        assert_eq!(thumb_img.dimensions(), (150, THUMBNAIL_SIZE));
+    }
+
     #[test]
     fn test_nonexistent_image() {
         let dir = tempdir().unwrap();
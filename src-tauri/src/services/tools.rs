@@ -0,0 +1,393 @@
+//! Oppslag av eksterne verktøy (ffmpeg og ffprobe)
+//!
+//! Stien finnes i denne rekkefølgen: medfølgende sidecar ved siden av appen,
+//! miljøvariabel, brukerens innstilling og til slutt PATH. Første kandidat som svarer
+//! på `-version` brukes. Oppslaget gjøres én gang ved oppstart (`init`), og tjenestene
+//! henter stien med `path`. Mangler et verktøy, sier `status` fra om det, så
+//! videofunksjonene kan skrus av synlig i stedet for å feile i stillhet.
+//!
+//! Verktøyene kjøres med `run`, som bruker tidsgrensen og prosessgrensen fra innstillingene.
+
+use crate::services::process::{self, ProcessLimiter};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{OnceLock, RwLock};
+use std::time::Duration;
+
+/// Tidsgrense for `-version` ved oppslag, så en ødelagt binær ikke stopper oppstarten
+const VERSION_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Tool {
+    Ffmpeg,
+    Ffprobe,
+}
+
+impl Tool {
+    pub fn name(self) -> &'static str {
+        match self {
+            Tool::Ffmpeg => "ffmpeg",
+            Tool::Ffprobe => "ffprobe",
+        }
+    }
+
+    /// Miljøvariabel som kan peke til binæren eller mappen den ligger i
+    pub fn env_var(self) -> &'static str {
+        match self {
+            Tool::Ffmpeg => "HEIMDALL_FFMPEG",
+            Tool::Ffprobe => "HEIMDALL_FFPROBE",
+        }
+    }
+
+    /// Filnavnet på denne plattformen (`ffmpeg.exe` på Windows)
+    fn file_name(self) -> String {
+        format!("{}{}", self.name(), std::env::consts::EXE_SUFFIX)
+    }
+}
+
+/// Feilmelding når et verktøy trengs, men ikke ble funnet
+pub fn missing_message(tool: Tool) -> String {
+    format!(
+        "{} ble ikke funnet. Installer det, sett {} eller velg stien under Verktøy.",
+        tool.name(),
+        tool.env_var()
+    )
+}
+
+/// Hvor stien til et verktøy kom fra
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ToolSource {
+    Sidecar,
+    Environment,
+    Setting,
+    Path,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolvedTool {
+    pub path: PathBuf,
+    pub source: ToolSource,
+    /// Versjonen fra `-version`, f.eks. "6.1.1"
+    pub version: Option<String>,
+}
+
+/// Hvilke verktøy som er tilgjengelige. Frontend viser en advarsel når videofunksjoner mangler.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolStatus {
+    pub ffmpeg: Option<ResolvedTool>,
+    pub ffprobe: Option<ResolvedTool>,
+    /// Video-thumbnails og fingeravtrykk av videoer krever ffmpeg
+    pub ffmpeg_available: bool,
+    /// Opptaksdato for andre videoer enn MP4/MOV krever ffprobe
+    pub ffprobe_available: bool,
+}
+
+/// Brukerens egne stier og grenser. Stiene kan peke til binæren eller mappen den ligger i.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ToolSettings {
+    pub ffmpeg_path: Option<PathBuf>,
+    pub ffprobe_path: Option<PathBuf>,
+    /// Sekunder før en ffmpeg/ffprobe-prosess stoppes (standard 30)
+    pub timeout_seconds: Option<u64>,
+    /// Antall ffmpeg/ffprobe-prosesser som kan kjøre samtidig (standard 4)
+    pub max_processes: Option<usize>,
+}
+
+impl ToolSettings {
+    fn timeout(&self) -> Duration {
+        self.timeout_seconds
+            .filter(|&seconds| seconds > 0)
+            .map_or(process::DEFAULT_TIMEOUT, Duration::from_secs)
+    }
+
+    fn path_for(&self, tool: Tool) -> Option<&Path> {
+        match tool {
+            Tool::Ffmpeg => self.ffmpeg_path.as_deref(),
+            Tool::Ffprobe => self.ffprobe_path.as_deref(),
+        }
+    }
+
+    /// Stier fra miljøvariablene
+    fn from_environment() -> Self {
+        Self {
+            ffmpeg_path: std::env::var_os(Tool::Ffmpeg.env_var()).map(PathBuf::from),
+            ffprobe_path: std::env::var_os(Tool::Ffprobe.env_var()).map(PathBuf::from),
+            ..Default::default()
+        }
+    }
+
+    /// Leser innstillingene. En fil som mangler eller ikke kan leses gir standardverdier.
+    pub fn load(file: &Path) -> Self {
+        fs::read_to_string(file)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, file: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(parent) = file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(file, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// Stedene et verktøy kan ligge, i prioritert rekkefølge
+#[derive(Debug, Clone, Default)]
+pub struct ToolLocator {
+    sidecar_dirs: Vec<PathBuf>,
+    environment: ToolSettings,
+    settings: ToolSettings,
+    search_path: Vec<PathBuf>,
+}
+
+impl ToolLocator {
+    /// `sidecar_dirs` er mappene appen pakker med ffmpeg i. Miljøvariabler og PATH
+    /// leses her, én gang.
+    pub fn new(sidecar_dirs: Vec<PathBuf>, settings: ToolSettings) -> Self {
+        Self {
+            sidecar_dirs,
+            environment: ToolSettings::from_environment(),
+            settings,
+            search_path: std::env::var_os("PATH")
+                .map(|paths| std::env::split_paths(&paths).collect())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Samme oppslag med nye brukerinnstillinger
+    pub fn with_settings(&self, settings: ToolSettings) -> Self {
+        Self { settings, ..self.clone() }
+    }
+
+    /// Første kandidat som kan kjøres
+    pub fn resolve(&self, tool: Tool) -> Option<ResolvedTool> {
+        let file_name = tool.file_name();
+        let configured = |path: &Path| if path.is_dir() { path.join(&file_name) } else { path.to_path_buf() };
+
+        let candidates = self
+            .sidecar_dirs
+            .iter()
+            .map(|dir| (dir.join(&file_name), ToolSource::Sidecar))
+            .chain(self.environment.path_for(tool).map(|p| (configured(p), ToolSource::Environment)))
+            .chain(self.settings.path_for(tool).map(|p| (configured(p), ToolSource::Setting)))
+            .chain(self.search_path.iter().map(|dir| (dir.join(&file_name), ToolSource::Path)));
+
+        candidates
+            .filter(|(path, _)| path.is_file())
+            .find_map(|(path, source)| {
+                let version = probe_version(&path, tool)?;
+                Some(ResolvedTool { path, source, version })
+            })
+    }
+
+    pub fn status(&self) -> ToolStatus {
+        let ffmpeg = self.resolve(Tool::Ffmpeg);
+        let ffprobe = self.resolve(Tool::Ffprobe);
+        ToolStatus {
+            ffmpeg_available: ffmpeg.is_some(),
+            ffprobe_available: ffprobe.is_some(),
+            ffmpeg,
+            ffprobe,
+        }
+    }
+}
+
+/// Kjører `<verktøy> -version`. Returnerer None hvis binæren ikke kan kjøres, og
+/// Some(None) hvis den kjører, men versjonen ikke kan leses.
+fn probe_version(path: &Path, tool: Tool) -> Option<Option<String>> {
+    let mut command = Command::new(path);
+    command.arg("-version");
+    let stdout = process::run(command, VERSION_TIMEOUT, &LIMITER).ok()?;
+    Some(parse_version(&String::from_utf8_lossy(&stdout), tool))
+}
+
+/// Første linje ser ut som "ffmpeg version 6.1.1-3ubuntu5 Copyright (c) 2000-2023 ..."
+fn parse_version(output: &str, tool: Tool) -> Option<String> {
+    let prefix = format!("{} version ", tool.name());
+    let rest = output.lines().next()?.strip_prefix(&prefix)?;
+    rest.split_whitespace().next().map(str::to_string)
+}
+
+struct Registry {
+    locator: ToolLocator,
+    status: ToolStatus,
+    timeout: Duration,
+}
+
+impl Registry {
+    fn new(locator: ToolLocator) -> Self {
+        LIMITER.set_max(locator.settings.max_processes.unwrap_or(process::DEFAULT_MAX_PROCESSES));
+        Self {
+            status: locator.status(),
+            timeout: locator.settings.timeout(),
+            locator,
+        }
+    }
+}
+
+/// Felles grense for alle ffmpeg/ffprobe-prosesser appen starter
+static LIMITER: ProcessLimiter = ProcessLimiter::new(process::DEFAULT_MAX_PROCESSES);
+
+static REGISTRY: OnceLock<RwLock<Registry>> = OnceLock::new();
+
+/// Uten `init` (tester, verktøy uten Tauri) slås verktøyene opp i miljøvariabler og PATH
+fn registry() -> &'static RwLock<Registry> {
+    REGISTRY.get_or_init(|| RwLock::new(Registry::new(ToolLocator::new(Vec::new(), ToolSettings::default()))))
+}
+
+/// Slår opp verktøyene ved oppstart
+pub fn init(locator: ToolLocator) {
+    let registry = Registry::new(locator);
+    if let Err(registry) = REGISTRY.set(RwLock::new(registry)) {
+        *REGISTRY.get().unwrap().write().unwrap() = registry.into_inner().unwrap();
+    }
+}
+
+/// Slår opp på nytt etter at brukeren har endret stiene
+pub fn update_settings(settings: ToolSettings) -> ToolStatus {
+    let mut registry = registry().write().unwrap();
+    *registry = Registry::new(registry.locator.with_settings(settings));
+    registry.status.clone()
+}
+
+pub fn status() -> ToolStatus {
+    registry().read().unwrap().status.clone()
+}
+
+/// Stien til verktøyet, eller None hvis det ikke ble funnet
+pub fn path(tool: Tool) -> Option<PathBuf> {
+    let registry = registry().read().unwrap();
+    let resolved = match tool {
+        Tool::Ffmpeg => &registry.status.ffmpeg,
+        Tool::Ffprobe => &registry.status.ffprobe,
+    };
+    resolved.as_ref().map(|r| r.path.clone())
+}
+
+/// Kjører verktøyet med argumentene `configure` legger til, og returnerer stdout.
+/// Feiler hvis verktøyet mangler, avslutter med feil (med stderr i meldingen) eller
+/// ikke blir ferdig innen tidsgrensen.
+pub fn run(tool: Tool, configure: impl FnOnce(&mut Command)) -> Result<Vec<u8>, Box<dyn std::error::Error + Send + Sync>> {
+    let program = path(tool).ok_or_else(|| missing_message(tool))?;
+    let timeout = registry().read().unwrap().timeout;
+
+    let mut command = Command::new(program);
+    configure(&mut command);
+    Ok(process::run(command, timeout, &LIMITER)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    /// Lager et falskt verktøy som skriver versjonslinjen og avslutter med `exit_code`
+    #[cfg(unix)]
+    fn fake_tool(dir: &Path, tool: Tool, version: &str, exit_code: i32) -> PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+        fs::create_dir_all(dir).unwrap();
+        let path = dir.join(tool.file_name());
+        let script = format!(
+            "#!/bin/sh\necho '{} version {} Copyright (c) 2000-2024 the FFmpeg developers'\nexit {}\n",
+            tool.name(),
+            version,
+            exit_code
+        );
+        fs::write(&path, script).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_parse_version() {
+        let output = "ffmpeg version 6.1.1-3ubuntu5 Copyright (c) 2000-2023 the FFmpeg developers\nbuilt with gcc";
+        assert_eq!(parse_version(output, Tool::Ffmpeg), Some("6.1.1-3ubuntu5".to_string()));
+        assert_eq!(parse_version(output, Tool::Ffprobe), None);
+        assert_eq!(parse_version("", Tool::Ffmpeg), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resolution_order() {
+        let dir = tempdir().unwrap();
+        let sidecar = dir.path().join("app");
+        let env = dir.path().join("env");
+        let setting = dir.path().join("setting");
+        let path_dir = dir.path().join("bin");
+        fake_tool(&sidecar, Tool::Ffmpeg, "7.0-sidecar", 0);
+        fake_tool(&env, Tool::Ffmpeg, "6.0-env", 0);
+        fake_tool(&setting, Tool::Ffmpeg, "5.0-setting", 0);
+        fake_tool(&path_dir, Tool::Ffmpeg, "4.0-path", 0);
+
+        let mut locator = ToolLocator {
+            sidecar_dirs: vec![sidecar.clone()],
+            environment: ToolSettings { ffmpeg_path: Some(env.join("ffmpeg")), ..Default::default() },
+            settings: ToolSettings { ffmpeg_path: Some(setting.clone()), ..Default::default() },
+            search_path: vec![path_dir],
+        };
+
+        let expected = [
+            (ToolSource::Sidecar, "7.0-sidecar"),
+            (ToolSource::Environment, "6.0-env"),
+            (ToolSource::Setting, "5.0-setting"),
+            (ToolSource::Path, "4.0-path"),
+        ];
+        for (source, version) in expected {
+            let resolved = locator.resolve(Tool::Ffmpeg).unwrap();
+            assert_eq!(resolved.source, source);
+            assert_eq!(resolved.version.as_deref(), Some(version));
+            // Fjern kandidaten så neste i rekkefølgen brukes
+            match source {
+                ToolSource::Sidecar => locator.sidecar_dirs.clear(),
+                ToolSource::Environment => locator.environment = ToolSettings::default(),
+                ToolSource::Setting => locator.settings = ToolSettings::default(),
+                ToolSource::Path => locator.search_path.clear(),
+            }
+        }
+        assert!(locator.resolve(Tool::Ffmpeg).is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_broken_sidecar_falls_back_to_path() {
+        let dir = tempdir().unwrap();
+        fake_tool(&dir.path().join("app"), Tool::Ffprobe, "7.0", 1);
+        let good = fake_tool(&dir.path().join("bin"), Tool::Ffprobe, "6.0", 0);
+
+        let locator = ToolLocator {
+            sidecar_dirs: vec![dir.path().join("app")],
+            search_path: vec![dir.path().join("bin")],
+            ..Default::default()
+        };
+        let status = locator.status();
+        assert!(status.ffprobe_available);
+        assert_eq!(status.ffprobe.unwrap().path, good);
+        // Ingen ffmpeg noe sted
+        assert!(!status.ffmpeg_available);
+        assert!(status.ffmpeg.is_none());
+    }
+
+    #[test]
+    fn test_settings_roundtrip() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("config").join("tools.json");
+        assert_eq!(ToolSettings::load(&file), ToolSettings::default());
+
+        let settings = ToolSettings {
+            ffmpeg_path: Some(PathBuf::from("/opt/ffmpeg/bin")),
+            timeout_seconds: Some(120),
+            ..Default::default()
+        };
+        settings.save(&file).unwrap();
+        assert_eq!(ToolSettings::load(&file), settings);
+    }
+}
@@ -0,0 +1,279 @@
+//! Fingeravtrykk av videoer for å finne nesten like kopier
+//!
+//! Et fingeravtrykk er varigheten pluss perceptuelle hasher av bilder jevnt fordelt
+//! gjennom videoen, hentet ut med ffmpeg. Omkodede eller nedskalerte kopier gir nesten
+//! like hasher, og sammenligningen tåler at starten eller slutten er klippet bort.
+
+use crate::services::clustering::{self, ClusterMode, Neighbours};
+use crate::services::hashing::{self, HashType, ImageHash};
+use crate::services::metadata;
+use crate::services::tools::{self, Tool};
+use image::DynamicImage;
+use std::path::Path;
+
+/// Antall bilder som hashes per video
+pub const FINGERPRINT_FRAMES: usize = 8;
+
+/// Hvor stor del av den lengste videoen som kan være klippet bort i kopien
+const MAX_TRIM_FRACTION: f64 = 0.25;
+
+/// Distanse mellom videoer som ikke kan være kopier av hverandre (for ulik varighet).
+/// Større enn enhver terskel.
+const NO_MATCH: u32 = u32::MAX / 2;
+
+/// Bredden bildene skaleres til før hashing. Hashene bruker uansett bare noen få piksler.
+const FRAME_WIDTH: u32 = 256;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct VideoFingerprint {
+    /// Varighet i millisekunder
+    pub duration_ms: u64,
+    /// Hash av bilde nr. `i` tatt ved (i + 0.5) / N av varigheten
+    pub frames: Vec<ImageHash>,
+}
+
+impl VideoFingerprint {
+    /// Tidspunktet bilde nr. `i` er hentet fra, i millisekunder
+    fn frame_time_ms(&self, i: usize) -> f64 {
+        self.duration_ms as f64 * (i as f64 + 0.5) / self.frames.len() as f64
+    }
+
+    /// Gjennomsnittlig Hamming-distanse per bilde når videoene legges over hverandre.
+    /// Prøver både felles start og felles slutt, så en kopi der starten (eller slutten)
+    /// er klippet bort fortsatt matcher.
+    pub fn distance(&self, other: &VideoFingerprint) -> u32 {
+        let (long, short) = if self.duration_ms >= other.duration_ms { (self, other) } else { (other, self) };
+        if long.duration_ms == 0 || long.frames.is_empty() || short.frames.is_empty() {
+            return NO_MATCH;
+        }
+        let trim = long.duration_ms - short.duration_ms;
+        if trim as f64 > long.duration_ms as f64 * MAX_TRIM_FRACTION {
+            return NO_MATCH;
+        }
+
+        [0, trim]
+            .iter()
+            .map(|&offset| aligned_distance(long, short, offset as f64))
+            .min()
+            .unwrap_or(NO_MATCH)
+    }
+
+    /// Streng som lagres i hash-cachen: "varighet:hash,hash,..."
+    pub fn to_cache_string(&self) -> String {
+        let frames: Vec<String> = self.frames.iter().map(ImageHash::to_hex).collect();
+        format!("{}:{}", self.duration_ms, frames.join(","))
+    }
+
+    pub fn from_cache_string(value: &str) -> Option<Self> {
+        let (duration, frames) = value.split_once(':')?;
+        let frames = frames.split(',').map(ImageHash::from_hex).collect::<Option<Vec<_>>>()?;
+        Some(Self {
+            duration_ms: duration.parse().ok()?,
+            frames,
+        })
+    }
+}
+
+/// Snittdistanse når `short` starter `offset_ms` inn i `long`. Hvert bilde i `short`
+/// sammenlignes med bildet i `long` som ligger nærmest i tid.
+fn aligned_distance(long: &VideoFingerprint, short: &VideoFingerprint, offset_ms: f64) -> u32 {
+    let step = long.duration_ms as f64 / long.frames.len() as f64;
+    let total: u32 = short
+        .frames
+        .iter()
+        .enumerate()
+        .map(|(i, hash)| {
+            let time = short.frame_time_ms(i) + offset_ms;
+            let nearest = ((time / step - 0.5).round().max(0.0) as usize).min(long.frames.len() - 1);
+            hash.dist(&long.frames[nearest])
+        })
+        .sum();
+    (total as f64 / short.frames.len() as f64).round() as u32
+}
+
+/// Deler videoene i klynger. Distansen er ingen metrikk (justeringen velges per par,
+/// og videoer med for ulik varighet kan ikke sammenlignes i det hele tatt), så den kan
+/// ikke beskjære et BK-tre. Videoene sorteres i stedet på varighet, og hver video
+/// sammenlignes parvis med dem som har en varighet innenfor klippetoleransen.
+pub fn cluster_videos(keys: &[VideoFingerprint], threshold: u32, mode: ClusterMode) -> Vec<Vec<usize>> {
+    let mut by_duration: Vec<usize> = (0..keys.len()).collect();
+    by_duration.sort_by_key(|&idx| keys[idx].duration_ms);
+    clustering::cluster_with(keys.len(), &DurationIndex { keys, by_duration }, threshold, mode)
+}
+
+/// Videoene sortert på varighet, for å finne dem som kan være klipp av hverandre
+struct DurationIndex<'a> {
+    keys: &'a [VideoFingerprint],
+    by_duration: Vec<usize>,
+}
+
+impl Neighbours for DurationIndex<'_> {
+    fn find(&self, idx: usize, threshold: u32) -> Vec<usize> {
+        // Kortere videoer kan mangle opptil MAX_TRIM_FRACTION av denne, og lengre videoer
+        // kan være opptil 1 / (1 - MAX_TRIM_FRACTION) ganger så lange
+        let duration = self.keys[idx].duration_ms as f64;
+        let shortest = duration * (1.0 - MAX_TRIM_FRACTION);
+        let longest = duration / (1.0 - MAX_TRIM_FRACTION);
+        let start = self.by_duration.partition_point(|&other| (self.keys[other].duration_ms as f64) < shortest);
+        let end = self.by_duration.partition_point(|&other| (self.keys[other].duration_ms as f64) <= longest);
+
+        self.by_duration[start..end]
+            .iter()
+            .copied()
+            .filter(|&other| self.within(idx, other, threshold))
+            .collect()
+    }
+
+    fn within(&self, a: usize, b: usize, threshold: u32) -> bool {
+        self.keys[a].distance(&self.keys[b]) <= threshold
+    }
+}
+
+/// Lager fingeravtrykk av en video med ffprobe (varighet) og ffmpeg (bildene)
+pub fn compute_fingerprint(
+    path: &Path,
+    hash_type: HashType,
+    hash_size: u32,
+) -> Result<VideoFingerprint, Box<dyn std::error::Error>> {
+    let duration = probe_duration(path)?;
+    let frames = (0..FINGERPRINT_FRAMES)
+        .map(|i| {
+            let seconds = duration * (i as f64 + 0.5) / FINGERPRINT_FRAMES as f64;
+            let frame = extract_frame(path, seconds)?;
+            hashing::compute_perceptual_hash_with_size(&frame, hash_type, hash_size)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(VideoFingerprint {
+        duration_ms: (duration * 1000.0).round() as u64,
+        frames,
+    })
+}
+
+/// Varighet i sekunder fra containeren
+fn probe_duration(path: &Path) -> Result<f64, Box<dyn std::error::Error>> {
+    let meta = metadata::read_video_metadata(path)
+        .map_err(|e| format!("ffprobe kunne ikke lese videoen {}: {}", path.display(), e))?;
+    meta.duration_ms
+        .map(|ms| ms as f64 / 1000.0)
+        .ok_or_else(|| format!("Ukjent varighet for videoen: {}", path.display()).into())
+}
+
+/// Henter ut ett bilde ved gitt tidspunkt som PNG via stdout.
+/// `-ss` før `-i` hopper raskt til nærmeste keyframe og dekoder derfra til tidspunktet.
+fn extract_frame(path: &Path, seconds: f64) -> Result<DynamicImage, Box<dyn std::error::Error>> {
+    let stdout = tools::run(Tool::Ffmpeg, |command| {
+        command
+            .args(["-v", "error", "-ss", &format!("{:.3}", seconds), "-i"])
+            .arg(path)
+            .args(["-frames:v", "1", "-vf", &format!("scale={}:-1", FRAME_WIDTH)])
+            .args(["-f", "image2pipe", "-vcodec", "png", "-"]);
+    })
+    .map_err(|e| format!("ffmpeg kunne ikke hente bilde ved {:.1} s i {}: {}", seconds, path.display(), e))?;
+    if stdout.is_empty() {
+        return Err(format!("ffmpeg fant ikke noe bilde ved {:.1} s i {}", seconds, path.display()).into());
+    }
+
+    Ok(image::load_from_memory_with_format(&stdout, image::ImageFormat::Png)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tilfeldig, men fast hash for en scene
+    fn scene_hash(scene: u64) -> ImageHash {
+        ImageHash::Bits64(scene.wrapping_mul(0x9E37_79B9_7F4A_7C15).rotate_left(17))
+    }
+
+    /// Fingeravtrykk av utsnittet `start_ms..end_ms` av en video der hver scene varer 10 s
+    fn clip(start_ms: u64, end_ms: u64) -> VideoFingerprint {
+        let duration_ms = end_ms - start_ms;
+        let frames = (0..FINGERPRINT_FRAMES)
+            .map(|i| {
+                let time = start_ms as f64 + duration_ms as f64 * (i as f64 + 0.5) / FINGERPRINT_FRAMES as f64;
+                scene_hash(time as u64 / 10_000)
+            })
+            .collect();
+        VideoFingerprint { duration_ms, frames }
+    }
+
+    #[test]
+    fn test_reencoded_copy_matches() {
+        let original = clip(0, 40_000);
+        // Omkoding endrer varigheten litt og noen få bit i hver hash
+        let mut copy = clip(0, 40_000);
+        copy.duration_ms += 33;
+        for (i, frame) in copy.frames.iter_mut().enumerate() {
+            *frame = ImageHash::Bits64(frame.words()[0] ^ (1 << i));
+        }
+
+        assert_eq!(original.distance(&original), 0);
+        assert_eq!(original.distance(&copy), 1);
+        assert_eq!(copy.distance(&original), 1);
+    }
+
+    #[test]
+    fn test_trimmed_start_matches() {
+        let original = clip(0, 40_000);
+        let trimmed = clip(8_000, 40_000);
+
+        assert_eq!(original.distance(&trimmed), 0);
+        // Uten å justere for klippet ville bildene ikke truffet samme scene
+        assert!(aligned_distance(&original, &trimmed, 0.0) > 10);
+
+        let trimmed_end = clip(0, 33_000);
+        assert_eq!(original.distance(&trimmed_end), 0);
+    }
+
+    #[test]
+    fn test_different_videos_do_not_match() {
+        let original = clip(0, 40_000);
+        let other = clip(1_000_000, 1_040_000);
+        assert!(original.distance(&other) > 10);
+
+        // Halvparten klippet bort er utenfor toleransen
+        assert_eq!(original.distance(&clip(20_000, 40_000)), NO_MATCH);
+    }
+
+    /// Fingeravtrykk av en video med bare én scene, så bare varigheten skiller dem
+    fn still_video(duration_ms: u64) -> VideoFingerprint {
+        VideoFingerprint { duration_ms, frames: vec![scene_hash(0); FINGERPRINT_FRAMES] }
+    }
+
+    #[test]
+    fn test_cluster_videos_compares_neighbouring_durations() {
+        // 40 s og 32 s kan være klipp av hverandre, og 32 s og 25 s, men ikke 40 s og 25 s.
+        // Et BK-tre med 40 s som rot ville lagt 25 s under NO_MATCH og beskjært den bort
+        // fra oppslaget på 32 s.
+        let keys = vec![still_video(40_000), still_video(32_000), still_video(25_000)];
+        assert_eq!(keys[1].distance(&keys[2]), 0);
+        assert_eq!(keys[0].distance(&keys[2]), NO_MATCH);
+
+        assert_eq!(cluster_videos(&keys, 0, ClusterMode::Transitive), vec![vec![0, 1, 2]]);
+        assert_eq!(cluster_videos(&keys, 0, ClusterMode::CompleteLink), vec![vec![0, 1], vec![2]]);
+
+        // 32 s og 25 s finner hverandre også når de ikke er med i samme klynge som 40 s
+        let reversed: Vec<VideoFingerprint> = keys.into_iter().rev().collect();
+        assert_eq!(cluster_videos(&reversed, 0, ClusterMode::Leader), vec![vec![0, 1], vec![2]]);
+    }
+
+    #[test]
+    fn test_cache_string_roundtrip() {
+        let fingerprint = clip(0, 12_345);
+        let cached = fingerprint.to_cache_string();
+        assert!(cached.starts_with("12345:"));
+        assert_eq!(VideoFingerprint::from_cache_string(&cached), Some(fingerprint));
+
+        // Bildehasher i cachen er ikke gyldige fingeravtrykk
+        assert_eq!(VideoFingerprint::from_cache_string("0123456789abcdef"), None);
+        assert_eq!(VideoFingerprint::from_cache_string("abc:0123456789abcdef"), None);
+    }
+
+    #[test]
+    fn test_unreadable_video_is_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("finnes_ikke.mp4");
+        assert!(compute_fingerprint(&path, HashType::Difference, 8).is_err());
+    }
+}
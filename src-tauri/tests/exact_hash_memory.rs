@@ -0,0 +1,91 @@
+//! Minnebruk ved eksakt hashing av store filer
+//!
+//! Egen testbinær fordi den bytter ut allokatoren: alt som allokeres telles per tråd.
+//! Høyeste RSS for prosessen (VmHWM) påvirkes av tester som kjører parallelt, men denne
+//! tellingen ser bare testens egen tråd.
+
+use heimdall_sort_lib::services::hashing::{compute_exact_hash, compute_exact_hash_mmap};
+use sha2::{Digest, Sha256};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::fs::File;
+use std::io::Write;
+
+thread_local! {
+    static CURRENT: Cell<isize> = const { Cell::new(0) };
+    static PEAK: Cell<isize> = const { Cell::new(0) };
+}
+
+struct CountingAllocator;
+
+fn record(delta: isize) {
+    // `try_with` fordi trådlokale verdier kan være borte mens tråden avsluttes
+    let _ = CURRENT.try_with(|current| {
+        let value = current.get() + delta;
+        current.set(value);
+        let _ = PEAK.try_with(|peak| peak.set(peak.get().max(value)));
+    });
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            record(layout.size() as isize);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        record(-(layout.size() as isize));
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            record(new_size as isize - layout.size() as isize);
+        }
+        new_ptr
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Høyeste mengde minne `f` hadde allokert samtidig på denne tråden, i byte
+fn peak_allocated<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let start = CURRENT.with(Cell::get);
+    PEAK.with(|peak| peak.set(start));
+    let result = f();
+    let peak = PEAK.with(Cell::get);
+    (result, (peak - start).max(0) as usize)
+}
+
+#[test]
+fn test_exact_hash_large_file_flat_memory() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("large.bin");
+    let mut file = File::create(&path).unwrap();
+    let block: Vec<u8> = (0..1024 * 1024).map(|i| (i % 251) as u8).collect();
+    for _ in 0..16 {
+        file.write_all(&block).unwrap();
+    }
+    drop(file);
+
+    let mut hasher = Sha256::new();
+    for _ in 0..16 {
+        hasher.update(&block);
+    }
+    let expected = hex::encode(hasher.finalize());
+
+    // Gammel implementasjon leste hele filen inn i en Vec (+16 MB)
+    let (streamed, allocated) = peak_allocated(|| compute_exact_hash(&path).unwrap());
+    assert!(allocated < 1024 * 1024, "Minnebruken skal være flat, allokerte {} byte", allocated);
+    assert_eq!(streamed, expected);
+
+    // Mappingen er ikke allokert minne, så også her skal tellingen være flat
+    let (mapped, allocated) = peak_allocated(|| compute_exact_hash_mmap(&path).unwrap());
+    assert!(allocated < 1024 * 1024, "Minnebruken skal være flat, allokerte {} byte", allocated);
+    assert_eq!(mapped, expected);
+}
@@ -4,12 +4,16 @@ import { open } from '@tauri-apps/plugin-dialog';
 // Actually I'll implement scanFolder here.
 
 import { state } from "./modules/state";
-import { ScanResult } from "./modules/types";
+import { ScanResult, ToolStatus } from "./modules/types";
+import { toast } from "./modules/toast";
 import { elements, updateStatus, showImportSuccess, toggleView } from "./modules/ui";
-import { initGallery } from "./modules/gallery";
+import { initGallery, listenForScanBatches, applyHardlinkAliases } from "./modules/gallery";
 import { renderVirtualItems } from "./modules/virtual-scroll";
+import { openToolSettings } from "./modules/tools";
 
 export function setupApp() {
+    checkTools();
+
     // Event Listeners for Import View
     elements.selectFolderBtn?.addEventListener("click", async () => {
         try {
@@ -34,6 +38,8 @@ export function setupApp() {
         }
     });
 
+    elements.toolSettingsBtn?.addEventListener("click", () => openToolSettings());
+
     elements.changeFolderBtn?.addEventListener("click", () => {
         toggleView('import');
         updateStatus("Velg en mappe for å starte");
@@ -84,18 +90,39 @@ export function setupApp() {
     });
 }
 
-async function performScan(path: string) {
+/** Advarer ved oppstart hvis videofunksjoner er slått av fordi ffmpeg/ffprobe mangler */
+async function checkTools() {
     try {
-        const result = await invoke<ScanResult>("scan_folder", { path });
+        const status = await invoke<ToolStatus>("get_tool_status");
+        if (!status.ffmpegAvailable) {
+            toast.show("ffmpeg ble ikke funnet - video-thumbnails og videoduplikater er slått av. Velg stien under Verktøy.", "warning", 8000);
+        }
+        if (!status.ffprobeAvailable) {
+            toast.show("ffprobe ble ikke funnet - AVI-, MKV- og WMV-videoer får ingen opptaksdato. Velg stien under Verktøy.", "warning", 8000);
+        }
+    } catch (error) {
+        console.error("Kunne ikke sjekke ffmpeg:", error);
+    }
+}
 
-        state.setImages(result.images);
-        state.clearSelection();
+async function performScan(path: string) {
+    // Galleriet fylles fortløpende fra "scan-batch"-events; svaret er bare en oppsummering
+    const jobId = `scan-${Date.now()}`;
+    state.setImages([]);
+    state.clearSelection();
+    initGallery();
+
+    const unlisten = await listenForScanBatches(jobId);
+    try {
+        const result = await invoke<ScanResult>("scan_folder", { path, jobId, stream: true });
 
+        applyHardlinkAliases(result.hardlinkGroups);
         showImportSuccess(result.imageCount, result.totalSizeBytes);
-        initGallery();
 
     } catch (error) {
         console.error("Feil ved skanning:", error);
         updateStatus(`Feil: ${error}`);
+    } finally {
+        unlisten();
     }
 }
@@ -6,6 +6,7 @@ document.querySelector<HTMLDivElement>("#app")!.innerHTML = `
     <header class="header" id="main-header">
       <h1>🛡️ Heimdall Sort</h1>
       <p class="subtitle">Sorter bilder og finn duplikater</p>
+      <button class="btn btn-secondary btn-sm" id="tool-settings-btn">⚙️ Verktøy</button>
     </header>
 
     <div id="toolbar-container" class="toolbar hidden">
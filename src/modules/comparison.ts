@@ -1,7 +1,7 @@
 import { convertFileSrc } from "@tauri-apps/api/core";
 import { invoke } from "@tauri-apps/api/core";
 import { toast } from "./toast";
-import { ImageInfo, DuplicateGroup, OperationResult } from "./types";
+import { ImageInfo, DuplicateGroup, MediaMetadata, OperationResult } from "./types";
 import { updateStatus } from "./ui";
 
 export class ComparisonManager {
@@ -113,8 +113,60 @@ export class ComparisonManager {
         const leftContainer = document.getElementById("comp-left");
         const rightContainer = document.getElementById("comp-right");
 
-        if (leftContainer) leftContainer.innerHTML = this.createImageCard(this.originalImage, "Original");
-        if (rightContainer) rightContainer.innerHTML = this.createImageCard(this.currentCandidate, "Kandidat");
+        if (leftContainer) {
+            leftContainer.innerHTML = this.createImageCard(this.originalImage, "Original");
+            leftContainer.dataset.path = this.originalImage.path;
+        }
+        if (rightContainer) {
+            rightContainer.innerHTML = this.createImageCard(this.currentCandidate, "Kandidat");
+            rightContainer.dataset.path = this.currentCandidate.path;
+        }
+
+        this.loadMetadata(this.originalImage, leftContainer);
+        this.loadMetadata(this.currentCandidate, rightContainer);
+    }
+
+    /** Legger til opptaksdato og videodetaljer når de er lest (ffprobe kan bruke litt tid) */
+    private async loadMetadata(img: ImageInfo, container: HTMLElement | null) {
+        if (!container) return;
+        try {
+            const meta = await invoke<MediaMetadata>("get_media_metadata", { path: img.path });
+            // Kortet kan ha blitt byttet ut mens vi ventet
+            if (container.dataset.path !== img.path) return;
+            const rows = container.querySelector(".comp-meta");
+            for (const text of this.metadataRows(meta)) {
+                const row = document.createElement("div");
+                row.className = "meta-row";
+                row.textContent = text;
+                rows?.appendChild(row);
+            }
+        } catch (e) {
+            console.warn("Kunne ikke lese metadata:", e);
+        }
+    }
+
+    private metadataRows(meta: MediaMetadata): string[] {
+        const rows: string[] = [];
+        if (meta.taken) rows.push(`📅 ${meta.taken.replace("T", " ")}`);
+
+        const video = meta.video;
+        if (!video) return rows;
+        if (video.width && video.height) {
+            const rotation = video.rotation ? ` (rotert ${video.rotation}°)` : "";
+            rows.push(`📐 ${video.width}×${video.height}${rotation}`);
+        }
+        const details = [
+            video.codec,
+            video.frameRate ? `${video.frameRate.toFixed(2)} fps` : null,
+            video.durationMs !== null ? `${(video.durationMs / 1000).toFixed(1)} s` : null,
+        ].filter(Boolean);
+        if (details.length > 0) rows.push(`🎬 ${details.join(" · ")}`);
+        const camera = [video.make, video.model].filter(Boolean).join(" ");
+        if (camera) rows.push(`📷 ${camera}`);
+        if (video.location) {
+            rows.push(`📍 ${video.location.latitude.toFixed(5)}, ${video.location.longitude.toFixed(5)}`);
+        }
+        return rows;
     }
 
     private createImageCard(img: ImageInfo, label: string): string {
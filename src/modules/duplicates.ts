@@ -2,7 +2,7 @@ import { invoke } from "@tauri-apps/api/core";
 import { toast } from "./toast";
 import { convertFileSrc } from "@tauri-apps/api/core";
 import { listen } from "@tauri-apps/api/event";
-import { DuplicateResult, DuplicateGroup, ImageInfo, OperationResult } from "./types";
+import { DuplicateResult, DuplicateGroup, ImageInfo, OperationResult, ProgressEvent } from "./types";
 import { state, CONFIG } from "./state";
 import { updateStatus } from "./ui";
 import { comparisonManager } from "./comparison";
@@ -20,10 +20,11 @@ export async function startDuplicateDetection() {
 
         const paths = state.currentImages.map((img) => img.path);
 
-        let processedCount = 0;
-        const unlisten = await listen("progress", () => {
-            processedCount++;
-            updateStatus(`Analyserer ${processedCount}/${paths.length} bilder...`);
+        const unlisten = await listen<ProgressEvent>("progress", (event) => {
+            const { phase, done, total } = event.payload;
+            if (phase === "visualStage") {
+                updateStatus(`Analyserer ${done}/${total ?? paths.length} bilder...`);
+            }
         });
 
         const result = await invoke<DuplicateResult>("find_duplicates", {
@@ -189,6 +190,9 @@ async function deleteSelectedDuplicates(section: HTMLElement) {
     try {
         const result = await invoke<OperationResult>("delete_images", { paths: selectedPaths });
         toast.show(`Slettet ${result.success} bilder.`, "success");
+        if (result.remainingPartners.length > 0) {
+            toast.show(`${result.remainingPartners.length} RAW+JPEG-partnere ble beholdt`, "info", 6000);
+        }
 
         selectedPaths.forEach(path => {
             const item = section.querySelector(`.gallery-item[data-path="${CSS.escape(path)}"]`);
@@ -1,9 +1,10 @@
 import { invoke } from "@tauri-apps/api/core";
 import { convertFileSrc } from "@tauri-apps/api/core";
 import { open } from '@tauri-apps/plugin-dialog';
-import { ImageInfo, OperationResult } from "./types";
+import { listen, UnlistenFn } from "@tauri-apps/api/event";
+import { ImageInfo, OperationResult, ScanBatch } from "./types";
 import { state } from "./state";
-import { setupVirtualScroll, renderVirtualItems } from "./virtual-scroll";
+import { setupVirtualScroll, renderVirtualItems, refreshVirtualItems } from "./virtual-scroll";
 import { startDuplicateDetection } from "./duplicates"; // Forward reference
 import { updateStatus } from "./ui";
 import { toast } from "./toast";
@@ -12,9 +13,8 @@ let scrollContainer: HTMLDivElement | null = null;
 let spacer: HTMLDivElement | null = null;
 let scrollContent: HTMLDivElement | null = null;
 
-const VIDEO_EXTENSIONS = ["mp4", "mov", "avi", "mkv", "webm", "wmv", "m4v"];
-function isVideo(ext: string): boolean {
-    return VIDEO_EXTENSIONS.includes(ext.toLowerCase());
+function isVideo(img: ImageInfo): boolean {
+    return img.kind === "video" || img.kind === "livePhotoVideo";
 }
 
 export function initGallery() {
@@ -68,6 +68,34 @@ export function initGallery() {
     setupVirtualScroll(scrollContainer, spacer, scrollContent, createGalleryItem);
 }
 
+/**
+ * Legger bilder fra "scan-batch"-events for `jobId` til i galleriet mens skanningen pågår.
+ * Events fra andre jobber ignoreres. Kall den returnerte funksjonen når skanningen er ferdig.
+ */
+export function listenForScanBatches(jobId: string): Promise<UnlistenFn> {
+    return listen<ScanBatch>("scan-batch", (event) => {
+        if (event.payload.jobId !== jobId) return;
+
+        state.currentImages.push(...event.payload.images);
+        if (document.getElementById("gallery-section")) {
+            refreshVirtualItems();
+        } else {
+            initGallery();
+        }
+        updateStatus(`Skanner mappe... ${state.currentImages.length} filer funnet`);
+    });
+}
+
+/** Setter `aliases` fra skannesammendraget - hardlenker er først kjent når skanningen er ferdig */
+export function applyHardlinkAliases(hardlinkGroups: string[][]) {
+    const aliases = new Map(hardlinkGroups.map((group) => [group[0], group.slice(1)]));
+    for (const img of state.currentImages) {
+        const paths = aliases.get(img.path);
+        if (paths) img.aliases = paths;
+    }
+    refreshVirtualItems();
+}
+
 function updateGalleryHeader(header: HTMLElement) {
     header.innerHTML = `
       <h2>📷 Bilder (${state.currentImages.length})</h2>
@@ -105,7 +133,7 @@ export function createGalleryItem(img: ImageInfo, index: number): HTMLDivElement
         <div class="thumbnail-placeholder"></div>
         <img src="" alt="${img.filename}" style="display: none;" />
         <div class="gallery-item-overlay">
-            ${isVideo(img.extension) ? '<div class="video-indicator">▶</div>' : ''}
+            ${isVideo(img) ? '<div class="video-indicator">▶</div>' : ''}
         </div>
       </div>
       <div class="gallery-item-info">
@@ -212,6 +240,15 @@ async function deleteSelected() {
         const paths = selected.map(img => img.path);
         const result = await invoke<OperationResult>("delete_images", { paths });
 
+        // Den andre halvdelen av et RAW+JPEG-par slettes bare når brukeren bekrefter det
+        const partners = result.remainingPartners;
+        if (partners.length > 0 && confirm(`${partners.length} RAW+JPEG-partnere var ikke valgt. Vil du slette dem også?`)) {
+            const partnerResult = await invoke<OperationResult>("delete_images", { paths: partners });
+            result.success += partnerResult.success;
+            result.errors += partnerResult.errors;
+            paths.push(...partners);
+        }
+
         let msg = `Slettet ${result.success} bilder.`;
         if (result.errors > 0) msg += ` ${result.errors} feil.`;
 
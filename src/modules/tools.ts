@@ -0,0 +1,107 @@
+import { invoke } from "@tauri-apps/api/core";
+import { open } from '@tauri-apps/plugin-dialog';
+import { ResolvedTool, Tool, ToolSettings, ToolSource, ToolStatus } from "./types";
+import { toast } from "./toast";
+
+const SOURCE_LABELS: Record<ToolSource, string> = {
+    sidecar: "medfølger appen",
+    environment: "miljøvariabel",
+    setting: "valgt her",
+    path: "PATH",
+};
+
+function escapeHtml(text: string): string {
+    const div = document.createElement("div");
+    div.textContent = text;
+    return div.innerHTML;
+}
+
+function describeTool(tool: ResolvedTool | null): string {
+    if (!tool) return `<span class="tool-missing">Ikke funnet</span>`;
+    const version = tool.version ? ` ${escapeHtml(tool.version)}` : "";
+    return `Versjon${version} (${SOURCE_LABELS[tool.source]})<br><small>${escapeHtml(tool.path)}</small>`;
+}
+
+function toolRow(tool: Tool, resolved: ResolvedTool | null, chosenPath: string | null | undefined): string {
+    return `
+        <div class="form-group">
+            <label for="tool-path-${tool}"><strong>${tool}</strong></label>
+            <p class="tool-status" id="tool-status-${tool}">${describeTool(resolved)}</p>
+            <div class="tool-path-row">
+                <input type="text" id="tool-path-${tool}" placeholder="Sti til ${tool} eller mappen den ligger i" value="${escapeHtml(chosenPath ?? "")}">
+                <button class="btn btn-secondary btn-sm" id="tool-browse-${tool}">Bla gjennom</button>
+            </div>
+        </div>
+    `;
+}
+
+/** Dialog som viser hvilke ffmpeg/ffprobe appen bruker, og lar brukeren velge egne stier */
+export async function openToolSettings() {
+    let status: ToolStatus;
+    let settings: ToolSettings;
+    try {
+        [status, settings] = await Promise.all([
+            invoke<ToolStatus>("get_tool_status"),
+            invoke<ToolSettings>("get_tool_settings"),
+        ]);
+    } catch (error) {
+        toast.show(`Kunne ikke hente verktøyene: ${error}`, "error");
+        return;
+    }
+
+    const overlay = document.createElement("div");
+    overlay.className = "modal-overlay";
+    overlay.innerHTML = `
+        <div class="modal">
+            <div class="modal-header">
+                <h3>Verktøy for video</h3>
+            </div>
+            <div class="modal-content">
+                <p>Miljøvariabler og ffmpeg som følger med appen brukes før stiene valgt her.</p>
+                ${toolRow("ffmpeg", status.ffmpeg, settings.ffmpegPath)}
+                ${toolRow("ffprobe", status.ffprobe, settings.ffprobePath)}
+            </div>
+            <div class="modal-footer">
+                <button class="btn btn-secondary" id="tool-cancel">Lukk</button>
+                <button class="btn btn-primary" id="tool-save">Lagre</button>
+            </div>
+        </div>
+    `;
+
+    document.body.appendChild(overlay);
+    requestAnimationFrame(() => overlay.classList.add("open"));
+
+    const close = () => {
+        overlay.classList.remove("open");
+        setTimeout(() => overlay.remove(), 300);
+    };
+    const pathInput = (tool: Tool) => document.getElementById(`tool-path-${tool}`) as HTMLInputElement;
+
+    for (const tool of ["ffmpeg", "ffprobe"] as Tool[]) {
+        document.getElementById(`tool-browse-${tool}`)?.addEventListener("click", async () => {
+            const selected = await open({ multiple: false, title: `Velg ${tool}` });
+            if (selected) pathInput(tool).value = Array.isArray(selected) ? selected[0] : selected;
+        });
+    }
+
+    document.getElementById("tool-cancel")?.addEventListener("click", close);
+
+    document.getElementById("tool-save")?.addEventListener("click", async () => {
+        try {
+            const updated = await invoke<ToolStatus>("set_tool_paths", {
+                ffmpegPath: pathInput("ffmpeg").value.trim() || null,
+                ffprobePath: pathInput("ffprobe").value.trim() || null,
+            });
+            document.getElementById("tool-status-ffmpeg")!.innerHTML = describeTool(updated.ffmpeg);
+            document.getElementById("tool-status-ffprobe")!.innerHTML = describeTool(updated.ffprobe);
+
+            if (updated.ffmpegAvailable && updated.ffprobeAvailable) {
+                toast.show("ffmpeg og ffprobe er klare", "success");
+            } else {
+                toast.show("Fant ikke alle verktøyene - sjekk stiene", "warning");
+            }
+        } catch (error) {
+            toast.show(`Kunne ikke lagre stiene: ${error}`, "error");
+        }
+    });
+}
@@ -5,32 +5,196 @@ export interface ImageInfo {
     filename: string;
     extension: string;
     sizeBytes: number;
+    /** Andre stier til samme fil (hardlenker) */
+    aliases: string[];
+    /** Format funnet i filinnholdet (kun med sniffContent) */
+    detectedFormat: string | null;
+    extensionMismatch: boolean;
+    kind: MediaKind;
+    /** Sekunder siden epoch */
+    modified: number | null;
+    created: number | null;
+    width: number | null;
+    height: number | null;
+    hasSidecar: boolean;
 }
 
+export type MediaKind = "image" | "animatedImage" | "raw" | "video" | "livePhotoVideo";
+
 export interface ScanResult {
     imageCount: number;
     totalSizeBytes: number;
     images: ImageInfo[];
+    hardlinkGroups: string[][];
+    symlinkLoops: string[];
+}
+
+export type VerificationLevel = "partial" | "fullHash" | "byteCompare";
+
+export type MatchKind = "exact" | "visual" | "mixed" | "hardlink";
+
+export interface ScanOptions {
+    exclude?: string[];
+    include?: string[];
+    maxDepth?: number;
+    minSizeBytes?: number;
+    maxSizeBytes?: number;
+    includeHidden?: boolean;
+    followSymlinks?: boolean;
+    useDefaultExcludes?: boolean;
+    sniffContent?: boolean;
+    readHeaders?: boolean;
 }
 
+export interface ScanBatch {
+    jobId: string;
+    images: ImageInfo[];
+}
+
+export type Transform =
+    | "identity"
+    | "rotate90"
+    | "rotate180"
+    | "rotate270"
+    | "flipHorizontal"
+    | "flipVertical"
+    | "transpose"
+    | "transverse";
+
 export interface DuplicateGroup {
     images: ImageInfo[];
+    distances: number[];
+    /** Rotasjon/speiling som gjør hvert bilde likt referansebildet (første bilde) */
+    transforms: Transform[];
+    kind: MatchKind;
+    exactSubgroups: string[][];
+    verification: VerificationLevel | null;
 }
 
 export interface DuplicateResult {
     groups: DuplicateGroup[];
+    hardlinkGroups: DuplicateGroup[];
     totalDuplicates: number;
     processed: number;
     errors: number;
 }
 
+export type HashType = "perceptual" | "difference" | "average";
+
+export type ClusterMode = "leader" | "transitive" | "completeLink";
+
+export interface DuplicateConfig {
+    hashType?: HashType;
+    hashSize?: 8 | 16 | 32;
+    verification?: VerificationLevel;
+    clusterMode?: ClusterMode;
+    /** Finn også roterte og speilvendte kopier */
+    transformInvariant?: boolean;
+}
+
+export interface GpsLocation {
+    latitude: number;
+    longitude: number;
+    altitude: number | null;
+}
+
+export interface VideoMetadata {
+    durationMs: number | null;
+    /** Lagret oppløsning, før rotasjon */
+    width: number | null;
+    height: number | null;
+    codec: string | null;
+    frameRate: number | null;
+    /** Grader med klokka ved avspilling */
+    rotation: number | null;
+    location: GpsLocation | null;
+    make: string | null;
+    model: string | null;
+    /** ISO 8601 med tidssonen fra opptaket */
+    creationDate: string | null;
+    /** ISO 8601 i UTC */
+    creationTime: string | null;
+}
+
+export interface MediaMetadata {
+    /** Lokal tid uten tidssone, f.eks. "2023-06-14T18:22:31" */
+    taken: string | null;
+    /** Bare for videoer. MP4/MOV leses uten ffprobe; AVI, MKV og WMV krever ffprobe */
+    video: VideoMetadata | null;
+}
+
 export interface OperationResult {
     processed: number;
     success: number;
     errors: number;
     errorMessages: string[];
+    cancelled: boolean;
+    /** RAW+JPEG-partnere til slettede filer som ikke var valgt og derfor ble latt være */
+    remainingPartners: string[];
 }
 export interface SortConfig {
     useDayFolder: boolean;
     useMonthNames: boolean;
 }
+
+export type KeepRule =
+    | { rule: "highestResolution" }
+    | { rule: "largestFile" }
+    | { rule: "hasExifDate" }
+    | { rule: "hasSidecars" }
+    | { rule: "preferredFolder"; prefix: string }
+    | { rule: "oldestDate" }
+    | { rule: "noCollisionSuffix" };
+
+/** Ett element per gruppe fra `select_keepers`, i samme rekkefølge (null for tomme grupper) */
+export interface KeepDecision {
+    keep: string;
+    remove: string[];
+    reasons: string[];
+}
+
+export type ProgressPhase =
+    | "scan"
+    | "exactStage"
+    | "verify"
+    | "visualStage"
+    | "clustering"
+    | "sort"
+    | "delete"
+    | "rename";
+
+export interface ProgressEvent {
+    jobId: string;
+    phase: ProgressPhase;
+    done: number;
+    total: number | null;
+    bytesProcessed: number;
+    errors: number;
+    currentFile: string | null;
+    etaSeconds: number | null;
+    finished: boolean;
+}
+
+export type Tool = "ffmpeg" | "ffprobe";
+
+export type ToolSource = "sidecar" | "environment" | "setting" | "path";
+
+export interface ResolvedTool {
+    path: string;
+    source: ToolSource;
+    version: string | null;
+}
+
+export interface ToolStatus {
+    ffmpeg: ResolvedTool | null;
+    ffprobe: ResolvedTool | null;
+    ffmpegAvailable: boolean;
+    ffprobeAvailable: boolean;
+}
+
+export interface ToolSettings {
+    ffmpegPath?: string | null;
+    ffprobePath?: string | null;
+    timeoutSeconds?: number | null;
+    maxProcesses?: number | null;
+}
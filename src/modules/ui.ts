@@ -6,6 +6,7 @@ export const elements = {
     get statusText() { return document.getElementById("status-text") },
     get app() { return document.getElementById("app") },
     get pathDisplay() { return document.getElementById("folder-path-display") },
+    get toolSettingsBtn() { return document.getElementById("tool-settings-btn") },
 };
 
 export function updateStatus(message: string) {
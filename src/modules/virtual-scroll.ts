@@ -17,32 +17,6 @@ export function setupVirtualScroll(
     contentRef = content;
     renderCallback = renderer;
 
-    const calculateMetrics = () => {
-        if (!containerRef) return;
-        const containerWidth = containerRef.clientWidth;
-        // Min width 160px + gap 16px
-        const minColWidth = 160 + 16;
-        state.virtualState.cols = Math.max(1, Math.floor((containerWidth - 32) / minColWidth));
-        state.virtualState.totalRows = Math.ceil(state.currentImages.length / state.virtualState.cols);
-
-        // Sync CSS grid columns
-        contentRef?.style.setProperty('--grid-cols', String(state.virtualState.cols));
-
-        const gap = 16;
-        const padding = 16;
-        const availableWidth = containerWidth - padding;
-        const colWidth = (availableWidth - (state.virtualState.cols - 1) * gap) / state.virtualState.cols;
-
-        // Height = colWidth (aspect-1) + info (50) + gap
-        state.virtualState.rowHeight = colWidth + 50 + gap;
-
-        // Update spacer height
-        const totalHeight = state.virtualState.totalRows * state.virtualState.rowHeight;
-        if (spacerRef) spacerRef.style.height = `${totalHeight}px`;
-
-        state.virtualState.containerHeight = containerRef.clientHeight;
-    };
-
     // Resize Observer
     const resizeObserver = new ResizeObserver(() => {
         calculateMetrics();
@@ -63,6 +37,40 @@ export function setupVirtualScroll(
     renderVirtualItems();
 }
 
+function calculateMetrics() {
+    if (!containerRef) return;
+    const containerWidth = containerRef.clientWidth;
+    // Min width 160px + gap 16px
+    const minColWidth = 160 + 16;
+    state.virtualState.cols = Math.max(1, Math.floor((containerWidth - 32) / minColWidth));
+    state.virtualState.totalRows = Math.ceil(state.currentImages.length / state.virtualState.cols);
+
+    // Sync CSS grid columns
+    contentRef?.style.setProperty('--grid-cols', String(state.virtualState.cols));
+
+    const gap = 16;
+    const padding = 16;
+    const availableWidth = containerWidth - padding;
+    const colWidth = (availableWidth - (state.virtualState.cols - 1) * gap) / state.virtualState.cols;
+
+    // Height = colWidth (aspect-1) + info (50) + gap
+    state.virtualState.rowHeight = colWidth + 50 + gap;
+
+    // Update spacer height
+    const totalHeight = state.virtualState.totalRows * state.virtualState.rowHeight;
+    if (spacerRef) spacerRef.style.height = `${totalHeight}px`;
+
+    state.virtualState.containerHeight = containerRef.clientHeight;
+}
+
+/** Regner ut høyden på nytt og tegner på nytt, f.eks. når en skanning legger til bilder */
+export function refreshVirtualItems() {
+    calculateMetrics();
+    state.virtualState.startIndex = 0;
+    state.virtualState.endIndex = 0;
+    renderVirtualItems();
+}
+
 export function renderVirtualItems() {
     if (!contentRef || !state.currentImages.length || !renderCallback) return;
 